@@ -0,0 +1,103 @@
+use std::future::Future;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{web, Error, HttpMessage, HttpRequest};
+use chrono::Utc;
+use recesser_core::audit::{AuditRecord, Outcome};
+
+use crate::auth::Token;
+use crate::AppState;
+
+/// Target of a mutating request that cannot be derived from its path
+struct AuditTarget(String);
+
+/// Record the target of the request in the audit log
+pub fn set_target(req: &HttpRequest, target: impl Into<String>) {
+    req.extensions_mut().insert(AuditTarget(target.into()));
+}
+
+/// Record every mutating request in the audit log, including requests that were rejected before
+/// they reached a handler.
+///
+/// Routes should be given a name which is used as the action of the record.
+pub fn record<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+{
+    let pending = PendingRecord::new(&req);
+    let fut = srv.call(req);
+    async move {
+        let result = fut.await;
+        if let Some(pending) = pending {
+            let record = match &result {
+                Ok(res) => pending.to_record(Some(res.request()), res.status()),
+                // Only requests that failed authentication end up here
+                Err(e) => pending.to_record(None, e.as_response_error().status_code()),
+            };
+            if let Err(e) = pending.app_state.database.audit.append(record).await {
+                tracing::error!(error = ?e, "Failed to write audit record");
+            }
+        }
+        result
+    }
+}
+
+struct PendingRecord {
+    app_state: web::Data<AppState>,
+    method_and_path: String,
+    source_ip: Option<String>,
+}
+
+impl PendingRecord {
+    fn new(req: &ServiceRequest) -> Option<Self> {
+        if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+            return None;
+        }
+        Some(Self {
+            app_state: req.app_data::<web::Data<AppState>>()?.clone(),
+            method_and_path: format!("{} {}", req.method(), req.path()),
+            source_ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+        })
+    }
+
+    fn to_record(&self, req: Option<&HttpRequest>, status: StatusCode) -> AuditRecord {
+        let ext = req.map(HttpRequest::extensions);
+        let token = ext.as_ref().and_then(|ext| ext.get::<Token>());
+
+        let action = match req.and_then(HttpRequest::match_name) {
+            Some(name) => String::from(name),
+            None => self.method_and_path.clone(),
+        };
+        let target = match ext.as_ref().and_then(|ext| ext.get::<AuditTarget>()) {
+            Some(target) => Some(target.0.clone()),
+            None => req.and_then(target_from_path),
+        };
+        let outcome = match status.is_success() {
+            true => Outcome::Success,
+            false => Outcome::Failure,
+        };
+
+        AuditRecord {
+            sequence: 0,
+            timestamp: Utc::now(),
+            actor: token.map(|t| String::from(t.user_id())),
+            scope: token.map(|t| t.extract_user().scope),
+            action,
+            target,
+            outcome,
+            status: status.as_u16(),
+            source_ip: self.source_ip.clone(),
+            previous_hash: String::new(),
+            hash: String::new(),
+        }
+    }
+}
+
+fn target_from_path(req: &HttpRequest) -> Option<String> {
+    let params: Vec<&str> = req.match_info().iter().map(|(_, v)| v).collect();
+    (!params.is_empty()).then(|| params.join("/"))
+}
@@ -227,6 +227,9 @@ pub mod middleware {
     use actix_web_httpauth::extractors::bearer::BearerAuth;
 
     use crate::error::UserError;
+    use crate::logging;
+    use crate::metrics::AUTH_FAILURES;
+    use crate::tls::ClientCertificate;
     use crate::AppState;
 
     use super::{Scope, Token};
@@ -234,7 +237,13 @@ pub mod middleware {
     pub fn validate_scope(req: &impl HttpMessage, scope: Scope) -> Result<(), UserError> {
         let ext = req.extensions();
         let token = ext.get::<Token>().ok_or(UserError::Internal)?;
-        token.validate_scope(scope)
+        let result = token.validate_scope(scope);
+        if result.is_err() {
+            AUTH_FAILURES
+                .with_label_values(&["insufficient_scope"])
+                .inc();
+        }
+        result
     }
 
     pub async fn validator(
@@ -243,10 +252,27 @@ pub mod middleware {
     ) -> Result<ServiceRequest, Error> {
         let app_state = extract_app_state(&req)?;
         let token = validate_token(credentials, app_state)?;
+        if app_state.client_certificates_required {
+            validate_client_certificate(&req, &token)?;
+        }
+        logging::record_user_id(&req, &token);
         req.extensions_mut().insert(token);
         Ok(req)
     }
 
+    /// Machine tokens are used in the cluster, e.g. by the steps of workflows, which can't present
+    /// client certificates. Everyone else needs to when mutual TLS is enabled.
+    fn validate_client_certificate(req: &ServiceRequest, token: &Token) -> Result<(), UserError> {
+        let presented = req.conn_data::<ClientCertificate>().is_some();
+        if presented || matches!(token.claims.scope, Scope::Machine) {
+            return Ok(());
+        }
+        AUTH_FAILURES
+            .with_label_values(&["missing_client_certificate"])
+            .inc();
+        Err(UserError::unauthorized("Client certificate is required"))
+    }
+
     fn extract_app_state(req: &ServiceRequest) -> Result<&web::Data<AppState>, UserError> {
         req.app_data::<web::Data<AppState>>()
             .ok_or(UserError::Internal)
@@ -258,6 +284,9 @@ pub mod middleware {
     ) -> Result<Token, UserError> {
         let token_str = credentials.token();
         let hmac_key = app_state.hmac_key.lock().unwrap();
-        Token::validate(token_str, &hmac_key).map_err(UserError::unauthorized)
+        Token::validate(token_str, &hmac_key).map_err(|e| {
+            AUTH_FAILURES.with_label_values(&["invalid_token"]).inc();
+            UserError::unauthorized(e)
+        })
     }
 }
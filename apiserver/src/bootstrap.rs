@@ -0,0 +1,75 @@
+use anyhow::Result;
+use recesser_core::user::Scope;
+use ring::rand::SystemRandom;
+
+use crate::auth::{HmacKey, Token};
+use crate::database::Database;
+use crate::kubernetes::KubernetesApiserver;
+use crate::secretstorage::SecretStorage;
+
+/// Name of the Kubernetes secret that holds the Machine token of the schandler
+const MACHINE_TOKEN_SECRET: &str = "apiserver-token";
+
+/// Retrieve the HMAC key from secret storage or generate it on first start.
+///
+/// Generating the key does not create an admin. The first admin is created explicitly through the
+/// init endpoint so that no token ever has to be written to the logs.
+pub async fn initialize_hmac_key(
+    rng: &SystemRandom,
+    secstore: &SecretStorage,
+    database: &Database,
+    k8s_apiserver: &KubernetesApiserver,
+) -> Result<HmacKey> {
+    let hmac_key = match secstore.get_hmac_key().await {
+        Ok(key_value) => {
+            // Systems set up before the init endpoint existed already have an admin
+            database.system.mark_legacy_initialized().await?;
+            HmacKey::new(&key_value)
+        }
+        Err(_) => generate_hmac_key(rng, secstore, database, k8s_apiserver).await?,
+    };
+
+    if !database.system.is_initialized().await? {
+        tracing::warn!("System is not initialized. Create the first admin via `rcssr admin init`");
+    }
+
+    Ok(hmac_key)
+}
+
+async fn generate_hmac_key(
+    rng: &SystemRandom,
+    secstore: &SecretStorage,
+    database: &Database,
+    k8s_apiserver: &KubernetesApiserver,
+) -> Result<HmacKey> {
+    // Record the uninitialized state before storing the key so that a crash in between is not
+    // mistaken for a legacy system on the next start
+    database.system.mark_uninitialized().await?;
+
+    let key_value = HmacKey::generate_key_value(rng)?;
+    let hmac_key = HmacKey::new(&key_value);
+    secstore.store_hmac_key(&key_value).await?;
+    tracing::info!("Generated new HMAC key");
+
+    issue_machine_token(&hmac_key, database, k8s_apiserver).await?;
+    Ok(hmac_key)
+}
+
+/// Issue a Machine token for the schandler and store it as a Kubernetes secret so that the
+/// schandler and the workflows it submits can access the apiserver.
+pub async fn issue_machine_token(
+    hmac_key: &HmacKey,
+    database: &Database,
+    k8s_apiserver: &KubernetesApiserver,
+) -> Result<()> {
+    let token = Token::create(Scope::Machine, hmac_key)?;
+    database.user.create(&token.extract_user()).await?;
+    k8s_apiserver
+        .apply_token_secret(MACHINE_TOKEN_SECRET, &token)
+        .await?;
+    tracing::info!(
+        user_id = token.user_id(),
+        "Issued Machine token for schandler"
+    );
+    Ok(())
+}
@@ -1,20 +1,38 @@
+mod audit;
+mod event;
 mod metadata;
+mod notification;
 mod repository;
+mod run;
+mod system;
 mod user;
 
 use anyhow::{Error, Result};
 use thiserror::Error;
 
 use crate::error::UserError;
+use audit::AuditStore;
 use metadata::MetadataStore;
 use repository::RepositoryStore;
+use system::SystemStore;
 use user::UserStore;
 
+pub use event::EventStore;
+pub use metadata::{Attribution, UsageField};
+pub use notification::NotificationStore;
+pub use run::RunStore;
+
 #[derive(Clone)]
 pub struct Database {
+    db: mongodb::Database,
     pub repositories: RepositoryStore,
     pub metadata: MetadataStore,
     pub user: UserStore,
+    pub system: SystemStore,
+    pub audit: AuditStore,
+    pub events: EventStore,
+    pub notifications: NotificationStore,
+    pub runs: RunStore,
 }
 
 impl Database {
@@ -22,12 +40,39 @@ impl Database {
         let client = mongodb::Client::with_uri_str(addr).await?;
         tracing::info!(addr, "Connected to database");
         let db = client.database("recesser");
+        let audit = AuditStore::new(db.collection("audit"));
+        audit.create_index().await?;
+        let events = EventStore::new(db.collection("events"));
+        events.create_index().await?;
+        let runs = RunStore::new(db.collection("runs"));
+        runs.create_index().await?;
+        let repositories = RepositoryStore::new(db.collection("repositories"));
+        // Fails if the collection already contains duplicates from before the index existed
+        if let Err(e) = repositories.create_index().await {
+            tracing::warn!(error = %e, "Failed to create unique index on repository names");
+        }
         Ok(Self {
-            repositories: RepositoryStore::new(db.collection("repositories")),
+            db: db.clone(),
+            repositories,
             metadata: MetadataStore::new(db.collection("metadata")),
             user: UserStore::new(db.collection("user")),
+            system: SystemStore::new(db.collection("system")),
+            audit,
+            events,
+            notifications: NotificationStore::new(
+                db.collection("channels"),
+                db.collection("deliveries"),
+            ),
+            runs,
         })
     }
+
+    pub async fn ping(&self) -> Result<()> {
+        self.db
+            .run_command(mongodb::bson::doc! {"ping": 1}, None)
+            .await?;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Error)]
@@ -50,3 +95,24 @@ impl DocumentNotFoundError {
         }
     }
 }
+
+#[derive(Debug, Error)]
+#[error("{message}")]
+pub struct AlreadyExistsError {
+    pub message: String,
+}
+
+impl AlreadyExistsError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: String::from(message),
+        }
+    }
+
+    pub fn downcast(e: Error, subject: &str) -> UserError {
+        match e.downcast::<Self>() {
+            Ok(e) => UserError::already_exists(subject, e),
+            Err(e) => UserError::internal(e),
+        }
+    }
+}
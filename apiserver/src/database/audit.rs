@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use mongodb::bson;
+use mongodb::options::{FindOneOptions, FindOptions, IndexOptions};
+use mongodb::IndexModel;
+use recesser_core::audit::{format_timestamp, AuditQuery, AuditRecord};
+use tokio::sync::Mutex;
+
+use super::repository::is_duplicate_key;
+
+/// Attempts to append a record when other apiserver replicas keep taking the next position
+const MAX_APPEND_ATTEMPTS: u32 = 10;
+
+#[derive(Clone)]
+pub struct AuditStore {
+    collection: mongodb::Collection<AuditRecord>,
+    // Appends of this replica are serialized because every record references its predecessor.
+    // Other replicas are detected by the unique sequence index.
+    append_lock: Arc<Mutex<()>>,
+}
+
+impl AuditStore {
+    pub fn new(collection: mongodb::Collection<AuditRecord>) -> Self {
+        Self {
+            collection,
+            append_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Ensure that no two records can occupy the same position in the chain
+    pub async fn create_index(&self) -> Result<()> {
+        let options = IndexOptions::builder().unique(true).build();
+        let index = IndexModel::builder()
+            .keys(bson::doc! {"sequence": 1})
+            .options(options)
+            .build();
+        self.collection.create_index(index, None).await?;
+        Ok(())
+    }
+
+    /// Append record to the end of the chain. Sequence number, timestamp and hashes of the
+    /// provided record are overwritten. If another replica appended in the meantime, the record
+    /// is linked to the new end of the chain and appended again.
+    pub async fn append(&self, mut record: AuditRecord) -> Result<()> {
+        let _guard = self.append_lock.lock().await;
+
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let options = FindOneOptions::builder()
+                .sort(bson::doc! {"sequence": -1})
+                .build();
+            let last = self.collection.find_one(None, options).await?;
+
+            record.sequence = last.as_ref().map(|r| r.sequence + 1).unwrap_or(0);
+            record.previous_hash = last.map(|r| r.hash).unwrap_or_default();
+            record.timestamp = Utc::now();
+            record.hash = record.compute_hash()?;
+
+            match self.collection.insert_one(&record, None).await {
+                Ok(_) => return Ok(()),
+                Err(e) if is_duplicate_key(&e) && attempts < MAX_APPEND_ATTEMPTS => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Search records matching the query ordered from oldest to newest. If a limit is set, only
+    /// the newest matching records are returned.
+    pub async fn search(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>> {
+        let mut filter = bson::Document::new();
+        if let Some(actor) = &query.actor {
+            filter.insert("actor", actor);
+        }
+        if let Some(action) = &query.action {
+            filter.insert("action", action);
+        }
+        if let Some(target) = &query.target {
+            filter.insert("target", target);
+        }
+        let mut timestamp = bson::Document::new();
+        if let Some(since) = &query.since {
+            timestamp.insert("$gte", format_timestamp(since));
+        }
+        if let Some(until) = &query.until {
+            timestamp.insert("$lte", format_timestamp(until));
+        }
+        if !timestamp.is_empty() {
+            filter.insert("timestamp", timestamp);
+        }
+
+        let options = FindOptions::builder()
+            .sort(bson::doc! {"sequence": -1})
+            .limit(query.limit)
+            .build();
+        let cursor = self.collection.find(filter, options).await?;
+        let mut records: Vec<AuditRecord> = cursor.try_collect().await?;
+        records.reverse();
+        Ok(records)
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use mongodb::bson;
+use mongodb::options::{FindOneOptions, FindOptions, IndexOptions};
+use mongodb::IndexModel;
+use recesser_core::event::{Event, EventKind};
+use tokio::sync::Mutex;
+
+#[derive(Clone)]
+pub struct EventStore {
+    collection: mongodb::Collection<Event>,
+    // Appends are serialized so that IDs are assigned without gaps
+    append_lock: Arc<Mutex<()>>,
+}
+
+impl EventStore {
+    pub fn new(collection: mongodb::Collection<Event>) -> Self {
+        Self {
+            collection,
+            append_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub async fn create_index(&self) -> Result<()> {
+        let options = IndexOptions::builder().unique(true).build();
+        let index = IndexModel::builder()
+            .keys(bson::doc! {"id": 1})
+            .options(options)
+            .build();
+        self.collection.create_index(index, None).await?;
+        Ok(())
+    }
+
+    /// Persist event with the next ID
+    pub async fn append(&self, project: Option<String>, kind: EventKind) -> Result<Event> {
+        let _guard = self.append_lock.lock().await;
+
+        let event = Event {
+            id: self.latest_id().await?.map(|id| id + 1).unwrap_or(0),
+            timestamp: Utc::now(),
+            project,
+            kind,
+        };
+        self.collection.insert_one(&event, None).await?;
+        Ok(event)
+    }
+
+    /// ID of the newest event, if any
+    pub async fn latest_id(&self) -> Result<Option<u64>> {
+        let options = FindOneOptions::builder()
+            .sort(bson::doc! {"id": -1})
+            .build();
+        let last = self.collection.find_one(None, options).await?;
+        Ok(last.map(|e| e.id))
+    }
+
+    /// All events after the given ID, or all events if there is none, ordered from oldest to
+    /// newest
+    pub async fn after(&self, id: Option<u64>) -> Result<Vec<Event>> {
+        let options = FindOptions::builder().sort(bson::doc! {"id": 1}).build();
+        let filter = id.map(|id| bson::doc! {"id": {"$gt": id as i64}});
+        let cursor = self.collection.find(filter, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+}
@@ -2,6 +2,7 @@ use anyhow::Result;
 use futures_util::TryStreamExt;
 use mongodb::bson;
 use recesser_core::metadata::Metadata;
+use recesser_core::usage::Usage;
 use serde::{Deserialize, Serialize};
 
 use super::DocumentNotFoundError;
@@ -15,6 +16,31 @@ pub struct MetadataStore {
 pub struct MetadataDoc {
     handle: String,
     metadata: Metadata,
+    // Artifacts uploaded before usage was tracked have no size and no owner
+    size: Option<u64>,
+    owner: Option<String>,
+    project: Option<String>,
+}
+
+/// Storage attribution of a newly uploaded artifact
+pub struct Attribution<'a> {
+    pub size: u64,
+    pub owner: &'a str,
+    pub project: Option<&'a str>,
+}
+
+#[derive(Clone, Copy)]
+pub enum UsageField {
+    Owner,
+    Project,
+}
+
+#[derive(Deserialize)]
+struct UsageGroup {
+    #[serde(rename = "_id")]
+    name: Option<String>,
+    bytes: u64,
+    objects: u64,
 }
 
 impl MetadataStore {
@@ -22,10 +48,18 @@ impl MetadataStore {
         Self { collection }
     }
 
-    pub async fn insert(&self, handle: &str, metadata: &Metadata) -> Result<()> {
+    pub async fn insert(
+        &self,
+        handle: &str,
+        metadata: &Metadata,
+        attribution: Attribution<'_>,
+    ) -> Result<()> {
         let metadata_doc = MetadataDoc {
             handle: String::from(handle),
             metadata: metadata.clone(),
+            size: Some(attribution.size),
+            owner: Some(String::from(attribution.owner)),
+            project: attribution.project.map(String::from),
         };
         self.collection.insert_one(&metadata_doc, None).await?;
         Ok(())
@@ -47,11 +81,13 @@ impl MetadataStore {
         Ok(handles)
     }
 
-    pub async fn delete(&self, handle: &str) -> Result<()> {
-        self.collection
+    /// Delete artifact and return the project it was accounted to
+    pub async fn delete(&self, handle: &str) -> Result<Option<String>> {
+        let metadata_doc = self
+            .collection
             .find_one_and_delete(filter_handle(handle), None)
             .await?;
-        Ok(())
+        Ok(metadata_doc.and_then(|d| d.project))
     }
 
     pub async fn search_object_handle(&self, handle: &str) -> Result<Vec<String>> {
@@ -68,6 +104,98 @@ impl MetadataStore {
 
         Ok(handles)
     }
+
+    /// Size of an object as recorded by any artifact that references it
+    pub async fn object_size(&self, object_handle: &str) -> Result<Option<u64>> {
+        let filter = bson::doc! {"metadata.object_handle": object_handle, "size": {"$ne": null}};
+        let metadata_doc = self.collection.find_one(filter, None).await?;
+        Ok(metadata_doc.and_then(|d| d.size))
+    }
+
+    /// Whether an artifact of the given owner or project already references the object
+    pub async fn references_object(
+        &self,
+        field: UsageField,
+        name: &str,
+        object_handle: &str,
+    ) -> Result<bool> {
+        let filter = bson::doc! {field.key(): name, "metadata.object_handle": object_handle};
+        Ok(self.collection.find_one(filter, None).await?.is_some())
+    }
+
+    /// Storage used by each owner or project
+    pub async fn usage_by(&self, field: UsageField) -> Result<Vec<Usage>> {
+        self.aggregate_usage(bson::doc! {}, field.group_key()).await
+    }
+
+    /// Storage used by a single owner or project
+    pub async fn usage_of(&self, field: UsageField, name: &str) -> Result<u64> {
+        let mut usage = self
+            .aggregate_usage(bson::doc! {field.key(): name}, field.group_key())
+            .await?;
+        Ok(usage.pop().map(|u| u.bytes).unwrap_or(0))
+    }
+
+    /// Storage used by the whole system
+    pub async fn total_usage(&self) -> Result<Usage> {
+        let mut usage = self
+            .aggregate_usage(bson::doc! {}, bson::Bson::Null)
+            .await?;
+        let (bytes, objects) = usage.pop().map(|u| (u.bytes, u.objects)).unwrap_or((0, 0));
+        Ok(Usage {
+            name: String::from("total"),
+            bytes,
+            objects,
+            quota: None,
+        })
+    }
+
+    async fn aggregate_usage(
+        &self,
+        filter: bson::Document,
+        group_key: bson::Bson,
+    ) -> Result<Vec<Usage>> {
+        let pipeline = vec![
+            bson::doc! {"$match": filter},
+            // Count every object only once per group
+            bson::doc! {"$group": {
+                "_id": {"name": group_key, "object": "$metadata.object_handle"},
+                "size": {"$max": {"$ifNull": ["$size", 0]}},
+            }},
+            bson::doc! {"$group": {
+                "_id": "$_id.name",
+                "bytes": {"$sum": "$size"},
+                "objects": {"$sum": 1},
+            }},
+            bson::doc! {"$sort": {"_id": 1}},
+        ];
+        let cursor = self.collection.aggregate(pipeline, None).await?;
+        let docs: Vec<bson::Document> = cursor.try_collect().await?;
+        docs.into_iter()
+            .map(|doc| {
+                let group: UsageGroup = bson::from_document(doc)?;
+                Ok(Usage {
+                    name: group.name.unwrap_or_else(|| String::from("unknown")),
+                    bytes: group.bytes,
+                    objects: group.objects,
+                    quota: None,
+                })
+            })
+            .collect()
+    }
+}
+
+impl UsageField {
+    fn key(&self) -> &'static str {
+        match self {
+            UsageField::Owner => "owner",
+            UsageField::Project => "project",
+        }
+    }
+
+    fn group_key(&self) -> bson::Bson {
+        bson::Bson::String(format!("${}", self.key()))
+    }
 }
 
 fn filter_handle(handle: &str) -> bson::Document {
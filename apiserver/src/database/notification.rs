@@ -0,0 +1,87 @@
+use anyhow::Result;
+use futures_util::TryStreamExt;
+use mongodb::bson;
+use mongodb::options::FindOptions;
+use recesser_core::notification::{Channel, Delivery};
+
+use crate::database::DocumentNotFoundError;
+
+#[derive(Clone)]
+pub struct NotificationStore {
+    channels: mongodb::Collection<Channel>,
+    deliveries: mongodb::Collection<Delivery>,
+}
+
+impl NotificationStore {
+    pub fn new(
+        channels: mongodb::Collection<Channel>,
+        deliveries: mongodb::Collection<Delivery>,
+    ) -> Self {
+        Self {
+            channels,
+            deliveries,
+        }
+    }
+
+    pub async fn add_channel(&self, channel: &Channel) -> Result<()> {
+        self.channels.insert_one(channel, None).await?;
+        tracing::info!(id = %channel.id, repository = %channel.repository, "Stored new notification channel");
+        Ok(())
+    }
+
+    pub async fn channels(&self, repository: &str) -> Result<Vec<Channel>> {
+        let cursor = self
+            .channels
+            .find(bson::doc! {"repository": repository}, None)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Remove a channel and return it
+    pub async fn remove_channel(&self, repository: &str, id: &str) -> Result<Channel> {
+        let channel = self
+            .channels
+            .find_one_and_delete(bson::doc! {"repository": repository, "id": id}, None)
+            .await?
+            .ok_or_else(|| {
+                DocumentNotFoundError::new(&format!("Notification channel doesn't exist: {id}"))
+            })?;
+        Ok(channel)
+    }
+
+    /// Remove all channels of a repository and return their IDs
+    pub async fn remove_channels(&self, repository: &str) -> Result<Vec<String>> {
+        let channels = self.channels(repository).await?;
+        self.channels
+            .delete_many(bson::doc! {"repository": repository}, None)
+            .await?;
+        Ok(channels.into_iter().map(|c| c.id).collect())
+    }
+
+    pub async fn remove_deliveries(&self, repository: &str) -> Result<()> {
+        self.deliveries
+            .delete_many(bson::doc! {"repository": repository}, None)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn record_delivery(&self, delivery: &Delivery) -> Result<()> {
+        self.deliveries.insert_one(delivery, None).await?;
+        Ok(())
+    }
+
+    /// Newest deliveries to the channels of a repository ordered from oldest to newest
+    pub async fn deliveries(&self, repository: &str, limit: Option<i64>) -> Result<Vec<Delivery>> {
+        let options = FindOptions::builder()
+            .sort(bson::doc! {"timestamp": -1})
+            .limit(limit)
+            .build();
+        let cursor = self
+            .deliveries
+            .find(bson::doc! {"repository": repository}, options)
+            .await?;
+        let mut deliveries: Vec<Delivery> = cursor.try_collect().await?;
+        deliveries.reverse();
+        Ok(deliveries)
+    }
+}
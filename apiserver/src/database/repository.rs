@@ -1,9 +1,15 @@
 use anyhow::Result;
 use futures_util::TryStreamExt;
 use mongodb::bson;
-use recesser_core::repository::Repository;
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::IndexOptions;
+use mongodb::IndexModel;
+use recesser_core::repository::{PublicKey, Repository, Triggers};
 
-use crate::database::DocumentNotFoundError;
+use crate::database::{AlreadyExistsError, DocumentNotFoundError};
+
+/// Code of MongoDB write errors caused by a violated unique index
+const DUPLICATE_KEY: i32 = 11000;
 
 #[derive(Clone)]
 pub struct RepositoryStore {
@@ -15,11 +21,43 @@ impl RepositoryStore {
         Self { collection }
     }
 
+    pub async fn create_index(&self) -> Result<()> {
+        let options = IndexOptions::builder().unique(true).build();
+        let index = IndexModel::builder()
+            .keys(bson::doc! {"name": 1})
+            .options(options)
+            .build();
+        self.collection.create_index(index, None).await?;
+        Ok(())
+    }
+
+    /// Store repository. Adding the same repository with the same URL and credentials again
+    /// succeeds without changes, so that failed registrations can be retried.
     pub async fn add(&self, repository: Repository) -> Result<()> {
         let name = repository.name.clone();
-        self.collection.insert_one(repository, None).await?;
-        tracing::info!(%name, "Stored new repository in database");
-        Ok(())
+        match self.collection.insert_one(&repository, None).await {
+            Ok(_) => {
+                tracing::info!(%name, "Stored new repository in database");
+                Ok(())
+            }
+            Err(e) if is_duplicate_key(&e) => match self.find(&name).await? {
+                Some(existing) if existing.same_registration(&repository) => {
+                    tracing::debug!(%name, "Repository is already stored in database");
+                    Ok(())
+                }
+                _ => Err(
+                    AlreadyExistsError::new(&format!("Repository already exists: {name}")).into(),
+                ),
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn find(&self, name: &str) -> Result<Option<Repository>> {
+        Ok(self
+            .collection
+            .find_one(bson::doc! {"name": name}, None)
+            .await?)
     }
 
     pub async fn update_last_commit(&self, name: &str, new_commit: &str) -> Result<()> {
@@ -39,6 +77,154 @@ impl RepositoryStore {
         Ok(())
     }
 
+    /// Update the last processed commit of a watched branch or tag
+    pub async fn update_tracked_ref(
+        &self,
+        name: &str,
+        reference: &str,
+        new_commit: &str,
+    ) -> Result<()> {
+        tracing::debug!(
+            message = "Updating last commit of tracked ref",
+            repository = name,
+            reference,
+            new_commit
+        );
+        let result = self
+            .collection
+            .update_one(
+                bson::doc! {"name": name, "refs.name": reference},
+                bson::doc! {"$set": {"refs.$.commit": new_commit}},
+                None,
+            )
+            .await?;
+        if result.matched_count == 0 {
+            self.collection
+                .update_one(
+                    bson::doc! {"name": name},
+                    bson::doc! {"$push": {"refs": {"name": reference, "commit": new_commit}}},
+                    None,
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Replace the trigger rules. Refs that are no longer watched are forgotten, so that they
+    /// start from scratch when they are watched again.
+    pub async fn set_triggers(&self, name: &str, triggers: &Triggers) -> Result<()> {
+        let watched: Vec<String> = triggers
+            .branches
+            .iter()
+            .map(|branch| format!("refs/heads/{branch}"))
+            .collect();
+        let update = bson::doc! {
+            "$set": {"triggers": bson::to_bson(triggers)?},
+            "$pull": {"refs": {"name": {"$regex": "^refs/heads/", "$nin": watched}}},
+        };
+        self.collection
+            .find_one_and_update(bson::doc! {"name": name}, update, None)
+            .await?
+            .ok_or_else(|| {
+                DocumentNotFoundError::new(&format!("Repository doesn't exist: {name}"))
+            })?;
+        Ok(())
+    }
+
+    /// Record whether the Git host reports pushes via webhook
+    pub async fn set_webhook(&self, name: &str, webhook: bool) -> Result<()> {
+        self.collection
+            .find_one_and_update(
+                bson::doc! {"name": name},
+                bson::doc! {"$set": {"webhook": webhook}},
+                None,
+            )
+            .await?
+            .ok_or_else(|| {
+                DocumentNotFoundError::new(&format!("Repository doesn't exist: {name}"))
+            })?;
+        Ok(())
+    }
+
+    /// Stage a new deploy key and return the one it replaces, if any
+    pub async fn stage_key(&self, name: &str, key: &PublicKey) -> Result<Option<PublicKey>> {
+        let repository = self
+            .collection
+            .find_one_and_update(
+                bson::doc! {"name": name},
+                bson::doc! {"$set": {"staged_key": bson::to_bson(key)?}},
+                None,
+            )
+            .await?
+            .ok_or_else(|| {
+                DocumentNotFoundError::new(&format!("Repository doesn't exist: {name}"))
+            })?;
+        Ok(repository.staged_key)
+    }
+
+    /// Replace the deploy key with the staged one if it still has the given fingerprint and
+    /// return the previous deploy key. The previous key is kept as retired key.
+    pub async fn activate_staged_key(
+        &self,
+        name: &str,
+        fingerprint: &str,
+    ) -> Result<Option<PublicKey>> {
+        let filter = bson::doc! {"name": name, "staged_key.fingerprint": fingerprint};
+        let repository = self.collection.find_one(filter.clone(), None).await?;
+        let staged_key = repository
+            .as_ref()
+            .and_then(|r| r.staged_key.as_ref())
+            .ok_or_else(|| {
+                DocumentNotFoundError::new(&format!("No key {fingerprint} is staged for {name}"))
+            })?;
+        let mut update = bson::doc! {
+            "$set": {"public_key": bson::to_bson(staged_key)?},
+            "$unset": {"staged_key": ""},
+        };
+        if let Some(public_key) = repository.as_ref().and_then(|r| r.public_key.as_ref()) {
+            update.insert(
+                "$push",
+                bson::doc! {"retired_keys": bson::to_bson(public_key)?},
+            );
+        }
+        let previous = self
+            .collection
+            .find_one_and_update(filter, update, None)
+            .await?
+            .ok_or_else(|| {
+                DocumentNotFoundError::new(&format!("No key {fingerprint} is staged for {name}"))
+            })?;
+        Ok(previous.public_key)
+    }
+
+    /// Forget a retired deploy key after it was revoked
+    pub async fn remove_retired_key(&self, name: &str, fingerprint: &str) -> Result<()> {
+        self.collection
+            .update_one(
+                bson::doc! {"name": name},
+                bson::doc! {"$pull": {"retired_keys": {"fingerprint": fingerprint}}},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Remove the staged deploy key and return it
+    pub async fn discard_staged_key(&self, name: &str) -> Result<PublicKey> {
+        let repository = self
+            .collection
+            .find_one_and_update(
+                bson::doc! {"name": name, "staged_key": {"$exists": true}},
+                bson::doc! {"$unset": {"staged_key": ""}},
+                None,
+            )
+            .await?
+            .and_then(|r| r.staged_key)
+            .ok_or_else(|| DocumentNotFoundError::new(&format!("No key is staged for {name}")))?;
+        Ok(repository)
+    }
+
     pub async fn list(&self) -> Result<Vec<Repository>> {
         let cursor = self.collection.find(None, None).await?;
         let repositories: Vec<Repository> = cursor.try_collect().await?;
@@ -46,21 +232,29 @@ impl RepositoryStore {
     }
 
     pub async fn show(&self, name: &str) -> Result<Repository> {
+        let repository = self.find(name).await?.ok_or_else(|| {
+            DocumentNotFoundError::new(&format!("Repository doesn't exist: {name}"))
+        })?;
+        tracing::debug!(?repository);
+        Ok(repository)
+    }
+
+    /// Remove repository and return it
+    pub async fn remove(&self, name: &str) -> Result<Repository> {
         let repository = self
             .collection
-            .find_one(bson::doc! {"name": name}, None)
+            .find_one_and_delete(bson::doc! {"name": name}, None)
             .await?
             .ok_or_else(|| {
                 DocumentNotFoundError::new(&format!("Repository doesn't exist: {name}"))
             })?;
-        tracing::debug!(?repository);
         Ok(repository)
     }
+}
 
-    pub async fn remove(&self, name: &str) -> Result<()> {
-        self.collection
-            .find_one_and_delete(bson::doc! {"name": name}, None)
-            .await?;
-        Ok(())
-    }
+pub(super) fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(
+        e.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == DUPLICATE_KEY
+    )
 }
@@ -0,0 +1,122 @@
+use anyhow::Result;
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use mongodb::bson;
+use mongodb::options::{FindOptions, IndexOptions};
+use mongodb::IndexModel;
+use recesser_core::audit::format_timestamp;
+use recesser_core::run::{Run, RunQuery, RunStatus};
+
+use super::repository::is_duplicate_key;
+use crate::database::{AlreadyExistsError, DocumentNotFoundError};
+
+#[derive(Clone)]
+pub struct RunStore {
+    collection: mongodb::Collection<Run>,
+}
+
+impl RunStore {
+    pub fn new(collection: mongodb::Collection<Run>) -> Self {
+        Self { collection }
+    }
+
+    pub async fn create_index(&self) -> Result<()> {
+        let options = IndexOptions::builder().unique(true).build();
+        let index = IndexModel::builder()
+            .keys(bson::doc! {"id": 1})
+            .options(options)
+            .build();
+        self.collection.create_index(index, None).await?;
+        Ok(())
+    }
+
+    pub async fn add(&self, run: &Run) -> Result<()> {
+        match self.collection.insert_one(run, None).await {
+            Ok(_) => {
+                tracing::info!(id = %run.id, repository = %run.repository, "Stored new run");
+                Ok(())
+            }
+            Err(e) if is_duplicate_key(&e) => {
+                Err(AlreadyExistsError::new(&format!("Run already exists: {}", run.id)).into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn find(&self, id: &str) -> Result<Run> {
+        self.collection
+            .find_one(bson::doc! {"id": id}, None)
+            .await?
+            .ok_or_else(|| DocumentNotFoundError::new(&format!("Run doesn't exist: {id}")).into())
+    }
+
+    /// Runs ordered from newest to oldest, of a single repository or of all
+    pub async fn list(&self, repository: Option<&str>, query: &RunQuery) -> Result<Vec<Run>> {
+        let mut filter = bson::doc! {};
+        if let Some(repository) = repository {
+            filter.insert("repository", repository);
+        }
+        if query.active {
+            filter.insert("status", bson::doc! {"$in": active_statuses()});
+        }
+        if !query.archived {
+            filter.insert("archived", false);
+        }
+        let options = FindOptions::builder()
+            .sort(bson::doc! {"created": -1})
+            .limit(query.limit)
+            .build();
+        let cursor = self.collection.find(filter, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Store the new state of a run unless it was archived in the meantime
+    pub async fn replace(&self, run: &Run) -> Result<()> {
+        let result = self
+            .collection
+            .replace_one(bson::doc! {"id": &run.id, "archived": false}, run, None)
+            .await?;
+        if result.matched_count == 0 {
+            let message = format!("Run doesn't exist or is archived: {}", run.id);
+            return Err(DocumentNotFoundError::new(&message).into());
+        }
+        Ok(())
+    }
+
+    /// Keep the runs of a removed repository as history. Runs that didn't complete are marked as
+    /// failed because their workflows were stopped.
+    pub async fn archive(&self, repository: &str) -> Result<u64> {
+        self.collection
+            .update_many(
+                bson::doc! {"repository": repository, "status": {"$in": active_statuses()}},
+                bson::doc! {"$set": {
+                    "status": RunStatus::Failed.name(),
+                    "message": "Stopped because the repository was removed",
+                    "finished": format_timestamp(&Utc::now()),
+                }},
+                None,
+            )
+            .await?;
+        let result = self
+            .collection
+            .update_many(
+                bson::doc! {"repository": repository},
+                bson::doc! {"$set": {"archived": true}},
+                None,
+            )
+            .await?;
+        Ok(result.modified_count)
+    }
+
+    pub async fn remove(&self, repository: &str) -> Result<u64> {
+        let result = self
+            .collection
+            .delete_many(bson::doc! {"repository": repository}, None)
+            .await?;
+        Ok(result.deleted_count)
+    }
+}
+
+fn active_statuses() -> Vec<&'static str> {
+    vec![RunStatus::Pending.name(), RunStatus::Running.name()]
+}
@@ -0,0 +1,93 @@
+use anyhow::Result;
+use mongodb::bson;
+use mongodb::options::UpdateOptions;
+use serde::{Deserialize, Serialize};
+
+const INITIALIZATION_ID: &str = "initialization";
+
+#[derive(Clone)]
+pub struct SystemStore {
+    collection: mongodb::Collection<InitializationDoc>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InitializationDoc {
+    #[serde(rename = "_id")]
+    id: String,
+    initialized: bool,
+    admin_id: Option<String>,
+}
+
+impl SystemStore {
+    pub fn new(collection: mongodb::Collection<InitializationDoc>) -> Self {
+        Self { collection }
+    }
+
+    /// Record that the system still needs to be initialized with a first admin. Does nothing if
+    /// the state is already tracked.
+    pub async fn mark_uninitialized(&self) -> Result<()> {
+        self.set_on_insert(bson::doc! {"initialized": false}).await
+    }
+
+    /// Record that the system was initialized by a previous version that did not track its
+    /// initialization state. Does nothing if the state is already tracked.
+    pub async fn mark_legacy_initialized(&self) -> Result<()> {
+        self.set_on_insert(bson::doc! {"initialized": true}).await
+    }
+
+    /// Atomically transition the system into the initialized state.
+    ///
+    /// Returns false if the system was already initialized.
+    pub async fn mark_initialized(&self, admin_id: &str) -> Result<bool> {
+        let mut filter = filter_initialization();
+        filter.insert("initialized", false);
+        let previous = self
+            .collection
+            .find_one_and_update(
+                filter,
+                bson::doc! {"$set": {"initialized": true, "admin_id": admin_id}},
+                None,
+            )
+            .await?;
+        Ok(previous.is_some())
+    }
+
+    /// Undo `mark_initialized` if creating the admin failed, so that initialization can be retried.
+    /// Only resets the state if it was set for this admin.
+    pub async fn revert_initialized(&self, admin_id: &str) -> Result<()> {
+        let mut filter = filter_initialization();
+        filter.insert("admin_id", admin_id);
+        self.collection
+            .update_one(
+                filter,
+                bson::doc! {"$set": {"initialized": false, "admin_id": null}},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn is_initialized(&self) -> Result<bool> {
+        let doc = self
+            .collection
+            .find_one(filter_initialization(), None)
+            .await?;
+        Ok(doc.map(|d| d.initialized).unwrap_or(false))
+    }
+
+    async fn set_on_insert(&self, doc: bson::Document) -> Result<()> {
+        let options = UpdateOptions::builder().upsert(true).build();
+        self.collection
+            .update_one(
+                filter_initialization(),
+                bson::doc! {"$setOnInsert": doc},
+                options,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+fn filter_initialization() -> bson::Document {
+    bson::doc! {"_id": INITIALIZATION_ID}
+}
@@ -5,6 +5,8 @@ use anyhow::Result;
 use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
 use ring::rand::{self, SecureRandom};
 
+use crate::metrics::ENCRYPTION_DURATION;
+
 pub const KEY_LEN: usize = 32;
 pub const NONCE_LEN: usize = aead::NONCE_LEN;
 
@@ -36,7 +38,7 @@ impl SecretBox {
     fn from_slice(input: &[u8]) -> Result<Self> {
         Ok(Self {
             nonce: input[..NONCE_LEN].try_into()?,
-            content: input[NONCE_LEN..].try_into()?,
+            content: input[NONCE_LEN..].into(),
         })
     }
 
@@ -69,28 +71,36 @@ pub fn encrypt_file(
     file_path: &Path,
     key_bytes: &[u8; KEY_LEN],
 ) -> Result<()> {
+    let _timer = ENCRYPTION_DURATION
+        .with_label_values(&["encrypt"])
+        .start_timer();
+
     let mut file_content = Vec::new();
-    let mut file = std::fs::File::open(&file_path)?;
+    let mut file = std::fs::File::open(file_path)?;
     file.read_to_end(&mut file_content)?;
 
     let mut secret_box = SecretBox::new(rng, file_content)?;
     secret_box.encrypt(key_bytes)?;
 
-    let mut file = std::fs::File::create(&file_path)?;
+    let mut file = std::fs::File::create(file_path)?;
     file.write_all(&secret_box.into_token())?;
 
     Ok(())
 }
 
 pub fn decrypt_file(file_path: &Path, key_bytes: &[u8; KEY_LEN]) -> Result<()> {
+    let _timer = ENCRYPTION_DURATION
+        .with_label_values(&["decrypt"])
+        .start_timer();
+
     let mut file_content = Vec::new();
-    let mut file = std::fs::File::open(&file_path)?;
+    let mut file = std::fs::File::open(file_path)?;
     file.read_to_end(&mut file_content)?;
 
     let mut secret_box = SecretBox::from_slice(&file_content)?;
     let plaintext = secret_box.decrypt(key_bytes)?;
 
-    let mut file = std::fs::File::create(&file_path)?;
+    let mut file = std::fs::File::create(file_path)?;
     file.write_all(plaintext)?;
 
     Ok(())
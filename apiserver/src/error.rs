@@ -1,7 +1,14 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
+use std::future::Future;
 
-use actix_web::{http, HttpResponse, HttpResponseBuilder};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{http, Error, HttpMessage, HttpResponse, HttpResponseBuilder, ResponseError};
+use recesser_core::error::{ErrorCode, ErrorResponse};
 use thiserror::Error;
+use tracing_actix_web::RequestId;
 
 #[derive(Debug, Error)]
 pub enum UserError {
@@ -9,8 +16,20 @@ pub enum UserError {
     Integrity,
     #[error("Request is not formatted properly.")]
     BadRequest,
+    #[error("Request is missing the field {field}.")]
+    MissingField { field: &'static str },
+    #[error("Field {field} of the request is invalid.")]
+    InvalidField { field: &'static str, reason: String },
     #[error("Access forbidden.")]
     Unauthorized,
+    #[error("System is already initialized.")]
+    AlreadyInitialized,
+    #[error("{subject} already exists.")]
+    AlreadyExists { subject: String },
+    #[error("Storage quota of {subject} is exceeded.")]
+    QuotaExceeded { subject: String },
+    #[error("Too many requests. Retry in {retry_after} seconds.")]
+    TooManyRequests { retry_after: u64 },
     #[error("Resource at {path} doesn't exist.")]
     NotFound { path: String },
     #[error("An internal error occurred. Please try again later.")]
@@ -28,6 +47,12 @@ impl UserError {
         UserError::BadRequest
     }
 
+    pub fn invalid_field(field: &'static str, e: impl Debug + Display) -> Self {
+        let reason = e.to_string();
+        log_original_error(e);
+        UserError::InvalidField { field, reason }
+    }
+
     pub fn unauthorized(e: impl Debug) -> Self {
         log_original_error(e);
         UserError::Unauthorized
@@ -40,30 +65,126 @@ impl UserError {
         }
     }
 
+    pub fn already_exists(subject: &str, e: impl Debug) -> Self {
+        log_original_error(e);
+        UserError::AlreadyExists {
+            subject: subject.to_string(),
+        }
+    }
+
     pub fn internal(e: impl Debug) -> Self {
         log_original_error(e);
         UserError::Internal
     }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            UserError::Integrity => ErrorCode::IntegrityMismatch,
+            UserError::BadRequest => ErrorCode::BadRequest,
+            UserError::MissingField { .. } => ErrorCode::MissingField,
+            UserError::InvalidField { .. } => ErrorCode::InvalidField,
+            UserError::Unauthorized => ErrorCode::Unauthorized,
+            UserError::AlreadyInitialized => ErrorCode::AlreadyInitialized,
+            UserError::AlreadyExists { .. } => ErrorCode::AlreadyExists,
+            UserError::QuotaExceeded { .. } => ErrorCode::QuotaExceeded,
+            UserError::TooManyRequests { .. } => ErrorCode::TooManyRequests,
+            UserError::NotFound { .. } => ErrorCode::NotFound,
+            UserError::Internal => ErrorCode::Internal,
+        }
+    }
+
+    fn to_error_response(&self) -> ErrorResponse {
+        let error_response = ErrorResponse::new(self.code(), self.to_string());
+        match self {
+            UserError::MissingField { field } => error_response.with_detail(field, "missing"),
+            UserError::InvalidField { field, reason } => {
+                error_response.with_detail(field, reason.as_str())
+            }
+            _ => error_response,
+        }
+    }
 }
 
 fn log_original_error(e: impl Debug) {
     tracing::debug!(error = ?e, "Original error");
 }
 
-impl actix_web::error::ResponseError for UserError {
+impl ResponseError for UserError {
     fn error_response(&self) -> HttpResponse {
         tracing::debug!(error = ?self);
-        HttpResponseBuilder::new(self.status_code())
-            .insert_header(http::header::ContentType::plaintext())
-            .body(self.to_string())
+        let mut builder = HttpResponseBuilder::new(self.status_code());
+        if let UserError::TooManyRequests { retry_after } = self {
+            builder.insert_header((http::header::RETRY_AFTER, *retry_after));
+        }
+        builder.json(self.to_error_response())
     }
     fn status_code(&self) -> http::StatusCode {
         match *self {
             UserError::Integrity => http::StatusCode::BAD_REQUEST,
             UserError::BadRequest => http::StatusCode::BAD_REQUEST,
+            UserError::MissingField { .. } => http::StatusCode::BAD_REQUEST,
+            UserError::InvalidField { .. } => http::StatusCode::BAD_REQUEST,
             UserError::Unauthorized => http::StatusCode::UNAUTHORIZED,
+            UserError::AlreadyInitialized => http::StatusCode::CONFLICT,
+            UserError::AlreadyExists { .. } => http::StatusCode::CONFLICT,
+            UserError::QuotaExceeded { .. } => http::StatusCode::PAYLOAD_TOO_LARGE,
+            UserError::TooManyRequests { .. } => http::StatusCode::TOO_MANY_REQUESTS,
             UserError::NotFound { .. } => http::StatusCode::NOT_FOUND,
-            UserError::Internal { .. } => http::StatusCode::INTERNAL_SERVER_ERROR,
+            UserError::Internal => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Render every error as JSON envelope that carries the ID of the request. This includes errors
+/// that don't originate from the apiserver itself, like failed extractors or authentication.
+pub fn envelope<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<BoxBody>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody + 'static,
+{
+    let request_id = req.extensions().get::<RequestId>().map(ToString::to_string);
+    let fut = srv.call(req);
+    async move {
+        match fut.await {
+            Ok(res) => {
+                let response = res
+                    .response()
+                    .error()
+                    .map(|e| render(e, request_id.clone()));
+                match response {
+                    Some(response) => Ok(res.into_response(response)),
+                    None => Ok(res.map_into_boxed_body()),
+                }
+            }
+            Err(e) => {
+                let response = render(&e, request_id);
+                Err(InternalError::from_response(e, response).into())
+            }
         }
     }
 }
+
+fn render(e: &Error, request_id: Option<String>) -> HttpResponse {
+    let mut error_response = match e.as_error::<UserError>() {
+        Some(e) => e.to_error_response(),
+        None => {
+            let status = e.as_response_error().status_code();
+            ErrorResponse::new(ErrorCode::from_status(status.as_u16()), e.to_string())
+        }
+    };
+    error_response.request_id = request_id;
+
+    // Keep status and headers (e.g. Retry-After) of the original response
+    let mut response = e.error_response();
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    match serde_json::to_string(&error_response) {
+        Ok(body) => response.set_body(BoxBody::new(body)),
+        Err(_) => response,
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use actix_web::web::Bytes;
+use anyhow::Result;
+use futures_util::stream::{self, Stream};
+use recesser_core::event::{Event, EventKind};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch, Mutex};
+
+use crate::database::EventStore;
+
+/// Number of events buffered for each subscriber. Subscribers that fall further behind catch up
+/// from the database.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Interval of comments that keep idle streams from being closed by proxies
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Persists events and distributes them to subscribers
+pub struct EventBus {
+    store: EventStore,
+    sender: broadcast::Sender<Event>,
+    // Publishing is serialized so that subscribers receive events in the order of their IDs
+    publish_lock: Mutex<()>,
+    // ID of the newest published event, which is where new subscriptions start
+    latest_id: std::sync::Mutex<Option<u64>>,
+    closed_sender: watch::Sender<bool>,
+    closed: watch::Receiver<bool>,
+}
+
+/// Message of an event stream
+pub enum Message {
+    Event(Event),
+    KeepAlive,
+}
+
+struct Subscription {
+    store: EventStore,
+    receiver: broadcast::Receiver<Event>,
+    closed: watch::Receiver<bool>,
+    backlog: VecDeque<Event>,
+    last_id: Option<u64>,
+    needs_catch_up: bool,
+}
+
+impl EventBus {
+    pub async fn new(store: EventStore) -> Result<Self> {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (closed_sender, closed) = watch::channel(false);
+        let latest_id = store.latest_id().await?;
+        Ok(Self {
+            store,
+            sender,
+            publish_lock: Mutex::new(()),
+            latest_id: std::sync::Mutex::new(latest_id),
+            closed_sender,
+            closed,
+        })
+    }
+
+    /// Persist and distribute an event. Failures are only logged because they shouldn't affect
+    /// the operation that caused the event.
+    pub async fn publish(&self, project: Option<String>, kind: EventKind) {
+        if let Err(e) = self.try_publish(project, kind).await {
+            tracing::error!(error = %e, "Failed to publish event");
+        }
+    }
+
+    pub async fn try_publish(&self, project: Option<String>, kind: EventKind) -> Result<Event> {
+        let _guard = self.publish_lock.lock().await;
+        let event = self.store.append(project, kind).await?;
+        tracing::debug!(id = event.id, kind = event.kind.name(), "Published event");
+        *self.latest_id.lock().unwrap() = Some(event.id);
+        // Sending only fails if there are no subscribers
+        let _ = self.sender.send(event.clone());
+        Ok(event)
+    }
+
+    /// Stream of events published from now on. If the ID of the last event the client has seen
+    /// is given, all later events are replayed from the database first.
+    pub fn subscribe(&self, last_id: Option<u64>) -> impl Stream<Item = Message> {
+        // Subscribe before catching up so that no event is missed in between
+        let receiver = self.sender.subscribe();
+        // Without a last ID the subscription starts after the newest event. That's also where it
+        // catches up from if it lags before receiving its first event.
+        let start_id = *self.latest_id.lock().unwrap();
+        let subscription = Subscription {
+            store: self.store.clone(),
+            receiver,
+            closed: self.closed.clone(),
+            backlog: VecDeque::new(),
+            last_id: last_id.or(start_id),
+            needs_catch_up: last_id.is_some(),
+        };
+        stream::unfold(subscription, |mut subscription| async move {
+            let message = subscription.next().await?;
+            Some((message, subscription))
+        })
+    }
+
+    /// End all streams, e.g. so that they don't delay a graceful shutdown
+    pub fn close(&self) {
+        let _ = self.closed_sender.send(true);
+    }
+}
+
+impl Subscription {
+    async fn next(&mut self) -> Option<Message> {
+        loop {
+            if let Some(event) = self.backlog.pop_front() {
+                self.last_id = Some(event.id);
+                return Some(Message::Event(event));
+            }
+
+            if self.needs_catch_up {
+                self.needs_catch_up = false;
+                match self.store.after(self.last_id).await {
+                    Ok(events) => self.backlog.extend(events),
+                    Err(e) => {
+                        tracing::error!(error = %e, "Failed to replay events");
+                        return None;
+                    }
+                }
+                continue;
+            }
+
+            tokio::select! {
+                result = self.receiver.recv() => match result {
+                    Ok(event) => {
+                        let is_new = match self.last_id {
+                            Some(last_id) => event.id > last_id,
+                            None => true,
+                        };
+                        if is_new {
+                            self.last_id = Some(event.id);
+                            return Some(Message::Event(event));
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => self.needs_catch_up = true,
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = self.closed.changed() => return None,
+                _ = tokio::time::sleep(KEEP_ALIVE_INTERVAL) => return Some(Message::KeepAlive),
+            }
+        }
+    }
+}
+
+impl Message {
+    /// Encode message in the Server-Sent Events format
+    pub fn to_sse(&self) -> Bytes {
+        match self {
+            Message::Event(event) => {
+                let data = serde_json::to_string(event).unwrap_or_default();
+                Bytes::from(format!(
+                    "id: {}\nevent: {}\ndata: {data}\n\n",
+                    event.id,
+                    event.kind.name()
+                ))
+            }
+            Message::KeepAlive => Bytes::from_static(b": keep-alive\n\n"),
+        }
+    }
+}
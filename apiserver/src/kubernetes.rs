@@ -1,58 +1,258 @@
 use std::collections::BTreeMap;
 
 use anyhow::Result;
-use k8s_openapi::api::core::v1::Secret;
-use kube::api::{Api, ObjectMeta, PostParams};
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::core::v1::{Pod, Secret};
+use kube::api::{
+    Api, ApiResource, DeleteParams, DynamicObject, GroupVersionKind, ListParams, LogParams,
+    ObjectMeta, Patch, PatchParams, PostParams,
+};
 use recesser_core::encoding::hex;
-use recesser_core::repository::KeyPair;
+use recesser_core::repository::{self, Fingerprint, REPOSITORY_LABEL};
+use serde_json::json;
 
 use crate::auth::Token;
 
+const SSH_SECRET_TYPE: &str = "kubernetes.io/ssh-auth";
+/// Label of the secrets created by the apiserver. Secrets created before the label was introduced
+/// are never considered orphaned.
+const MANAGED_BY_LABEL: &str = "app.kubernetes.io/managed-by";
+const REPOSITORY_ANNOTATION: &str = "recesser.io/repository";
+/// Label of workflows that are kept as history of a removed repository
+const ARCHIVED_LABEL: &str = "recesser.io/archived";
+/// Label Argo sets on the pods of a workflow
+const WORKFLOW_LABEL: &str = "workflows.argoproj.io/workflow";
+/// Annotation Argo sets on pods with the name of the step they run
+const NODE_NAME_ANNOTATION: &str = "workflows.argoproj.io/node-name";
+
+/// Secret with the private SSH key of a repository in the argo namespace
+pub struct SshSecret {
+    pub name: String,
+    pub managed: bool,
+    pub created: Option<DateTime<Utc>>,
+}
+
+/// Argo workflow that was submitted for a repository
+pub struct RepositoryWorkflow {
+    pub name: String,
+    pub finished: bool,
+}
+
+/// Log of the main container of a pod of a workflow
+pub struct StepLog {
+    /// Name of the step the pod ran
+    pub step: String,
+    pub log: Result<String>,
+}
+
 pub struct KubernetesApiserver {
+    client: kube::Client,
     recesser_secrets: Api<Secret>,
     argo_secrets: Api<Secret>,
+    argo_workflows: Api<DynamicObject>,
+    argo_pods: Api<Pod>,
 }
 
 impl KubernetesApiserver {
     pub async fn new() -> Result<Self> {
         let client = kube::Client::try_default().await?;
         let recesser_secrets: Api<Secret> = Api::namespaced(client.clone(), "recesser");
-        let argo_secrets: Api<Secret> = Api::namespaced(client, "argo");
+        let argo_secrets: Api<Secret> = Api::namespaced(client.clone(), "argo");
+        let workflow = GroupVersionKind::gvk("argoproj.io", "v1alpha1", "Workflow");
+        let argo_workflows =
+            Api::namespaced_with(client.clone(), "argo", &ApiResource::from_gvk(&workflow));
+        let argo_pods: Api<Pod> = Api::namespaced(client.clone(), "argo");
         tracing::info!("Connected to kubernetes apiserver");
         Ok(Self {
+            client,
             recesser_secrets,
             argo_secrets,
+            argo_workflows,
+            argo_pods,
         })
     }
 
-    pub async fn create_ssh_secret(&self, key_pair: &KeyPair) -> Result<()> {
-        // Mostly an ugly hack to keep to the Kubernetes constraint of volume names not being
-        // allowed to exceed 63 characters
-        let short_fingerprint: String = key_pair
-            .public_key
-            .fingerprint
-            .to_string()
-            .chars()
-            .take(20)
-            .collect();
-        let secret_name = hex::encode_str(&short_fingerprint)?;
+    pub async fn ping(&self) -> Result<()> {
+        self.client.apiserver_version().await?;
+        Ok(())
+    }
+
+    /// Create the secret with the private key of a repository or replace it if it already exists
+    pub async fn apply_ssh_secret(
+        &self,
+        repository: &str,
+        fingerprint: &Fingerprint,
+        private_key: &str,
+    ) -> Result<()> {
         let secret = Secret {
             metadata: ObjectMeta {
-                name: Some(secret_name),
+                name: Some(ssh_secret_name(fingerprint)?),
+                labels: Some(BTreeMap::from([(
+                    MANAGED_BY_LABEL.into(),
+                    "recesser".into(),
+                )])),
+                annotations: Some(BTreeMap::from([(
+                    REPOSITORY_ANNOTATION.into(),
+                    repository.into(),
+                )])),
                 ..Default::default()
             },
             string_data: Some(BTreeMap::from([(
                 "ssh-privatekey".into(),
-                key_pair.private_key.to_string(),
+                private_key.into(),
             )])),
-            type_: Some("kubernetes.io/ssh-auth".into()),
+            type_: Some(SSH_SECRET_TYPE.into()),
             ..Default::default()
         };
-        self.create_argo_secret(&secret).await?;
+        create_or_replace(&self.argo_secrets, &secret).await
+    }
+
+    /// Create or replace the secret with the access token of a repository that is cloned via HTTPS
+    pub async fn apply_git_token_secret(
+        &self,
+        repository: &str,
+        username: &str,
+        token: &str,
+    ) -> Result<()> {
+        let secret = Secret {
+            metadata: ObjectMeta {
+                name: Some(repository::token_secret_name(repository)),
+                labels: Some(BTreeMap::from([(
+                    MANAGED_BY_LABEL.into(),
+                    "recesser".into(),
+                )])),
+                annotations: Some(BTreeMap::from([(
+                    REPOSITORY_ANNOTATION.into(),
+                    repository.into(),
+                )])),
+                ..Default::default()
+            },
+            string_data: Some(BTreeMap::from([
+                ("username".into(), username.into()),
+                ("password".into(), token.into()),
+            ])),
+            type_: Some("kubernetes.io/basic-auth".into()),
+            ..Default::default()
+        };
+        create_or_replace(&self.argo_secrets, &secret).await
+    }
+
+    /// Delete a secret in the argo namespace. Does nothing if it doesn't exist.
+    pub async fn delete_argo_secret(&self, name: &str) -> Result<()> {
+        match self
+            .argo_secrets
+            .delete(name, &DeleteParams::default())
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(kube::Error::Api(e)) if e.code == 404 => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn list_ssh_secrets(&self) -> Result<Vec<SshSecret>> {
+        let params = ListParams::default().fields(&format!("type={SSH_SECRET_TYPE}"));
+        let secrets = self.argo_secrets.list(&params).await?;
+        Ok(secrets
+            .into_iter()
+            .filter_map(|secret| {
+                let metadata = secret.metadata;
+                let managed = metadata
+                    .labels
+                    .as_ref()
+                    .and_then(|labels| labels.get(MANAGED_BY_LABEL))
+                    .map(|value| value == "recesser")
+                    .unwrap_or(false);
+                Some(SshSecret {
+                    name: metadata.name?,
+                    managed,
+                    created: metadata.creation_timestamp.map(|t| t.0),
+                })
+            })
+            .collect())
+    }
+
+    /// Workflows of a repository that haven't been archived
+    pub async fn list_workflows(&self, repository: &str) -> Result<Vec<RepositoryWorkflow>> {
+        let selector = format!(
+            "{REPOSITORY_LABEL}={},!{ARCHIVED_LABEL}",
+            repository::label_value(repository)
+        );
+        let workflows = self
+            .argo_workflows
+            .list(&ListParams::default().labels(&selector))
+            .await?;
+        Ok(workflows
+            .into_iter()
+            .filter_map(|workflow| {
+                let phase = workflow.data["status"]["phase"].as_str();
+                let finished = matches!(phase, Some("Succeeded" | "Failed" | "Error"));
+                Some(RepositoryWorkflow {
+                    name: workflow.metadata.name?,
+                    finished,
+                })
+            })
+            .collect())
+    }
+
+    /// Delete a workflow, which also stops it if it's still running
+    pub async fn delete_workflow(&self, name: &str) -> Result<()> {
+        match self
+            .argo_workflows
+            .delete(name, &DeleteParams::default())
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(kube::Error::Api(e)) if e.code == 404 => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Mark a workflow as history so that it's no longer considered part of a repository
+    pub async fn archive_workflow(&self, name: &str) -> Result<()> {
+        let patch = json!({ "metadata": { "labels": { ARCHIVED_LABEL: "true" } } });
+        self.argo_workflows
+            .patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+            .await?;
         Ok(())
     }
 
-    pub async fn create_token_secret(&self, name: &str, token: &Token) -> Result<()> {
+    /// Logs of the pods of a workflow in the order they were created. Pods that Argo already
+    /// garbage collected are missing.
+    pub async fn workflow_logs(&self, workflow: &str) -> Result<Vec<StepLog>> {
+        let selector = format!("{WORKFLOW_LABEL}={workflow}");
+        let mut pods = self
+            .argo_pods
+            .list(&ListParams::default().labels(&selector))
+            .await?
+            .items;
+        pods.sort_by_key(|pod| pod.metadata.creation_timestamp.as_ref().map(|t| t.0));
+
+        let params = LogParams {
+            container: Some(String::from("main")),
+            ..Default::default()
+        };
+        let mut logs = Vec::new();
+        for pod in pods {
+            let name = pod.metadata.name.unwrap_or_default();
+            let step = pod
+                .metadata
+                .annotations
+                .and_then(|mut annotations| annotations.remove(NODE_NAME_ANNOTATION))
+                .unwrap_or_else(|| name.clone());
+            let log = self
+                .argo_pods
+                .logs(&name, &params)
+                .await
+                .map_err(Into::into);
+            logs.push(StepLog { step, log });
+        }
+        Ok(logs)
+    }
+
+    /// Create or replace the secret that holds an access token in both the recesser and argo
+    /// namespace
+    pub async fn apply_token_secret(&self, name: &str, token: &Token) -> Result<()> {
         let secret = Secret {
             metadata: ObjectMeta {
                 name: Some(name.into()),
@@ -61,20 +261,30 @@ impl KubernetesApiserver {
             string_data: Some(BTreeMap::from([("token".into(), token.to_string()?)])),
             ..Default::default()
         };
-        self.create_recesser_secret(&secret).await?;
-        self.create_argo_secret(&secret).await?;
+        create_or_replace(&self.recesser_secrets, &secret).await?;
+        create_or_replace(&self.argo_secrets, &secret).await?;
         Ok(())
     }
+}
 
-    async fn create_recesser_secret(&self, secret: &Secret) -> Result<()> {
-        let params = PostParams::default();
-        self.recesser_secrets.create(&params, secret).await?;
-        Ok(())
-    }
+/// Name of the secret with the private key of a repository
+pub fn ssh_secret_name(fingerprint: &Fingerprint) -> Result<String> {
+    // Mostly an ugly hack to keep to the Kubernetes constraint of volume names not being
+    // allowed to exceed 63 characters
+    let short_fingerprint: String = fingerprint.to_string().chars().take(20).collect();
+    hex::encode_str(&short_fingerprint)
+}
 
-    async fn create_argo_secret(&self, secret: &Secret) -> Result<()> {
-        let params = PostParams::default();
-        self.argo_secrets.create(&params, secret).await?;
-        Ok(())
+/// Create secret or replace it if it already exists
+async fn create_or_replace(api: &Api<Secret>, secret: &Secret) -> Result<()> {
+    let params = PostParams::default();
+    match api.create(&params, secret).await {
+        Ok(_) => Ok(()),
+        Err(kube::Error::Api(e)) if e.code == 409 => {
+            let name = secret.metadata.name.as_deref().unwrap_or_default();
+            api.replace(name, &params, secret).await?;
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
     }
 }
@@ -0,0 +1,134 @@
+#![forbid(unsafe_code)]
+
+pub mod audit;
+pub mod auth;
+pub mod bootstrap;
+pub mod database;
+pub mod encryption;
+pub mod error;
+pub mod events;
+pub mod kubernetes;
+pub mod logging;
+pub mod metrics;
+pub mod notifications;
+pub mod objectstorage;
+pub mod ratelimit;
+pub mod reconciler;
+pub mod routes;
+pub mod secretstorage;
+pub mod settings;
+pub mod tls;
+pub mod webhooks;
+
+use std::sync::{Mutex, RwLock};
+
+use anyhow::{anyhow, Result};
+use ring::rand::SystemRandom;
+
+use auth::HmacKey;
+use database::Database;
+use events::EventBus;
+use kubernetes::KubernetesApiserver;
+use notifications::{Notifier, SmtpConfig};
+use objectstorage::ObjectStorage;
+use ratelimit::{RateLimiter, RateLimits, TransferLimiter};
+use secretstorage::SecretStorage;
+use settings::{Redacted, Settings};
+
+pub struct AppState {
+    objstore: ObjectStorage,
+    database: Database,
+    secstore: SecretStorage,
+    k8s_apiserver: KubernetesApiserver,
+    hmac_key: Mutex<HmacKey>,
+    rng: SystemRandom,
+    bootstrap_secret: Option<Redacted>,
+    quotas: RwLock<Quotas>,
+    rate_limiter: RateLimiter,
+    transfer_limiter: TransferLimiter,
+    events: EventBus,
+    notifier: Notifier,
+    reconciliation_lock: tokio::sync::Mutex<()>,
+    /// Users and admins need to connect with a client certificate
+    client_certificates_required: bool,
+    /// Repositories can be registered with local paths
+    allow_file_urls: bool,
+}
+
+impl AppState {
+    /// Connect to all backends and initialize the HMAC key
+    pub async fn new(s: &Settings) -> Result<Self> {
+        // Initialize object storage
+        let objstore = ObjectStorage::new(&s.objectstorage_addr).await?;
+
+        // Initialize database
+        let database = Database::new(&s.database_addr).await?;
+
+        // Initialize secret storage
+        let vault_token = std::env::var("RECESSER_SECRETSTORAGE_TOKEN")
+            .map_err(|_| anyhow!("Secret storage token needs to be specified via environment"))?;
+        let secstore = SecretStorage::new(&s.secretstorage_addr, vault_token)?;
+        secstore.setup().await?;
+
+        // Initialize kubernetes apiserver
+        let k8s_apiserver = KubernetesApiserver::new().await?;
+
+        // Initialize HMAC key and the Machine token of the schandler
+        let rng = ring::rand::SystemRandom::new();
+        let hmac_key =
+            bootstrap::initialize_hmac_key(&rng, &secstore, &database, &k8s_apiserver).await?;
+
+        let events = EventBus::new(database.events.clone()).await?;
+
+        Ok(Self {
+            objstore,
+            database,
+            secstore,
+            k8s_apiserver,
+            hmac_key: Mutex::new(hmac_key),
+            rng,
+            bootstrap_secret: s.bootstrap_secret.clone(),
+            quotas: RwLock::new(Quotas::from_settings(s)),
+            rate_limiter: RateLimiter::new(RateLimits::from_settings(s)),
+            transfer_limiter: TransferLimiter::new(s.max_concurrent_transfers),
+            events,
+            notifier: Notifier::new(SmtpConfig::from_settings(s))?
+                .with_allowed_hosts(s.notification_allowed_hosts.as_deref().unwrap_or_default()),
+            reconciliation_lock: tokio::sync::Mutex::new(()),
+            client_certificates_required: tls::client_certificates_required(s),
+            allow_file_urls: s.allow_file_urls,
+        })
+    }
+
+    /// Apply the settings that can change without a restart
+    pub fn reload(&self, s: &Settings) {
+        *self.quotas.write().unwrap() = Quotas::from_settings(s);
+        self.rate_limiter.set_limits(RateLimits::from_settings(s));
+        self.transfer_limiter.set_max(s.max_concurrent_transfers);
+    }
+
+    /// Close event streams so that they don't keep the server from shutting down
+    pub fn close_event_streams(&self) {
+        self.events.close();
+    }
+
+    fn quotas(&self) -> Quotas {
+        *self.quotas.read().unwrap()
+    }
+}
+
+/// Storage quotas in bytes. No quota is enforced if unset.
+#[derive(Clone, Copy)]
+struct Quotas {
+    user: Option<u64>,
+    project: Option<u64>,
+}
+
+impl Quotas {
+    fn from_settings(s: &Settings) -> Self {
+        Self {
+            user: s.user_quota,
+            project: s.project_quota,
+        }
+    }
+}
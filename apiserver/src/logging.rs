@@ -1,7 +1,12 @@
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::{Error, HttpMessage};
 use tracing::Span;
-use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder, TracingLogger};
+use tracing_actix_web::{
+    DefaultRootSpanBuilder, RequestId, RootSpan, RootSpanBuilder, TracingLogger,
+};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, Registry};
 
 use crate::auth::Token;
 
@@ -9,11 +14,14 @@ pub struct CustomRootSpanBuilder;
 
 impl RootSpanBuilder for CustomRootSpanBuilder {
     fn on_request_start(req: &ServiceRequest) -> Span {
-        let user_id: String = match req.extensions().get::<Token>() {
-            Some(token) => token.user_id().into(),
-            None => "Not provided".into(),
-        };
-        tracing::info_span!("Request", %user_id)
+        // The request ID is also returned in error responses to correlate them with the logs
+        let request_id = req.extensions().get::<RequestId>().map(ToString::to_string);
+        // The user is only known after authentication and is recorded by `record_user_id`
+        tracing::info_span!(
+            "Request",
+            request_id = tracing::field::display(request_id.unwrap_or_default()),
+            user_id = tracing::field::Empty
+        )
     }
 
     fn on_request_end<B>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
@@ -21,6 +29,26 @@ impl RootSpanBuilder for CustomRootSpanBuilder {
     }
 }
 
+/// Handle to change the log level while the apiserver is running
+pub type LogLevelHandle = reload::Handle<LevelFilter, Registry>;
+
+/// Install the global subscriber with a log level that can be changed at runtime
+pub fn init_subscriber(level: LevelFilter) -> LogLevelHandle {
+    let (filter, handle) = reload::Layer::new(level);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .init();
+    handle
+}
+
 pub fn init() -> TracingLogger<CustomRootSpanBuilder> {
     TracingLogger::<CustomRootSpanBuilder>::new()
 }
+
+/// Record the authenticated user in the root span of the request
+pub fn record_user_id(req: &ServiceRequest, token: &Token) {
+    if let Some(span) = req.extensions().get::<RootSpan>() {
+        span.record("user_id", &token.user_id());
+    }
+}
@@ -1,110 +1,129 @@
 #![forbid(unsafe_code)]
 
-mod auth;
-mod database;
-mod encryption;
-mod error;
-mod kubernetes;
-mod logging;
-mod objectstorage;
-mod routes;
-mod secretstorage;
-mod settings;
-
 use std::str::FromStr;
-use std::sync::Mutex;
+use std::time::Duration;
 
+use actix_web::dev::ServerHandle;
 use actix_web::{web, App, HttpServer};
 use actix_web_httpauth::middleware::HttpAuthentication;
-use anyhow::{anyhow, Result};
-use recesser_core::user::Scope;
-use ring::rand::SystemRandom;
+use anyhow::Result;
+use tokio::signal::unix::{signal, SignalKind};
 use tracing_subscriber::filter::LevelFilter;
 
-use auth::middleware::validator;
-use auth::{HmacKey, Token};
-use database::Database;
-use kubernetes::KubernetesApiserver;
-use objectstorage::ObjectStorage;
-use secretstorage::SecretStorage;
-use settings::Settings;
-
-pub struct AppState {
-    objstore: ObjectStorage,
-    database: Database,
-    secstore: SecretStorage,
-    k8s_apiserver: KubernetesApiserver,
-    hmac_key: Mutex<HmacKey>,
-    rng: SystemRandom,
-}
+use recesser_apiserver::auth::middleware::validator;
+use recesser_apiserver::logging::LogLevelHandle;
+use recesser_apiserver::settings::Settings;
+use recesser_apiserver::{
+    audit, error, logging, metrics, notifications, ratelimit, reconciler, routes, tls, AppState,
+};
 
 #[actix_web::main]
 async fn main() -> Result<()> {
     let s = Settings::new()?;
 
     let log_level = LevelFilter::from_str(&s.log_level)?;
-    tracing_subscriber::fmt().with_max_level(log_level).init();
+    let log_level_handle = logging::init_subscriber(log_level);
 
     tracing::debug!(settings = ?s);
 
-    // Initialize object storage
-    let objstore = ObjectStorage::new(&s.objectstorage_addr).await?;
-
-    // Initialize database
-    let database = Database::new(&s.database_addr).await?;
-
-    // Initialize secret storage
-    let vault_token = std::env::var("RECESSER_SECRETSTORAGE_TOKEN")
-        .map_err(|_| anyhow!("Secret storage token needs to be specified via environment"))?;
-    let secstore = SecretStorage::new(&s.secretstorage_addr, vault_token)?;
-    secstore.setup().await?;
-
-    // Initialize kubernetes apiserver
-    let k8s_apiserver = KubernetesApiserver::new().await?;
-
-    // Initialize HMAC key and access token
-    let rng = ring::rand::SystemRandom::new();
-    let hmac_key = match secstore.get_hmac_key().await {
-        Ok(key_value) => HmacKey::new(&key_value),
-        Err(_) => {
-            let key_value = HmacKey::generate_key_value(&rng)?;
-            let hmac_key = HmacKey::new(&key_value);
-
-            secstore.store_hmac_key(&key_value).await?;
-
-            let initial_token = Token::create(Scope::Admin, &hmac_key)?;
-
-            // Store initial token as a kubernetes secret so schandler can access Apiserver
-            // automatically. This should really generate a separate Machine token instead
-            // to avoid exposing the root token to Kubernetes.
-            k8s_apiserver
-                .create_token_secret("apiserver-token", &initial_token)
-                .await?;
+    let app_state = web::Data::new(AppState::new(&s).await?);
+
+    actix_web::rt::spawn(reload_on_hangup(app_state.clone(), log_level_handle));
+    actix_web::rt::spawn(notifications::dispatch(app_state.clone()));
+    if s.reconcile_interval > 0 {
+        let interval = Duration::from_secs(s.reconcile_interval * 60);
+        actix_web::rt::spawn(reconciler::run_periodically(
+            app_state.clone(),
+            interval,
+            s.reconcile_repair,
+        ));
+    }
+
+    let shutdown_state = app_state.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(app_state.clone())
+            .configure(routes::public_config)
+            .service(
+                web::scope(routes::API_PREFIX)
+                    .configure(routes::public_api_config)
+                    .service(
+                        web::scope("")
+                            .configure(routes::config)
+                            .wrap_fn(ratelimit::limit)
+                            .wrap(HttpAuthentication::bearer(validator)),
+                    ),
+            )
+            .wrap_fn(error::envelope)
+            .wrap_fn(audit::record)
+            .wrap_fn(metrics::record)
+            .wrap(logging::init())
+    })
+    // On SIGTERM the server stops accepting connections and waits for in-flight requests
+    .shutdown_timeout(s.shutdown_timeout)
+    .on_connect(tls::record_client_certificate)
+    .disable_signals();
+
+    let server = match tls::server_config(&s)? {
+        Some((config, certificate)) => {
+            actix_web::rt::spawn(certificate.watch());
+            server.bind_rustls(&s.addr, config)?
+        }
+        None => server.bind(&s.addr)?,
+    };
+    let server = server.run();
+    actix_web::rt::spawn(stop_on_signal(shutdown_state, server.handle()));
+    server.await?;
 
-            println!("Initial token: {}", initial_token.to_string()?);
+    tracing::info!("Shut down");
+    Ok(())
+}
 
-            hmac_key
+/// Close event streams before stopping the server so they don't hold up the graceful shutdown
+async fn stop_on_signal(app_state: web::Data<AppState>, server_handle: ServerHandle) {
+    let (mut terminate, mut interrupt) = match (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) {
+        (Ok(terminate), Ok(interrupt)) => (terminate, interrupt),
+        (Err(e), _) | (_, Err(e)) => {
+            tracing::error!(error = %e, "Failed to listen for shutdown signals");
+            return;
         }
     };
+    tokio::select! {
+        _ = terminate.recv() => tracing::info!("Received SIGTERM. Shutting down"),
+        _ = interrupt.recv() => tracing::info!("Received SIGINT. Shutting down"),
+    }
+    app_state.close_event_streams();
+    server_handle.stop(true).await;
+}
 
-    let app_state = web::Data::new(AppState {
-        objstore,
-        database,
-        secstore,
-        k8s_apiserver,
-        hmac_key: Mutex::new(hmac_key),
-        rng,
-    });
+/// Reload the log level and limits from the configuration on SIGHUP
+async fn reload_on_hangup(app_state: web::Data<AppState>, log_level_handle: LogLevelHandle) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to listen for SIGHUP");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        match reload(&app_state, &log_level_handle) {
+            Ok(()) => tracing::info!("Reloaded settings"),
+            Err(e) => tracing::error!(
+                error = %e,
+                "Failed to reload settings. Continuing with the previous ones"
+            ),
+        }
+    }
+}
 
-    HttpServer::new(move || {
-        App::new()
-            .app_data(app_state.clone())
-            .configure(routes::config)
-            .wrap(logging::init())
-            .wrap(HttpAuthentication::bearer(validator))
-    })
-    .bind(&s.addr)?
-    .run()
-    .await?;
+fn reload(app_state: &AppState, log_level_handle: &LogLevelHandle) -> Result<()> {
+    let s = Settings::new()?;
+    let log_level = LevelFilter::from_str(&s.log_level)?;
+    log_level_handle.reload(log_level)?;
+    app_state.reload(&s);
+    tracing::debug!(settings = ?s);
     Ok(())
 }
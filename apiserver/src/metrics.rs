@@ -0,0 +1,115 @@
+use std::future::Future;
+use std::time::Instant;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::Error;
+use anyhow::Result;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+
+lazy_static::lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "recesser_apiserver_http_requests_total",
+        "Number of HTTP requests",
+        &["method", "route", "status"]
+    )
+    .unwrap();
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "recesser_apiserver_http_request_duration_seconds",
+        "Time until the response to an HTTP request was ready",
+        &["method", "route"]
+    )
+    .unwrap();
+    pub static ref BYTES_UPLOADED: IntCounter = register_int_counter!(
+        "recesser_apiserver_uploaded_bytes_total",
+        "Number of bytes of uploaded artifacts"
+    )
+    .unwrap();
+    pub static ref BYTES_DOWNLOADED: IntCounter = register_int_counter!(
+        "recesser_apiserver_downloaded_bytes_total",
+        "Number of bytes of downloaded artifacts"
+    )
+    .unwrap();
+    pub static ref ENCRYPTION_DURATION: HistogramVec = register_histogram_vec!(
+        "recesser_apiserver_encryption_duration_seconds",
+        "Time spent encrypting and decrypting files",
+        &["operation"]
+    )
+    .unwrap();
+    pub static ref OBJECTSTORAGE_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "recesser_apiserver_objectstorage_errors_total",
+        "Number of failed object storage operations",
+        &["operation"]
+    )
+    .unwrap();
+    pub static ref SECRETSTORAGE_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "recesser_apiserver_secretstorage_errors_total",
+        "Number of failed secret storage operations",
+        &["operation"]
+    )
+    .unwrap();
+    pub static ref AUTH_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "recesser_apiserver_auth_failures_total",
+        "Number of rejected access tokens",
+        &["reason"]
+    )
+    .unwrap();
+    pub static ref RECONCILIATION_DRIFT: IntGaugeVec = register_int_gauge_vec!(
+        "recesser_apiserver_reconciliation_drift",
+        "Drift found by the last reconciliation",
+        &["kind"]
+    )
+    .unwrap();
+}
+
+/// Encode all metrics in the Prometheus text format
+pub fn encode() -> Result<String> {
+    let mut buf = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buf)?;
+    Ok(String::from_utf8(buf)?)
+}
+
+/// Count the error of a backend operation
+pub fn count_error<T>(counter: &IntCounterVec, operation: &str, result: Result<T>) -> Result<T> {
+    if result.is_err() {
+        counter.with_label_values(&[operation]).inc();
+    }
+    result
+}
+
+/// Record count and duration of requests per route
+pub fn record<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+{
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let fut = srv.call(req);
+    async move {
+        let result = fut.await;
+        let (route, status) = match &result {
+            // Use the route pattern instead of the path to keep the number of labels bounded
+            Ok(res) => (
+                res.request().match_pattern(),
+                res.status().as_u16().to_string(),
+            ),
+            Err(e) => (
+                None,
+                e.as_response_error().status_code().as_u16().to_string(),
+            ),
+        };
+        let route = route.unwrap_or_else(|| String::from("unmatched"));
+        HTTP_REQUESTS
+            .with_label_values(&[&method, &route, &status])
+            .inc();
+        HTTP_REQUEST_DURATION
+            .with_label_values(&[&method, &route])
+            .observe(start.elapsed().as_secs_f64());
+        result
+    }
+}
@@ -0,0 +1,321 @@
+mod smtp;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use actix_web::web;
+use anyhow::{bail, Result};
+use chrono::Utc;
+use futures_util::StreamExt;
+use recesser_core::encoding::hex;
+use recesser_core::notification::{Channel, Delivery, Notification, Target};
+use ring::hmac;
+use serde_json::json;
+
+use crate::events::Message;
+use crate::AppState;
+
+pub use smtp::SmtpConfig;
+
+/// Maximum number of attempts to deliver a notification
+pub const MAX_ATTEMPTS: u32 = 5;
+
+/// Time after which a webhook is considered unreachable
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delivers notifications to channels and retries failed deliveries with exponential backoff
+pub struct Notifier {
+    client: reqwest::Client,
+    smtp: Option<SmtpConfig>,
+    retry_delay: Duration,
+    allowed_hosts: Vec<String>,
+}
+
+impl Notifier {
+    pub fn new(smtp: Option<SmtpConfig>) -> Result<Self> {
+        Ok(Self {
+            client: client_builder().build()?,
+            smtp,
+            retry_delay: Duration::from_secs(2),
+            allowed_hosts: Vec::new(),
+        })
+    }
+
+    /// Delay before the first retry. Every further retry waits twice as long.
+    pub fn with_retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Comma separated hosts that webhooks may target even though they are internal, e.g. a
+    /// self-hosted Mattermost in the cluster
+    pub fn with_allowed_hosts(mut self, allowed_hosts: &str) -> Self {
+        self.allowed_hosts = allowed_hosts
+            .split(',')
+            .map(|host| host.trim().to_ascii_lowercase())
+            .filter(|host| !host.is_empty())
+            .collect();
+        self
+    }
+
+    pub fn supports_email(&self) -> bool {
+        self.smtp.is_some()
+    }
+
+    /// Ensure that a webhook doesn't target the cluster or another internal service unless its
+    /// host is allowed. Hostnames are resolved so that names of cluster services are caught too.
+    pub async fn check_url(&self, url: &reqwest::Url) -> Result<()> {
+        self.resolve(url).await?;
+        Ok(())
+    }
+
+    /// Checked address to connect to if the host of the URL is a name that isn't allowed
+    /// explicitly
+    async fn resolve(&self, url: &reqwest::Url) -> Result<Option<SocketAddr>> {
+        let host = match url.host_str() {
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+            None => bail!("URL has no host"),
+        };
+        if self.allowed_hosts.iter().any(|allowed| allowed == host) {
+            return Ok(None);
+        }
+        if let Ok(address) = host.parse() {
+            if !is_public(&address) {
+                bail!("Host {host} is internal");
+            }
+            return Ok(None);
+        }
+        if is_internal_name(host) {
+            bail!("Host {host} is internal");
+        }
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+        if addresses.iter().any(|address| !is_public(&address.ip())) {
+            bail!("Host {host} is internal");
+        }
+        match addresses.first() {
+            Some(address) => Ok(Some(*address)),
+            None => bail!("Host {host} has no address"),
+        }
+    }
+
+    /// Client that connects to the address the URL was checked with, so that a name can't
+    /// resolve to an internal address in between
+    async fn client_for(&self, url: &reqwest::Url) -> Result<reqwest::Client> {
+        match (url.host_str(), self.resolve(url).await?) {
+            (Some(host), Some(address)) => Ok(client_builder().resolve(host, address).build()?),
+            _ => Ok(self.client.clone()),
+        }
+    }
+
+    /// Deliver notification to a channel. Webhooks are signed with the secret of the channel.
+    pub async fn deliver(
+        &self,
+        channel: &Channel,
+        secret: Option<&[u8]>,
+        notification: &Notification,
+    ) -> Delivery {
+        let mut attempts = 0;
+        let mut delay = self.retry_delay;
+        let error = loop {
+            attempts += 1;
+            let e = match self.send(&channel.target, secret, notification).await {
+                Ok(()) => break None,
+                Err(e) => e,
+            };
+            tracing::debug!(error = %e, channel = %channel.id, attempts, "Failed to deliver notification");
+            if attempts == MAX_ATTEMPTS {
+                break Some(e.to_string());
+            }
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        };
+        Delivery {
+            channel: channel.id.clone(),
+            repository: channel.repository.clone(),
+            event: notification.event.id,
+            trigger: notification.trigger,
+            timestamp: Utc::now(),
+            attempts,
+            error,
+        }
+    }
+
+    async fn send(
+        &self,
+        target: &Target,
+        secret: Option<&[u8]>,
+        notification: &Notification,
+    ) -> Result<()> {
+        match target {
+            Target::Webhook { url } => {
+                let client = self.client_for(&reqwest::Url::parse(url)?).await?;
+                let body = serde_json::to_vec(notification)?;
+                let mut request = client
+                    .post(url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .header("X-Recesser-Trigger", notification.trigger.name())
+                    .header("X-Recesser-Delivery", notification.event.id);
+                if let Some(secret) = secret {
+                    request = request.header("X-Recesser-Signature", sign(secret, &body));
+                }
+                check_status(request.body(body).send().await?)
+            }
+            Target::Slack { url } => {
+                let client = self.client_for(&reqwest::Url::parse(url)?).await?;
+                // Mattermost accepts the same payload as Slack
+                let body = json!({ "text": notification.summary });
+                check_status(client.post(url).json(&body).send().await?)
+            }
+            Target::Email { to } => {
+                let config = self
+                    .smtp
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("Email notifications are not configured"))?;
+                smtp::send(config, to, &notification.summary, &email_body(notification)).await
+            }
+        }
+    }
+}
+
+/// Redirects aren't followed because their targets would escape the check of the URL
+fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+}
+
+fn is_internal_name(host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    // Names without a dot are completed with the search domains of the cluster
+    !host.contains('.')
+        || host == "localhost"
+        || [".localhost", ".svc", ".cluster.local", ".internal"]
+            .iter()
+            .any(|suffix| host.ends_with(suffix))
+}
+
+fn is_public(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => is_public_v4(address),
+        IpAddr::V6(address) => match address.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(&mapped),
+            None => is_public_v6(address),
+        },
+    }
+}
+
+fn is_public_v4(address: &Ipv4Addr) -> bool {
+    let [first, second, ..] = address.octets();
+    !(address.is_loopback()
+        || address.is_private()
+        || address.is_link_local()
+        || address.is_unspecified()
+        || address.is_broadcast()
+        // Shared address space of carrier-grade NAT
+        || (first == 100 && (64..128).contains(&second)))
+}
+
+fn is_public_v6(address: &Ipv6Addr) -> bool {
+    let first = address.segments()[0];
+    !(address.is_loopback()
+        || address.is_unspecified()
+        // Unique local addresses
+        || (first & 0xfe00) == 0xfc00
+        // Link-local addresses
+        || (first & 0xffc0) == 0xfe80)
+}
+
+/// Signature of a webhook payload in the format `sha256=<hex encoded HMAC>`
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let tag = hmac::sign(&key, body);
+    format!("sha256={}", hex::encode(tag.as_ref()))
+}
+
+/// Deliver notifications for all published events until the event streams are closed
+pub async fn dispatch(app_state: web::Data<AppState>) {
+    let mut messages = Box::pin(app_state.events.subscribe(None));
+    while let Some(message) = messages.next().await {
+        let notification = match message {
+            Message::Event(event) => match Notification::from_event(&event) {
+                Some(notification) => notification,
+                None => continue,
+            },
+            Message::KeepAlive => continue,
+        };
+        let channels = match app_state
+            .database
+            .notifications
+            .channels(&notification.repository)
+            .await
+        {
+            Ok(channels) => channels,
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to retrieve notification channels");
+                continue;
+            }
+        };
+        for channel in channels {
+            if channel.triggers.contains(&notification.trigger) {
+                let app_state = app_state.clone();
+                let notification = notification.clone();
+                // Retries of one channel must not hold up the others
+                actix_web::rt::spawn(async move {
+                    deliver_and_record(&app_state, &channel, &notification).await
+                });
+            }
+        }
+    }
+}
+
+async fn deliver_and_record(app_state: &AppState, channel: &Channel, notification: &Notification) {
+    let secret = match &channel.target {
+        Target::Webhook { .. } => match app_state.secstore.get_webhook_secret(&channel.id).await {
+            Ok(secret) => Some(secret),
+            Err(e) => {
+                tracing::error!(error = %e, channel = %channel.id, "Failed to retrieve webhook secret");
+                return;
+            }
+        },
+        _ => None,
+    };
+    let delivery = app_state
+        .notifier
+        .deliver(channel, secret.as_deref(), notification)
+        .await;
+    match &delivery.error {
+        Some(error) => tracing::warn!(
+            channel = %channel.id,
+            attempts = delivery.attempts,
+            %error,
+            "Failed to deliver notification"
+        ),
+        None => tracing::debug!(channel = %channel.id, "Delivered notification"),
+    }
+    if let Err(e) = app_state
+        .database
+        .notifications
+        .record_delivery(&delivery)
+        .await
+    {
+        tracing::error!(error = %e, "Failed to record delivery");
+    }
+}
+
+fn check_status(resp: reqwest::Response) -> Result<()> {
+    if !resp.status().is_success() {
+        anyhow::bail!("Endpoint responded with status {}", resp.status());
+    }
+    Ok(())
+}
+
+fn email_body(notification: &Notification) -> String {
+    format!(
+        "{}\n\nRepository: {}\nEvent: {} at {}\n",
+        notification.summary,
+        notification.repository,
+        notification.event.id,
+        notification.event.timestamp.to_rfc2822(),
+    )
+}
@@ -0,0 +1,74 @@
+//! Notification emails sent over SMTP
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::settings::{Redacted, Settings};
+
+/// Time after which an SMTP session is aborted
+const SESSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+pub struct SmtpConfig {
+    /// Address of the server as `host:port`
+    pub addr: String,
+    pub from: String,
+    pub username: Option<String>,
+    pub password: Option<Redacted>,
+    /// Upgrade the connection with STARTTLS before authenticating
+    pub starttls: bool,
+}
+
+impl SmtpConfig {
+    /// Email notifications are enabled if the address of an SMTP server and a sender are set
+    pub fn from_settings(s: &Settings) -> Option<Self> {
+        Some(Self {
+            addr: s.smtp_addr.clone()?,
+            from: s.smtp_from.clone()?,
+            username: s.smtp_username.clone(),
+            password: s.smtp_password.clone(),
+            starttls: s.smtp_starttls,
+        })
+    }
+
+    fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let (host, port) = self
+            .addr
+            .rsplit_once(':')
+            .context("SMTP address is not in the format host:port")?;
+        let mut builder = if self.starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        };
+        builder = builder
+            .port(port.parse().context("Invalid SMTP port")?)
+            .timeout(Some(SESSION_TIMEOUT));
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                password.expose().to_owned(),
+            ));
+        }
+        Ok(builder.build())
+    }
+}
+
+pub async fn send(config: &SmtpConfig, to: &[String], subject: &str, body: &str) -> Result<()> {
+    let mut message = Message::builder()
+        .from(config.from.parse::<Mailbox>()?)
+        // Summaries can span several lines but the subject is shown as one
+        .subject(subject.replace(['\r', '\n'], " "))
+        .header(ContentType::TEXT_PLAIN);
+    for recipient in to {
+        message = message.to(recipient.parse::<Mailbox>()?);
+    }
+    let message = message.body(body.to_owned())?;
+    config.transport()?.send(message).await?;
+    Ok(())
+}
@@ -7,6 +7,8 @@ use s3::region::Region;
 use s3::{Bucket, BucketConfiguration};
 use tokio::fs;
 
+use crate::metrics::{count_error, OBJECTSTORAGE_ERRORS};
+
 const BUCKET_NAME: &str = "artifacts";
 
 #[derive(Clone)]
@@ -59,10 +61,11 @@ impl ObjectStorage {
         file_path: impl AsRef<Path>,
     ) -> Result<()> {
         let mut file = fs::File::open(file_path).await?;
-        let _code = self
+        let result = self
             .bucket
             .put_object_stream(&mut file, content_address)
-            .await?;
+            .await;
+        count_error(&OBJECTSTORAGE_ERRORS, "upload", result)?;
         Ok(())
     }
 
@@ -72,10 +75,11 @@ impl ObjectStorage {
         filepath: &Path,
     ) -> Result<()> {
         let mut file = fs::File::create(&filepath).await?;
-        let _code = self
+        let result = self
             .bucket
             .get_object_stream(content_address, &mut file)
-            .await?;
+            .await;
+        count_error(&OBJECTSTORAGE_ERRORS, "download", result)?;
         Ok(())
     }
 
@@ -86,12 +90,29 @@ impl ObjectStorage {
     // }
 
     pub async fn exists(&self, content_address: impl AsRef<str>) -> Result<bool> {
-        let (_, code) = self.bucket.head_object(content_address).await?;
+        let result = self.bucket.head_object(content_address).await;
+        let (_, code) = count_error(&OBJECTSTORAGE_ERRORS, "head", result)?;
         Ok(!matches!(code, 404))
     }
 
+    /// Size of the stored (encrypted) object
+    pub async fn size(&self, content_address: impl AsRef<str>) -> Result<u64> {
+        let result = self.bucket.head_object(content_address).await;
+        let (head, _code) = count_error(&OBJECTSTORAGE_ERRORS, "head", result)?;
+        Ok(head.content_length.unwrap_or(0).try_into()?)
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        let (_, code) = self.bucket.location().await?;
+        if code != 200 {
+            anyhow::bail!("Object storage responded with status {code}");
+        }
+        Ok(())
+    }
+
     pub async fn delete(&self, content_address: &str) -> Result<()> {
-        let (_, _code) = self.bucket.delete_object(content_address).await?;
+        let result = self.bucket.delete_object(content_address).await;
+        count_error(&OBJECTSTORAGE_ERRORS, "delete", result)?;
         Ok(())
     }
 }
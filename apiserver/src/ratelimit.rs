@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::{web, Error, HttpMessage};
+use futures_util::future::{self, Either};
+use recesser_core::user::Scope;
+
+use crate::auth::Token;
+use crate::error::UserError;
+use crate::settings::Settings;
+use crate::AppState;
+
+/// Time after which clients should retry a transfer that was rejected because too many transfers
+/// were in progress
+const TRANSFER_RETRY_AFTER: u64 = 5;
+
+/// Time in which an empty bucket refills completely, whatever the limit of its scope
+const REFILL_PERIOD: Duration = Duration::from_secs(60);
+
+/// Maximum number of requests per minute for each token of a scope. No limit is applied if unset.
+#[derive(Clone, Debug)]
+pub struct RateLimits {
+    pub user: Option<u32>,
+    pub machine: Option<u32>,
+    pub admin: Option<u32>,
+}
+
+/// Token bucket rate limiter keyed by user. Each bucket holds as many requests as the limit of its
+/// scope allows per minute and refills continuously.
+pub struct RateLimiter {
+    limits: RwLock<RateLimits>,
+    buckets: Mutex<Buckets>,
+}
+
+/// Buckets of users that sent a request recently. Buckets that are full again behave like new
+/// ones, so they are dropped at most once per refill period.
+struct Buckets {
+    entries: HashMap<String, Bucket>,
+    last_eviction: Instant,
+}
+
+struct Bucket {
+    available: f64,
+    last_refill: Instant,
+}
+
+impl RateLimits {
+    pub fn from_settings(s: &Settings) -> Self {
+        Self {
+            user: s.rate_limit_user,
+            machine: s.rate_limit_machine,
+            admin: s.rate_limit_admin,
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits: RwLock::new(limits),
+            buckets: Mutex::new(Buckets {
+                entries: HashMap::new(),
+                last_eviction: Instant::now(),
+            }),
+        }
+    }
+
+    /// Replace the limits. Buckets keep their available requests up to the new limit.
+    pub fn set_limits(&self, limits: RateLimits) {
+        *self.limits.write().unwrap() = limits;
+    }
+
+    /// Take a request from the bucket of the user. Returns the time until the next request is
+    /// available if the bucket is empty.
+    pub fn check(&self, user_id: &str, scope: &Scope) -> Result<(), Duration> {
+        self.check_at(user_id, scope, Instant::now())
+    }
+
+    /// Same as [`RateLimiter::check`] at a given point in time
+    pub fn check_at(&self, user_id: &str, scope: &Scope, now: Instant) -> Result<(), Duration> {
+        let limit = {
+            let limits = self.limits.read().unwrap();
+            match scope {
+                Scope::User => limits.user,
+                Scope::Machine => limits.machine,
+                Scope::Admin => limits.admin,
+            }
+        };
+        let capacity = match limit {
+            Some(limit) => f64::from(limit),
+            None => return Ok(()),
+        };
+        let refill_per_second = capacity / REFILL_PERIOD.as_secs_f64();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if now.saturating_duration_since(buckets.last_eviction) >= REFILL_PERIOD {
+            buckets
+                .entries
+                .retain(|_, b| now.saturating_duration_since(b.last_refill) < REFILL_PERIOD);
+            buckets.last_eviction = now;
+        }
+        let bucket = buckets
+            .entries
+            .entry(String::from(user_id))
+            .or_insert_with(|| Bucket {
+                available: capacity,
+                last_refill: now,
+            });
+
+        let elapsed = now
+            .saturating_duration_since(bucket.last_refill)
+            .as_secs_f64();
+        bucket.available = (bucket.available + elapsed * refill_per_second).min(capacity);
+        bucket.last_refill = now;
+
+        if bucket.available < 1.0 {
+            let wait = (1.0 - bucket.available) / refill_per_second;
+            return Err(Duration::from_secs_f64(wait));
+        }
+        bucket.available -= 1.0;
+        Ok(())
+    }
+
+    /// Number of users that currently have a bucket
+    pub fn tracked_users(&self) -> usize {
+        self.buckets.lock().unwrap().entries.len()
+    }
+}
+
+/// Reject requests of users that exceeded the rate limit of their scope. Needs to be wrapped by
+/// the authentication middleware.
+pub fn limit<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    match check_rate_limit(&req) {
+        Ok(()) => Either::Left(srv.call(req)),
+        Err(e) => Either::Right(future::ok(req.error_response(e))),
+    }
+}
+
+fn check_rate_limit(req: &ServiceRequest) -> Result<(), UserError> {
+    let app_state = req
+        .app_data::<web::Data<AppState>>()
+        .ok_or(UserError::Internal)?;
+    let ext = req.extensions();
+    let user = ext
+        .get::<Token>()
+        .ok_or(UserError::Internal)?
+        .extract_user();
+    app_state
+        .rate_limiter
+        .check(&user.id, &user.scope)
+        .map_err(|wait| {
+            tracing::debug!(user_id = %user.id, "Rate limit exceeded");
+            UserError::TooManyRequests {
+                // Round up so that the client doesn't retry too early
+                retry_after: wait.as_secs() + 1,
+            }
+        })
+}
+
+/// Limits the number of uploads and downloads each user can run concurrently
+pub struct TransferLimiter {
+    max: RwLock<Option<usize>>,
+    active: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+/// Held for the duration of a transfer. Frees the slot of the user when dropped.
+pub struct TransferPermit {
+    user_id: String,
+    active: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl TransferLimiter {
+    pub fn new(max: Option<usize>) -> Self {
+        Self {
+            max: RwLock::new(max),
+            active: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Replace the limit. Transfers that are already running are not affected.
+    pub fn set_max(&self, max: Option<usize>) {
+        *self.max.write().unwrap() = max;
+    }
+
+    pub fn acquire(&self, req: &impl HttpMessage) -> Result<Option<TransferPermit>, UserError> {
+        let max = match *self.max.read().unwrap() {
+            Some(max) => max,
+            None => return Ok(None),
+        };
+        let user_id = {
+            let ext = req.extensions();
+            String::from(ext.get::<Token>().ok_or(UserError::Internal)?.user_id())
+        };
+
+        let mut active = self.active.lock().unwrap();
+        let count = active.entry(user_id.clone()).or_insert(0);
+        if *count >= max {
+            tracing::debug!(%user_id, "Too many concurrent transfers");
+            return Err(UserError::TooManyRequests {
+                retry_after: TRANSFER_RETRY_AFTER,
+            });
+        }
+        *count += 1;
+
+        Ok(Some(TransferPermit {
+            user_id,
+            active: Arc::clone(&self.active),
+        }))
+    }
+}
+
+/// Response body that holds the permit of a download until it's completely sent or the client
+/// disconnected. Handlers return before the body is streamed, so a permit that only lives as long
+/// as the handler wouldn't limit anything.
+pub struct PermittedBody<B> {
+    body: B,
+    permit: Option<TransferPermit>,
+}
+
+impl<B> PermittedBody<B> {
+    pub fn new(body: B, permit: Option<TransferPermit>) -> Self {
+        Self { body, permit }
+    }
+}
+
+impl<B: MessageBody + Unpin> MessageBody for PermittedBody<B> {
+    type Error = B::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<web::Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.body).poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            this.permit = None;
+        }
+        poll
+    }
+}
+
+impl Drop for TransferPermit {
+    fn drop(&mut self) {
+        let mut active = self.active.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.user_id) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.user_id);
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Detect and repair drift between the repositories in the database and their private keys in
+//! secret storage and the argo namespace
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::Duration;
+
+use actix_web::web;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use recesser_core::reconciliation::{Drift, DriftKind, ReconciliationReport, Resolution};
+
+use crate::kubernetes;
+use crate::metrics::RECONCILIATION_DRIFT;
+use crate::AppState;
+
+/// Orphans younger than this may belong to a registration that is still in progress
+const GRACE_PERIOD_MINUTES: i64 = 10;
+
+/// Compare repositories, SSH keys and Kubernetes secrets. Drift is only repaired if requested.
+pub async fn reconcile(app_state: &AppState, repair: bool) -> Result<ReconciliationReport> {
+    // Concurrent runs would try to repair the same drift
+    let _guard = app_state.reconciliation_lock.lock().await;
+
+    let repositories = app_state.database.repositories.list().await?;
+    let keys: HashSet<String> = app_state
+        .secstore
+        .list_ssh_keys()
+        .await?
+        .into_iter()
+        .collect();
+    let secrets = app_state.k8s_apiserver.list_ssh_secrets().await?;
+    let secret_names: HashSet<&str> = secrets.iter().map(|s| s.name.as_str()).collect();
+
+    let mut report = ReconciliationReport::default();
+    let mut expected_keys = HashSet::new();
+    let mut expected_secrets = HashSet::new();
+
+    for repository in &repositories {
+        // Only repositories that are cloned via SSH have a private key
+        let fingerprint = match &repository.public_key {
+            Some(public_key) => &public_key.fingerprint,
+            None => continue,
+        };
+        let secret_name = kubernetes::ssh_secret_name(fingerprint)?;
+        expected_keys.insert(fingerprint.to_string());
+        expected_secrets.insert(secret_name.clone());
+        // Staged and retired keys are only checked for being known. A missing staged key is
+        // fixed by staging again and retired keys are on their way out anyway.
+        for other_key in repository.staged_key.iter().chain(&repository.retired_keys) {
+            expected_keys.insert(other_key.fingerprint.to_string());
+            expected_secrets.insert(kubernetes::ssh_secret_name(&other_key.fingerprint)?);
+        }
+
+        if !keys.contains(fingerprint.as_str()) {
+            // Without the private key neither the key nor the secret can be restored
+            report.drift.push(Drift {
+                kind: DriftKind::MissingSshKey,
+                subject: repository.name.clone(),
+                resolution: Resolution::Unrepairable,
+                error: None,
+            });
+            continue;
+        }
+        if !secret_names.contains(secret_name.as_str()) {
+            let restore = async {
+                let private_key = app_state.secstore.get_ssh_key(fingerprint.as_str()).await?;
+                app_state
+                    .k8s_apiserver
+                    .apply_ssh_secret(&repository.name, fingerprint, &private_key)
+                    .await
+            };
+            let drift = resolve(
+                DriftKind::MissingKubernetesSecret,
+                &repository.name,
+                repair,
+                restore,
+            )
+            .await;
+            report.drift.push(drift);
+        }
+    }
+
+    for fingerprint in keys.difference(&expected_keys) {
+        let created = app_state.secstore.ssh_key_created(fingerprint).await;
+        if !past_grace_period(created.ok()) {
+            continue;
+        }
+        let delete = app_state.secstore.delete_ssh_key(fingerprint);
+        let drift = resolve(DriftKind::OrphanedSshKey, fingerprint, repair, delete).await;
+        report.drift.push(drift);
+    }
+
+    for secret in &secrets {
+        // Secrets that weren't created by the apiserver are left alone
+        if !secret.managed
+            || expected_secrets.contains(&secret.name)
+            || !past_grace_period(secret.created)
+        {
+            continue;
+        }
+        let delete = app_state.k8s_apiserver.delete_argo_secret(&secret.name);
+        let drift = resolve(
+            DriftKind::OrphanedKubernetesSecret,
+            &secret.name,
+            repair,
+            delete,
+        )
+        .await;
+        report.drift.push(drift);
+    }
+
+    for kind in DriftKind::ALL {
+        let count = report.drift.iter().filter(|d| d.kind == kind).count();
+        RECONCILIATION_DRIFT
+            .with_label_values(&[kind.name()])
+            .set(count as i64);
+    }
+    Ok(report)
+}
+
+/// Reconcile in a fixed interval until the server shuts down
+pub async fn run_periodically(app_state: web::Data<AppState>, interval: Duration, repair: bool) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        match reconcile(&app_state, repair).await {
+            Ok(report) => {
+                for drift in &report.drift {
+                    tracing::warn!(
+                        kind = drift.kind.name(),
+                        subject = %drift.subject,
+                        resolution = ?drift.resolution,
+                        error = ?drift.error,
+                        "Detected drift"
+                    );
+                }
+                tracing::info!(drift = report.drift.len(), "Reconciled repositories");
+            }
+            Err(e) => tracing::error!(error = %e, "Failed to reconcile repositories"),
+        }
+    }
+}
+
+async fn resolve(
+    kind: DriftKind,
+    subject: &str,
+    repair: bool,
+    action: impl Future<Output = Result<()>>,
+) -> Drift {
+    let (resolution, error) = match repair {
+        false => (Resolution::Reported, None),
+        true => match action.await {
+            Ok(()) => (Resolution::Repaired, None),
+            Err(e) => (Resolution::Failed, Some(e.to_string())),
+        },
+    };
+    Drift {
+        kind,
+        subject: subject.to_string(),
+        resolution,
+        error,
+    }
+}
+
+/// Objects with an unknown creation time are treated as young
+fn past_grace_period(created: Option<DateTime<Utc>>) -> bool {
+    match created {
+        Some(created) => Utc::now() - created > chrono::Duration::minutes(GRACE_PERIOD_MINUTES),
+        None => false,
+    }
+}
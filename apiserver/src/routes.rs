@@ -1,26 +1,91 @@
 mod artifact;
+mod audit;
+mod deploy_key;
+mod event;
+mod health;
+mod init;
+mod metrics;
+mod notification;
+pub mod openapi;
+mod reconciliation;
 mod repository;
+mod run;
+mod usage;
 mod user;
+mod webhook;
 
-use actix_web::dev::Service;
-use actix_web::web;
+use std::future::Future;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::{web, Error};
+use futures_util::future::{self, Either};
 use recesser_core::user::Scope;
 
 use crate::auth::middleware::validate_scope;
 
+/// Prefix of the current version of the API
+pub const API_PREFIX: &str = "/v1";
+
+/// Operational routes outside of the versioned API that are accessible without an access token
+pub fn public_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(metrics::export)
+        .service(health::healthz)
+        .service(health::readyz);
+}
+
+/// Routes of the versioned API that are accessible without an access token
+pub fn public_api_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(init::init)
+        .service(openapi::spec)
+        .service(webhook::receive);
+}
+
+/// Routes of the versioned API that require an access token
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(web::scope("/artifacts").configure(artifact::config));
-    cfg.service(web::scope("/repositories").configure(repository::config));
+    cfg.service(
+        web::scope("/repositories")
+            .configure(repository::config)
+            .configure(deploy_key::config)
+            .configure(webhook::config)
+            .configure(notification::config)
+            .configure(run::repository_config),
+    );
+    cfg.service(web::scope("/runs").configure(run::config));
+    cfg.service(web::scope("/events").configure(event::config));
     cfg.service(
         web::scope("/users")
             .configure(user::config)
-            .wrap_fn(|req, srv| {
-                let result = validate_scope(&req, Scope::Admin);
-                let fut = srv.call(req);
-                async {
-                    result?;
-                    fut.await
-                }
-            }),
+            .wrap_fn(admin_only),
     );
+    cfg.service(
+        web::scope("/audit")
+            .configure(audit::config)
+            .wrap_fn(admin_only),
+    );
+    cfg.service(
+        web::scope("/reconciliation")
+            .configure(reconciliation::config)
+            .wrap_fn(admin_only),
+    );
+    cfg.service(
+        web::scope("/usage")
+            .configure(usage::config)
+            .wrap_fn(admin_only),
+    );
+}
+
+/// Reject requests without Admin scope. The rejection is returned as a response rather than an
+/// error so that outer middleware still has access to the request.
+fn admin_only<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    match validate_scope(&req, Scope::Admin) {
+        Ok(()) => Either::Left(srv.call(req)),
+        Err(e) => Either::Right(future::ok(req.error_response(e))),
+    }
 }
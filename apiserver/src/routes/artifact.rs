@@ -1,7 +1,7 @@
-mod delete;
-mod download;
-mod list;
-mod upload;
+pub(super) mod delete;
+pub(super) mod download;
+pub(super) mod list;
+pub(super) mod upload;
 
 use actix_web::web;
 
@@ -1,10 +1,24 @@
 use actix_web::{delete, web, Error, HttpResponse};
+use recesser_core::event::EventKind;
 
 use crate::database::DocumentNotFoundError;
 use crate::error::UserError;
 use crate::AppState;
 
-#[delete("/{handle}")]
+/// Delete an artifact. The object is deleted as well once no artifact references it anymore.
+#[utoipa::path(
+    delete,
+    path = "/v1/artifacts/{handle}",
+    operation_id = "delete_artifact",
+    tag = "Artifacts",
+    params(("handle" = String, Path, description = "Handle of the artifact")),
+    responses(
+        (status = 202, description = "Artifact is deleted"),
+        (status = 404, description = "Artifact doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[delete("/{handle}", name = "artifact.delete")]
 async fn delete(
     handle: web::Path<String>,
     app_state: web::Data<AppState>,
@@ -16,16 +30,20 @@ async fn delete(
     let object_handle = metadata_store
         .retrieve(&handle)
         .await
-        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/artifacts/{handle}")))?
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/artifacts/{handle}")))?
         .object_handle
         .to_string();
 
-    metadata_store
+    let project = metadata_store
         .delete(&handle)
         .await
         .map_err(UserError::internal)?;
 
     tracing::debug!(%handle, "Deleted artifact");
+    let kind = EventKind::ArtifactDeleted {
+        handle: handle.clone(),
+    };
+    app_state.events.publish(project, kind).await;
 
     let in_use = metadata_store
         .search_object_handle(&object_handle)
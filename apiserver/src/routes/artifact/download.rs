@@ -1,20 +1,37 @@
 use std::path::PathBuf;
 
 use actix_files::NamedFile;
-use actix_web::{get, web, Error};
+use actix_web::body::BoxBody;
+use actix_web::{get, web, Error, HttpRequest, HttpResponse, Responder};
 use anyhow::Result;
 use recesser_core::metadata::Metadata;
 
 use crate::database::DocumentNotFoundError;
 use crate::encryption::decrypt_file;
 use crate::error::UserError;
+use crate::metrics;
+use crate::ratelimit::PermittedBody;
 use crate::AppState;
 
+#[utoipa::path(
+    get,
+    path = "/v1/artifacts/{handle}/file",
+    operation_id = "download_artifact_file",
+    tag = "Artifacts",
+    params(("handle" = String, Path, description = "Handle of the artifact")),
+    responses(
+        (status = 200, description = "Decrypted file", content_type = "application/octet-stream", body = Vec<u8>),
+        (status = 404, description = "Artifact doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
 #[get("/{handle}/file")]
 async fn download_file(
+    req: HttpRequest,
     handle: web::Path<String>,
     app_state: web::Data<AppState>,
-) -> Result<NamedFile, Error> {
+) -> Result<HttpResponse<PermittedBody<BoxBody>>, Error> {
+    let permit = app_state.transfer_limiter.acquire(&req)?;
     let handle = handle.into_inner();
 
     let metadata_store = &app_state.database.metadata;
@@ -22,7 +39,7 @@ async fn download_file(
     let metadata = metadata_store
         .retrieve(&handle)
         .await
-        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/artifacts/{handle}")))?;
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/artifacts/{handle}")))?;
 
     let file = tempfile::NamedTempFile::new()?;
     let file_path = file.into_temp_path();
@@ -39,7 +56,11 @@ async fn download_file(
         .await
         .map_err(UserError::internal)?;
 
-    Ok(NamedFile::open_async(&file_path).await?)
+    let file = NamedFile::open_async(&file_path).await?;
+    metrics::BYTES_DOWNLOADED.inc_by(file.metadata()?.len());
+    // The file stays readable after the temporary path is removed because it's already open
+    let response = file.respond_to(&req);
+    Ok(response.map_body(|_, body| PermittedBody::new(body, permit)))
 }
 
 async fn get_key_and_decrypt_file(
@@ -52,6 +73,18 @@ async fn get_key_and_decrypt_file(
     Ok(())
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/artifacts/{handle}/metadata",
+    operation_id = "download_artifact_metadata",
+    tag = "Artifacts",
+    params(("handle" = String, Path, description = "Handle of the artifact")),
+    responses(
+        (status = 200, description = "Metadata of the artifact", body = Metadata),
+        (status = 404, description = "Artifact doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
 #[get("/{handle}/metadata")]
 async fn download_metadata(
     handle: web::Path<String>,
@@ -64,7 +97,7 @@ async fn download_metadata(
     let metadata = metadata_store
         .retrieve(&handle)
         .await
-        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/artifacts/{handle}")))?;
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/artifacts/{handle}")))?;
 
     Ok(web::Json(metadata))
 }
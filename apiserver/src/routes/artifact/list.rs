@@ -3,6 +3,14 @@ use actix_web::{get, web, Error};
 use crate::error::UserError;
 use crate::AppState;
 
+#[utoipa::path(
+    get,
+    path = "/v1/artifacts",
+    operation_id = "list_artifacts",
+    tag = "Artifacts",
+    responses((status = 200, description = "Handles of all artifacts", body = [String])),
+    security(("token" = []))
+)]
 #[get("")]
 async fn list(app_state: web::Data<AppState>) -> Result<web::Json<Vec<String>>, Error> {
     let metadata_store = &app_state.database.metadata;
@@ -2,27 +2,65 @@ use std::path::PathBuf;
 use std::str::FromStr;
 
 use actix_multipart::{Field, Multipart};
-use actix_web::{put, web, Error, HttpResponse};
+use actix_web::{put, web, Error, HttpMessage, HttpRequest, HttpResponse};
 use anyhow::Result;
 use futures_util::TryStreamExt;
+use recesser_core::event::EventKind;
 use recesser_core::handle::Handle;
 use recesser_core::metadata::Metadata;
+use recesser_core::user::Scope;
+use tempfile::TempPath;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 
+use crate::audit;
+use crate::auth::Token;
+use crate::database::{Attribution, UsageField};
 use crate::encryption::{encrypt_file, generate_random_key};
 use crate::error::UserError;
+use crate::metrics;
 use crate::AppState;
 
-#[put("")]
+const FIELD_AFTER_FILE: &str = "Field needs to be sent before the file";
+
+/// Upload an artifact. The file is only stored if no other artifact references the same object.
+#[utoipa::path(
+    put,
+    path = "/v1/artifacts",
+    operation_id = "upload_artifact",
+    tag = "Artifacts",
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Artifact is stored"),
+        (status = 400, description = "Form is invalid, its fields are out of order or the file doesn't match its handle", body = ErrorResponse),
+        (status = 413, description = "Storage quota is exceeded", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[put("", name = "artifact.upload")]
 async fn upload(
+    req: HttpRequest,
     mut payload: Multipart,
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
+    let _permit = app_state.transfer_limiter.acquire(&req)?;
     let metadata_store = &app_state.database.metadata;
+    let (owner, owner_scope) = {
+        let ext = req.extensions();
+        let user = ext
+            .get::<Token>()
+            .ok_or(UserError::Internal)?
+            .extract_user();
+        (user.id, user.scope)
+    };
 
     let mut handle: Option<Handle> = None;
     let mut metadata: Option<Metadata> = None;
+    let mut project: Option<String> = None;
+    let mut repository: Option<String> = None;
+    // The file is processed while it's streamed, so the fields it's accounted with need to come
+    // first. Later ones would silently be ignored.
+    let mut file_received = false;
 
     while let Some(mut field) = payload.try_next().await? {
         let content_disposition = field.content_disposition();
@@ -34,38 +72,97 @@ async fn upload(
             "handle" => {
                 handle = extract_handle(&mut field)
                     .await
-                    .map_err(UserError::bad_request)?;
+                    .map_err(|e| UserError::invalid_field("handle", e))?;
             }
             "metadata" => {
-                let handle = handle.as_ref().ok_or(UserError::BadRequest)?;
+                let handle = handle
+                    .as_ref()
+                    .ok_or(UserError::MissingField { field: "handle" })?;
                 tracing::debug!(%handle);
+                audit::set_target(&req, handle.to_string());
 
                 metadata = extract_metadata(&mut field)
                     .await
-                    .map_err(UserError::bad_request)?;
+                    .map_err(|e| UserError::invalid_field("metadata", e))?;
+            }
+            "project" => {
+                if file_received {
+                    return Err(UserError::invalid_field("project", FIELD_AFTER_FILE).into());
+                }
+                project = extract_name(&mut field)
+                    .await
+                    .map_err(|e| UserError::invalid_field("project", e))?;
+            }
+            "repository" => {
+                if file_received {
+                    return Err(UserError::invalid_field("repository", FIELD_AFTER_FILE).into());
+                }
+                repository = extract_name(&mut field)
+                    .await
+                    .map_err(|e| UserError::invalid_field("repository", e))?;
             }
             "file" => {
-                let handle = handle.as_ref().ok_or(UserError::BadRequest)?;
-                let metadata = metadata.as_ref().ok_or(UserError::BadRequest)?;
+                file_received = true;
+                let handle = handle
+                    .as_ref()
+                    .ok_or(UserError::MissingField { field: "handle" })?;
+                let metadata = metadata
+                    .as_ref()
+                    .ok_or(UserError::MissingField { field: "metadata" })?;
                 tracing::debug!(?metadata);
 
+                let object_handle = metadata.object_handle.to_string();
+                let quota_check = QuotaCheck {
+                    app_state: &app_state,
+                    object_handle: &object_handle,
+                    owner: match owner_scope {
+                        Scope::User => Some(&owner),
+                        _ => None,
+                    },
+                    project: project.as_deref(),
+                };
+
                 let file_exists = app_state
                     .objstore
-                    .exists(&metadata.object_handle.to_string())
+                    .exists(&object_handle)
                     .await
                     .map_err(UserError::internal)?;
 
-                if file_exists {
+                let size = if file_exists {
                     tracing::debug!("File already exist in object storage. Skipping upload.");
+                    let size = stored_object_size(&object_handle, &app_state)
+                        .await
+                        .map_err(UserError::internal)?;
+                    quota_check.run(size).await?;
+                    size
                 } else {
                     tracing::debug!("File doesn't exist in object storage. Uploading it.");
-                    extract_encrypt_and_upload_file(&mut field, metadata, &app_state).await?
-                }
+                    let file_path = extract_and_verify_file(&mut field, metadata).await?;
+                    let size = fs::metadata(&file_path)
+                        .await
+                        .map_err(UserError::internal)?
+                        .len();
+                    quota_check.run(size).await?;
+                    encrypt_and_upload_file(file_path, &object_handle, &app_state).await?;
+                    metrics::BYTES_UPLOADED.inc_by(size);
+                    size
+                };
 
+                let attribution = Attribution {
+                    size,
+                    owner: &owner,
+                    project: project.as_deref(),
+                };
                 metadata_store
-                    .insert(&handle.to_string(), metadata)
+                    .insert(&handle.to_string(), metadata, attribution)
                     .await
                     .map_err(UserError::internal)?;
+
+                let kind = EventKind::ArtifactUploaded {
+                    handle: handle.to_string(),
+                    repository: repository.clone(),
+                };
+                app_state.events.publish(project.clone(), kind).await;
             }
             _ => tracing::debug!(name = field_name, "Unknown field"),
         }
@@ -73,6 +170,20 @@ async fn upload(
     Ok(HttpResponse::Ok().into())
 }
 
+/// Fields of the multipart form of an upload in the order they need to be sent
+#[derive(utoipa::ToSchema)]
+#[allow(dead_code)]
+pub struct UploadForm {
+    handle: String,
+    metadata: Metadata,
+    /// Project the artifact is accounted to
+    project: Option<String>,
+    /// Repository whose workflow produced the artifact
+    repository: Option<String>,
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
 async fn extract_handle(field: &mut Field) -> Result<Option<Handle>> {
     let buf = field.try_collect::<Vec<web::Bytes>>().await?.concat();
     let handle = Handle::from_str(&String::from_utf8(buf)?)?;
@@ -84,11 +195,19 @@ async fn extract_metadata(field: &mut Field) -> Result<Option<Metadata>> {
     Ok(Some(serde_json::from_slice(&buf)?))
 }
 
-async fn extract_encrypt_and_upload_file(
+async fn extract_name(field: &mut Field) -> Result<Option<String>> {
+    let buf = field.try_collect::<Vec<web::Bytes>>().await?.concat();
+    let name = String::from_utf8(buf)?;
+    if name.is_empty() {
+        anyhow::bail!("Name is empty");
+    }
+    Ok(Some(name))
+}
+
+async fn extract_and_verify_file(
     field: &mut Field,
     metadata: &Metadata,
-    app_state: &web::Data<AppState>,
-) -> std::result::Result<(), UserError> {
+) -> std::result::Result<TempPath, UserError> {
     let file = tempfile::NamedTempFile::new().map_err(UserError::internal)?;
     let file_path = file.into_temp_path();
 
@@ -99,23 +218,96 @@ async fn extract_encrypt_and_upload_file(
         .verify(&metadata.object_handle)
         .map_err(UserError::integrity)?;
 
-    encrypt_file_and_store_key(
-        app_state,
-        file_path.to_path_buf(),
-        &metadata.object_handle.to_string(),
-    )
-    .await
-    .map_err(UserError::internal)?;
+    Ok(file_path)
+}
+
+async fn encrypt_and_upload_file(
+    file_path: TempPath,
+    object_handle: &str,
+    app_state: &web::Data<AppState>,
+) -> std::result::Result<(), UserError> {
+    encrypt_file_and_store_key(app_state, file_path.to_path_buf(), object_handle)
+        .await
+        .map_err(UserError::internal)?;
 
     app_state
         .objstore
-        .upload_file(computed_object_handle.to_string(), &file_path)
+        .upload_file(object_handle, &file_path)
         .await
         .map_err(UserError::internal)?;
 
     Ok(())
 }
 
+/// Size of an object that is already stored. Falls back to the size of the encrypted object for
+/// objects that were uploaded before sizes were recorded.
+async fn stored_object_size(object_handle: &str, app_state: &web::Data<AppState>) -> Result<u64> {
+    match app_state
+        .database
+        .metadata
+        .object_size(object_handle)
+        .await?
+    {
+        Some(size) => Ok(size),
+        None => app_state.objstore.size(object_handle).await,
+    }
+}
+
+/// Checks whether storing an object would exceed the quota of the uploading user or the project.
+///
+/// Objects are only counted once for each user and project. Only users with User scope are
+/// subject to quotas, because Machine uploads are accounted to their project.
+struct QuotaCheck<'a> {
+    app_state: &'a web::Data<AppState>,
+    object_handle: &'a str,
+    owner: Option<&'a str>,
+    project: Option<&'a str>,
+}
+
+impl QuotaCheck<'_> {
+    async fn run(&self, size: u64) -> std::result::Result<(), UserError> {
+        let quotas = self.app_state.quotas();
+        let limits = [
+            (UsageField::Owner, self.owner, quotas.user),
+            (UsageField::Project, self.project, quotas.project),
+        ];
+        for (field, name, quota) in limits {
+            if let (Some(name), Some(quota)) = (name, quota) {
+                self.check(field, name, quota, size).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn check(
+        &self,
+        field: UsageField,
+        name: &str,
+        quota: u64,
+        size: u64,
+    ) -> std::result::Result<(), UserError> {
+        let metadata_store = &self.app_state.database.metadata;
+        let already_referenced = metadata_store
+            .references_object(field, name, self.object_handle)
+            .await
+            .map_err(UserError::internal)?;
+        if already_referenced {
+            return Ok(());
+        }
+        let usage = metadata_store
+            .usage_of(field, name)
+            .await
+            .map_err(UserError::internal)?;
+        if usage + size > quota {
+            tracing::info!(name, usage, size, quota, "Rejected upload exceeding quota");
+            return Err(UserError::QuotaExceeded {
+                subject: String::from(name),
+            });
+        }
+        Ok(())
+    }
+}
+
 async fn extract_file(field: &mut Field, file_path: PathBuf) -> Result<Handle> {
     let mut file = fs::File::create(&file_path).await?;
     while let Some(chunk) = field.try_next().await? {
@@ -0,0 +1,32 @@
+use actix_web::{get, web, Error};
+use recesser_core::audit::{AuditQuery, AuditRecord};
+
+use crate::error::UserError;
+use crate::AppState;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(search);
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/audit",
+    operation_id = "search_audit_log",
+    tag = "Administration",
+    params(AuditQuery),
+    responses((status = 200, description = "Matching audit records in ascending order", body = [AuditRecord])),
+    security(("token" = []))
+)]
+#[get("")]
+async fn search(
+    query: web::Query<AuditQuery>,
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<Vec<AuditRecord>>, Error> {
+    let records = app_state
+        .database
+        .audit
+        .search(&query)
+        .await
+        .map_err(UserError::internal)?;
+    Ok(web::Json(records))
+}
@@ -0,0 +1,213 @@
+use actix_web::{delete, post, put, web, Error, HttpRequest, HttpResponse};
+use recesser_core::event::EventKind;
+use recesser_core::repository::{Auth, KeyPair};
+use recesser_core::run::RunQuery;
+use recesser_core::user::Scope;
+
+use super::repository::{extract_name, revoke_key, RepositoryPath};
+use crate::auth::middleware::validate_scope;
+use crate::database::DocumentNotFoundError;
+use crate::error::UserError;
+use crate::AppState;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(stage).service(confirm).service(discard);
+}
+
+/// Stage a new deploy key. The current key stays in use until the schandler confirms that it
+/// can fetch the repository with the new key. A previously staged key is replaced.
+#[utoipa::path(
+    put,
+    path = "/v1/repositories/{organisation}/{repository}/staged-key",
+    operation_id = "stage_deploy_key",
+    tag = "Repositories",
+    params(RepositoryPath),
+    request_body = KeyPair,
+    responses(
+        (status = 200, description = "Key is staged"),
+        (status = 400, description = "Key is already the deploy key or repository isn't cloned via SSH", body = ErrorResponse),
+        (status = 404, description = "Repository doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[put(
+    "/{organisation}/{repository}/staged-key",
+    name = "repository.stage_key"
+)]
+async fn stage(
+    path: web::Path<RepositoryPath>,
+    keypair: web::Json<KeyPair>,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let name = extract_name(path);
+    let keypair = keypair.into_inner();
+    let fingerprint = &keypair.public_key.fingerprint;
+
+    let repository = app_state
+        .database
+        .repositories
+        .show(&name)
+        .await
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}")))?;
+    let current = match (&repository.auth, &repository.public_key) {
+        (Auth::Ssh, Some(public_key)) => &public_key.fingerprint,
+        _ => {
+            let e = "Repository isn't cloned via SSH";
+            return Err(UserError::invalid_field("keypair", e).into());
+        }
+    };
+    if current.as_str() == fingerprint.as_str() {
+        let e = "Key is already the deploy key of the repository";
+        return Err(UserError::invalid_field("keypair", e).into());
+    }
+
+    let staged = async {
+        app_state.secstore.store_ssh_key(&keypair).await?;
+        app_state
+            .k8s_apiserver
+            .apply_ssh_secret(&name, fingerprint, keypair.private_key.as_str())
+            .await?;
+        app_state
+            .database
+            .repositories
+            .stage_key(&name, &keypair.public_key)
+            .await
+    }
+    .await;
+    let replaced = match staged {
+        Ok(replaced) => replaced,
+        Err(e) => {
+            if let Err(e) = revoke_key(&app_state, fingerprint).await {
+                tracing::error!(error = %e, repository = %name, "Failed to remove staged key");
+            }
+            return Err(
+                DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}")).into(),
+            );
+        }
+    };
+
+    if let Some(replaced) = replaced.filter(|k| k.fingerprint.as_str() != fingerprint.as_str()) {
+        if let Err(e) = revoke_key(&app_state, &replaced.fingerprint).await {
+            tracing::error!(error = %e, repository = %name, "Failed to remove replaced staged key");
+        }
+    }
+    tracing::info!(repository = %name, %fingerprint, "Staged new deploy key");
+
+    Ok(HttpResponse::Ok().into())
+}
+
+/// Switch to the staged key after a successful fetch and retire the previous key. Only
+/// accessible with Machine scope.
+#[utoipa::path(
+    post,
+    path = "/v1/repositories/{organisation}/{repository}/staged-key/confirm",
+    operation_id = "confirm_deploy_key",
+    tag = "Repositories",
+    params(RepositoryPath),
+    request_body(content = String, description = "Fingerprint of the staged key", content_type = "text/plain"),
+    responses(
+        (status = 200, description = "Staged key is the new deploy key"),
+        (status = 404, description = "Key is not staged", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[post(
+    "/{organisation}/{repository}/staged-key/confirm",
+    name = "repository.rotate_key"
+)]
+async fn confirm(
+    req: HttpRequest,
+    path: web::Path<RepositoryPath>,
+    fingerprint: String,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    validate_scope(&req, Scope::Machine)?;
+    let name = extract_name(path);
+
+    app_state
+        .database
+        .repositories
+        .activate_staged_key(&name, fingerprint.trim())
+        .await
+        .map_err(|e| {
+            DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}/staged-key"))
+        })?;
+    tracing::info!(repository = %name, fingerprint = %fingerprint.trim(), "Rotated deploy key");
+
+    // Runs that were submitted before still check out with the previous key. Otherwise it's
+    // revoked when the last of them completes.
+    if let Err(e) = revoke_retired_keys(&app_state, &name).await {
+        tracing::error!(error = %e, repository = %name, "Failed to retire previous deploy key");
+    }
+
+    let kind = EventKind::DeployKeyRotated { repository: name };
+    app_state.events.publish(None, kind).await;
+
+    Ok(HttpResponse::Ok().into())
+}
+
+/// Revoke the previous deploy keys of a repository unless it has runs that haven't completed yet
+pub(super) async fn revoke_retired_keys(app_state: &AppState, name: &str) -> anyhow::Result<()> {
+    let query = RunQuery {
+        active: true,
+        archived: false,
+        limit: Some(1),
+    };
+    if !app_state
+        .database
+        .runs
+        .list(Some(name), &query)
+        .await?
+        .is_empty()
+    {
+        return Ok(());
+    }
+    let repository = app_state.database.repositories.show(name).await?;
+    for retired in &repository.retired_keys {
+        revoke_key(app_state, &retired.fingerprint).await?;
+        app_state
+            .database
+            .repositories
+            .remove_retired_key(name, retired.fingerprint.as_str())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Discard the staged key and keep the current deploy key
+#[utoipa::path(
+    delete,
+    path = "/v1/repositories/{organisation}/{repository}/staged-key",
+    operation_id = "discard_deploy_key",
+    tag = "Repositories",
+    params(RepositoryPath),
+    responses(
+        (status = 200, description = "Staged key is discarded"),
+        (status = 404, description = "Key is not staged", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[delete(
+    "/{organisation}/{repository}/staged-key",
+    name = "repository.discard_key"
+)]
+async fn discard(
+    path: web::Path<RepositoryPath>,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let name = extract_name(path);
+
+    let staged = app_state
+        .database
+        .repositories
+        .discard_staged_key(&name)
+        .await
+        .map_err(|e| {
+            DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}/staged-key"))
+        })?;
+    revoke_key(&app_state, &staged.fingerprint)
+        .await
+        .map_err(UserError::internal)?;
+
+    Ok(HttpResponse::Ok().into())
+}
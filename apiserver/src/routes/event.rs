@@ -0,0 +1,109 @@
+use actix_web::http::header::{self, CacheDirective};
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
+use futures_util::{future, StreamExt};
+use recesser_core::event::{Event, EventFilter, NewEvent};
+use recesser_core::user::Scope;
+
+use crate::audit;
+use crate::auth::middleware::validate_scope;
+use crate::error::UserError;
+use crate::events::Message;
+use crate::AppState;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(subscribe).service(report);
+}
+
+/// Stream events as Server-Sent Events. Clients resume after the last event they received by
+/// sending its ID in the `Last-Event-ID` header.
+#[utoipa::path(
+    get,
+    path = "/v1/events",
+    operation_id = "subscribe_events",
+    tag = "Events",
+    params(
+        EventFilter,
+        ("Last-Event-ID" = Option<u64>, Header, description = "ID of the last received event"),
+    ),
+    responses((status = 200, description = "Stream of events", content_type = "text/event-stream", body = String)),
+    security(("token" = []))
+)]
+#[get("")]
+async fn subscribe(
+    req: HttpRequest,
+    filter: web::Query<EventFilter>,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let last_event_id = match req.headers().get("Last-Event-ID") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .ok_or_else(|| UserError::InvalidField {
+                    field: "Last-Event-ID",
+                    reason: String::from("Not an event ID"),
+                })?,
+        ),
+        None => None,
+    };
+
+    let filter = filter.into_inner();
+    let stream = app_state
+        .events
+        .subscribe(last_event_id)
+        .filter_map(move |message| {
+            let message = match &message {
+                Message::Event(event) if !filter.matches(event) => None,
+                _ => Some(Ok::<_, Error>(message.to_sse())),
+            };
+            future::ready(message)
+        });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(header::CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(stream))
+}
+
+/// Report an event that happened outside of the apiserver. Only accessible with Machine scope.
+#[utoipa::path(
+    post,
+    path = "/v1/events",
+    operation_id = "report_event",
+    tag = "Events",
+    request_body = NewEvent,
+    responses(
+        (status = 200, description = "Published event", body = Event),
+        (status = 400, description = "Event type is emitted by the apiserver itself", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[post("", name = "event.report")]
+async fn report(
+    req: HttpRequest,
+    new_event: web::Json<NewEvent>,
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<Event>, Error> {
+    validate_scope(&req, Scope::Machine)?;
+    let new_event = new_event.into_inner();
+    audit::set_target(&req, new_event.kind.name());
+
+    if !new_event.kind.is_reported() {
+        return Err(UserError::InvalidField {
+            field: "type",
+            reason: format!(
+                "Events of type {} are emitted by the apiserver",
+                new_event.kind.name()
+            ),
+        }
+        .into());
+    }
+
+    let event = app_state
+        .events
+        .try_publish(new_event.project, new_event.kind)
+        .await
+        .map_err(UserError::internal)?;
+    Ok(web::Json(event))
+}
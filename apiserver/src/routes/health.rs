@@ -0,0 +1,48 @@
+use actix_web::{get, web, HttpResponse};
+use recesser_core::health::{DependencyStatus, Readiness};
+
+use crate::AppState;
+
+/// The apiserver is alive as long as it is able to respond
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    operation_id = "healthz",
+    tag = "Operations",
+    responses((status = 200, description = "Apiserver is alive", content_type = "text/plain", body = String))
+)]
+#[get("/healthz")]
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().body("ok")
+}
+
+/// Check whether all backends of the apiserver are reachable
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    operation_id = "readyz",
+    tag = "Operations",
+    responses(
+        (status = 200, description = "All dependencies are available", body = Readiness),
+        (status = 503, description = "At least one dependency is unavailable", body = Readiness),
+    )
+)]
+#[get("/readyz")]
+async fn readyz(app_state: web::Data<AppState>) -> HttpResponse {
+    let (database, objectstorage, secretstorage, kubernetes) = futures_util::join!(
+        DependencyStatus::check(app_state.database.ping()),
+        DependencyStatus::check(app_state.objstore.ping()),
+        DependencyStatus::check(app_state.secstore.ping()),
+        DependencyStatus::check(app_state.k8s_apiserver.ping()),
+    );
+    let readiness = Readiness::new([
+        ("database", database),
+        ("objectstorage", objectstorage),
+        ("secretstorage", secretstorage),
+        ("kubernetes", kubernetes),
+    ]);
+    match readiness.ready {
+        true => HttpResponse::Ok().json(readiness),
+        false => HttpResponse::ServiceUnavailable().json(readiness),
+    }
+}
@@ -0,0 +1,79 @@
+use actix_web::{post, web, Error, HttpRequest};
+use recesser_core::user::{Initialization, Scope};
+use ring::constant_time;
+
+use crate::audit;
+use crate::auth;
+use crate::error::UserError;
+use crate::settings::Redacted;
+use crate::AppState;
+
+/// Create the first admin. Only succeeds once.
+#[utoipa::path(
+    post,
+    path = "/v1/init",
+    operation_id = "init",
+    tag = "Administration",
+    request_body = Initialization,
+    responses(
+        (status = 200, description = "Token of the first admin", content_type = "text/plain", body = String),
+        (status = 401, description = "Bootstrap secret is wrong", body = ErrorResponse),
+        (status = 409, description = "System is already initialized", body = ErrorResponse),
+    )
+)]
+#[post("/init", name = "system.init")]
+async fn init(
+    req: HttpRequest,
+    initialization: web::Json<Initialization>,
+    app_state: web::Data<AppState>,
+) -> Result<String, Error> {
+    let initialization = initialization.into_inner();
+
+    verify_bootstrap_secret(
+        app_state.bootstrap_secret.as_ref().map(Redacted::expose),
+        initialization.bootstrap_secret.as_deref(),
+    )?;
+
+    let token = auth::Token::create(Scope::Admin, &app_state.hmac_key.lock().unwrap())
+        .map_err(UserError::internal)?;
+
+    // Claiming the initialization first keeps concurrent requests from creating two admins
+    let system = &app_state.database.system;
+    let initialized = system
+        .mark_initialized(token.user_id())
+        .await
+        .map_err(UserError::internal)?;
+    if !initialized {
+        return Err(UserError::AlreadyInitialized.into());
+    }
+
+    if let Err(e) = app_state.database.user.create(&token.extract_user()).await {
+        if let Err(e) = system.revert_initialized(token.user_id()).await {
+            tracing::error!(error = %e, "Failed to revert initialization");
+        }
+        return Err(UserError::internal(e).into());
+    }
+
+    audit::set_target(&req, token.user_id());
+
+    tracing::info!(
+        user_id = token.user_id(),
+        "Initialized system with first admin"
+    );
+
+    let serialized_token = token.to_string().map_err(UserError::internal)?;
+    Ok(serialized_token)
+}
+
+fn verify_bootstrap_secret(
+    expected: Option<&str>,
+    provided: Option<&str>,
+) -> Result<(), UserError> {
+    let expected = match expected {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+    let provided = provided.ok_or(UserError::Unauthorized)?;
+    constant_time::verify_slices_are_equal(expected.as_bytes(), provided.as_bytes())
+        .map_err(UserError::unauthorized)
+}
@@ -0,0 +1,19 @@
+use actix_web::{get, Error, HttpResponse};
+
+use crate::error::UserError;
+use crate::metrics;
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    operation_id = "metrics",
+    tag = "Operations",
+    responses((status = 200, description = "Metrics in the Prometheus text format", content_type = "text/plain", body = String))
+)]
+#[get("/metrics")]
+async fn export() -> Result<HttpResponse, Error> {
+    let body = metrics::encode().map_err(UserError::internal)?;
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(body))
+}
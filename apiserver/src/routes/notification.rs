@@ -0,0 +1,256 @@
+use actix_web::{delete, get, post, web, Error, HttpRequest, HttpResponse};
+use recesser_core::encoding::hex;
+use recesser_core::notification::{Channel, CreatedChannel, Delivery, NewChannel, Target, Trigger};
+use ring::rand::SecureRandom;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+use super::repository::{extract_name, RepositoryPath};
+use crate::audit;
+use crate::database::DocumentNotFoundError;
+use crate::error::UserError;
+use crate::AppState;
+
+/// Length of generated webhook secrets in bytes
+const SECRET_LEN: usize = 32;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(add)
+        .service(list)
+        .service(remove)
+        .service(deliveries);
+}
+
+/// Add a notification channel to a repository. The secret that webhook payloads are signed with
+/// is only returned once.
+#[utoipa::path(
+    post,
+    path = "/v1/repositories/{organisation}/{repository}/notifications",
+    operation_id = "add_notification_channel",
+    tag = "Notifications",
+    params(RepositoryPath),
+    request_body = NewChannel,
+    responses(
+        (status = 200, description = "Channel is added", body = CreatedChannel),
+        (status = 400, description = "Channel is invalid", body = ErrorResponse),
+        (status = 404, description = "Repository doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[post(
+    "/{organisation}/{repository}/notifications",
+    name = "notification.add"
+)]
+async fn add(
+    req: HttpRequest,
+    path: web::Path<RepositoryPath>,
+    new_channel: web::Json<NewChannel>,
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<CreatedChannel>, Error> {
+    let name = extract_name(path);
+    let new_channel = new_channel.into_inner();
+    validate_target(&new_channel.target, &app_state).await?;
+
+    app_state
+        .database
+        .repositories
+        .show(&name)
+        .await
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}")))?;
+
+    let channel = Channel {
+        id: Uuid::new_v4().to_string(),
+        repository: name,
+        target: new_channel.target,
+        triggers: new_channel
+            .triggers
+            .unwrap_or_else(|| Trigger::ALL.to_vec()),
+    };
+    audit::set_target(&req, &channel.id);
+
+    let secret = match channel.target {
+        Target::Webhook { .. } => {
+            let mut buf = [0; SECRET_LEN];
+            app_state
+                .rng
+                .fill(&mut buf)
+                .map_err(|_| UserError::Internal)?;
+            let secret = hex::encode(&buf);
+            app_state
+                .secstore
+                .store_webhook_secret(&channel.id, secret.as_bytes())
+                .await
+                .map_err(UserError::internal)?;
+            Some(secret)
+        }
+        _ => None,
+    };
+
+    if let Err(e) = app_state.database.notifications.add_channel(&channel).await {
+        if secret.is_some() {
+            if let Err(e) = app_state.secstore.delete_webhook_secret(&channel.id).await {
+                tracing::error!(error = %e, id = %channel.id, "Failed to delete webhook secret of channel that wasn't added");
+            }
+        }
+        return Err(UserError::internal(e).into());
+    }
+
+    Ok(web::Json(CreatedChannel { channel, secret }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/repositories/{organisation}/{repository}/notifications",
+    operation_id = "list_notification_channels",
+    tag = "Notifications",
+    params(RepositoryPath),
+    responses((status = 200, description = "Channels of the repository", body = [Channel])),
+    security(("token" = []))
+)]
+#[get("/{organisation}/{repository}/notifications")]
+async fn list(
+    path: web::Path<RepositoryPath>,
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<Vec<Channel>>, Error> {
+    let name = extract_name(path);
+    let channels = app_state
+        .database
+        .notifications
+        .channels(&name)
+        .await
+        .map_err(UserError::internal)?;
+    Ok(web::Json(channels))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+struct ChannelPath {
+    organisation: String,
+    repository: String,
+    /// ID of the channel
+    id: String,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/repositories/{organisation}/{repository}/notifications/{id}",
+    operation_id = "remove_notification_channel",
+    tag = "Notifications",
+    params(ChannelPath),
+    responses(
+        (status = 200, description = "Channel is removed"),
+        (status = 404, description = "Channel doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[delete(
+    "/{organisation}/{repository}/notifications/{id}",
+    name = "notification.remove"
+)]
+async fn remove(
+    req: HttpRequest,
+    path: web::Path<ChannelPath>,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let path = path.into_inner();
+    let name = format!("{}/{}", path.organisation, path.repository);
+    audit::set_target(&req, &path.id);
+
+    let channel = app_state
+        .database
+        .notifications
+        .remove_channel(&name, &path.id)
+        .await
+        .map_err(|e| {
+            DocumentNotFoundError::downcast(
+                e,
+                &format!("/v1/repositories/{name}/notifications/{}", path.id),
+            )
+        })?;
+
+    if let Target::Webhook { .. } = channel.target {
+        app_state
+            .secstore
+            .delete_webhook_secret(&channel.id)
+            .await
+            .map_err(UserError::internal)?;
+    }
+
+    Ok(HttpResponse::Ok().into())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DeliveryQuery {
+    /// Only return the newest deliveries
+    limit: Option<i64>,
+}
+
+/// Log of deliveries to the channels of a repository
+#[utoipa::path(
+    get,
+    path = "/v1/repositories/{organisation}/{repository}/deliveries",
+    operation_id = "list_deliveries",
+    tag = "Notifications",
+    params(RepositoryPath, DeliveryQuery),
+    responses((status = 200, description = "Deliveries from oldest to newest", body = [Delivery])),
+    security(("token" = []))
+)]
+#[get("/{organisation}/{repository}/deliveries")]
+async fn deliveries(
+    path: web::Path<RepositoryPath>,
+    query: web::Query<DeliveryQuery>,
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<Vec<Delivery>>, Error> {
+    let name = extract_name(path);
+    let deliveries = app_state
+        .database
+        .notifications
+        .deliveries(&name, query.limit)
+        .await
+        .map_err(UserError::internal)?;
+    Ok(web::Json(deliveries))
+}
+
+async fn validate_target(target: &Target, app_state: &AppState) -> Result<(), UserError> {
+    match target {
+        Target::Webhook { url } | Target::Slack { url } => {
+            let url = reqwest::Url::parse(url).map_err(|e| UserError::invalid_field("url", e))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(UserError::InvalidField {
+                    field: "url",
+                    reason: String::from("Only HTTP and HTTPS URLs are supported"),
+                });
+            }
+            app_state
+                .notifier
+                .check_url(&url)
+                .await
+                .map_err(|e| UserError::invalid_field("url", e))?;
+        }
+        Target::Email { to } => {
+            if !app_state.notifier.supports_email() {
+                return Err(UserError::InvalidField {
+                    field: "type",
+                    reason: String::from("Email notifications are not configured"),
+                });
+            }
+            if to.is_empty() || !to.iter().all(|address| is_valid_address(address)) {
+                return Err(UserError::InvalidField {
+                    field: "to",
+                    reason: String::from("Expected a list of email addresses"),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rough check that rejects anything that could break out of an SMTP command or header
+fn is_valid_address(address: &str) -> bool {
+    address.contains('@')
+        && !address
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '<' | '>' | ','))
+}
@@ -0,0 +1,148 @@
+use actix_web::{get, web};
+use recesser_core::audit::{AuditRecord, Outcome};
+use recesser_core::error::{ErrorCode, ErrorResponse, FieldError};
+use recesser_core::event::{Event, EventKind, NewEvent, Output};
+use recesser_core::health::{DependencyStatus, Readiness};
+use recesser_core::metadata::Metadata;
+use recesser_core::notification::{Channel, CreatedChannel, Delivery, NewChannel, Target, Trigger};
+use recesser_core::reconciliation::{Drift, DriftKind, ReconciliationReport, Resolution};
+use recesser_core::repository::{
+    Auth, CommitID, CreatedWebhook, Fingerprint, KeyPair, NewRepository, PrivateKey, PublicKey,
+    RemovalReport, Repository, TrackedRef, Triggers,
+};
+use recesser_core::run::{NewRun, Run, RunStatus, RunUpdate};
+use recesser_core::usage::{Usage, UsageReport};
+use recesser_core::user::{Initialization, NewUser, Scope, User};
+use utoipa::openapi;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use super::artifact::upload::UploadForm;
+use super::{
+    artifact, audit, deploy_key, event, health, init, metrics, notification, reconciliation,
+    repository, run, usage, user, webhook,
+};
+
+/// OpenAPI document generated from the annotated route handlers
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Recesser Apiserver"),
+    paths(
+        artifact::upload::upload,
+        artifact::list::list,
+        artifact::download::download_file,
+        artifact::download::download_metadata,
+        artifact::delete::delete,
+        repository::add,
+        repository::update_last_commit,
+        repository::set_triggers,
+        repository::list,
+        repository::show,
+        repository::credentials,
+        repository::remove,
+        run::add,
+        run::list,
+        run::history,
+        run::show,
+        run::update,
+        run::logs,
+        deploy_key::stage,
+        deploy_key::confirm,
+        deploy_key::discard,
+        webhook::create,
+        webhook::remove,
+        webhook::receive,
+        notification::add,
+        notification::list,
+        notification::remove,
+        notification::deliveries,
+        event::subscribe,
+        event::report,
+        user::create,
+        user::list,
+        user::delete,
+        audit::search,
+        usage::report,
+        reconciliation::check,
+        reconciliation::repair,
+        init::init,
+        health::healthz,
+        health::readyz,
+        metrics::export,
+        spec,
+    ),
+    components(schemas(
+        AuditRecord,
+        Auth,
+        Channel,
+        CommitID,
+        CreatedChannel,
+        CreatedWebhook,
+        Delivery,
+        DependencyStatus,
+        Drift,
+        DriftKind,
+        ErrorCode,
+        ErrorResponse,
+        Event,
+        EventKind,
+        FieldError,
+        Fingerprint,
+        Initialization,
+        KeyPair,
+        Metadata,
+        NewChannel,
+        NewEvent,
+        NewRepository,
+        NewRun,
+        NewUser,
+        Outcome,
+        Output,
+        PrivateKey,
+        PublicKey,
+        Readiness,
+        ReconciliationReport,
+        RemovalReport,
+        Repository,
+        Resolution,
+        Run,
+        RunStatus,
+        RunUpdate,
+        Scope,
+        Target,
+        TrackedRef,
+        Trigger,
+        Triggers,
+        UploadForm,
+        Usage,
+        UsageReport,
+        User,
+    )),
+    modifiers(&BearerToken)
+)]
+pub struct ApiDoc;
+
+/// Register the bearer token that most operations require
+struct BearerToken;
+
+impl Modify for BearerToken {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/openapi.json",
+    operation_id = "openapi",
+    tag = "Operations",
+    responses((status = 200, description = "This document", content_type = "application/json"))
+)]
+#[get("/openapi.json")]
+async fn spec() -> web::Json<openapi::OpenApi> {
+    web::Json(ApiDoc::openapi())
+}
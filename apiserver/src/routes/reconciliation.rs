@@ -0,0 +1,44 @@
+use actix_web::{get, post, web, Error};
+use recesser_core::reconciliation::ReconciliationReport;
+
+use crate::error::UserError;
+use crate::reconciler;
+use crate::AppState;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(check).service(repair);
+}
+
+/// Drift between repositories, SSH keys in secret storage and secrets in the argo namespace
+#[utoipa::path(
+    get,
+    path = "/v1/reconciliation",
+    operation_id = "report_drift",
+    tag = "Administration",
+    responses((status = 200, description = "Detected drift", body = ReconciliationReport)),
+    security(("token" = []))
+)]
+#[get("")]
+async fn check(app_state: web::Data<AppState>) -> Result<web::Json<ReconciliationReport>, Error> {
+    let report = reconciler::reconcile(&app_state, false)
+        .await
+        .map_err(UserError::internal)?;
+    Ok(web::Json(report))
+}
+
+/// Restore missing Kubernetes secrets and delete orphaned keys and secrets
+#[utoipa::path(
+    post,
+    path = "/v1/reconciliation",
+    operation_id = "repair_drift",
+    tag = "Administration",
+    responses((status = 200, description = "Detected drift and its resolution", body = ReconciliationReport)),
+    security(("token" = []))
+)]
+#[post("", name = "system.reconcile")]
+async fn repair(app_state: web::Data<AppState>) -> Result<web::Json<ReconciliationReport>, Error> {
+    let report = reconciler::reconcile(&app_state, true)
+        .await
+        .map_err(UserError::internal)?;
+    Ok(web::Json(report))
+}
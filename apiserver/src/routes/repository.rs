@@ -1,73 +1,333 @@
 use actix_web::http::header;
 use actix_web::{delete, get, put, web, Error, HttpRequest, HttpResponse};
-use recesser_core::repository::{NewRepository, Repository};
+use recesser_core::event::EventKind;
+use recesser_core::repository::{
+    self, Auth, Fingerprint, NewRepository, RemovalReport, Repository, Transport, Triggers,
+};
 use recesser_core::user::Scope;
+use serde::Deserialize;
+use utoipa::IntoParams;
 
+use crate::audit;
 use crate::auth::middleware::validate_scope;
-use crate::database::DocumentNotFoundError;
+use crate::database::{AlreadyExistsError, DocumentNotFoundError};
 use crate::error::UserError;
+use crate::kubernetes;
 use crate::AppState;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(add)
         .service(update_last_commit)
+        .service(set_triggers)
         .service(list)
         .service(show)
         .service(credentials)
         .service(remove);
 }
 
-#[put("")]
+#[utoipa::path(
+    put,
+    path = "/v1/repositories",
+    operation_id = "add_repository",
+    tag = "Repositories",
+    request_body = NewRepository,
+    responses(
+        (status = 200, description = "Repository is added"),
+        (status = 400, description = "Name, URL or credentials are invalid", body = ErrorResponse),
+        (status = 409, description = "Repository exists with another URL or key", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[put("", name = "repository.add")]
 async fn add(
+    req: HttpRequest,
     new_repository: web::Json<NewRepository>,
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
     let new_repository = new_repository.into_inner();
+    let name = new_repository.name.clone();
+    audit::set_target(&req, &name);
+    let repository = validate(&new_repository, app_state.allow_file_urls)?;
 
-    app_state
-        .k8s_apiserver
-        .create_ssh_secret(&new_repository.keypair)
-        .await
-        .map_err(UserError::internal)?;
-
-    let repository = Repository::new(
-        &new_repository.name,
-        new_repository.keypair.public_key.clone(),
-    );
-    app_state
+    // Adding an already registered repository with the same URL and key is a retry and repairs
+    // missing secrets. Its secrets are never removed on failure.
+    let registered = match app_state
         .database
         .repositories
-        .add(repository)
+        .find(&name)
         .await
-        .map_err(UserError::internal)?;
+        .map_err(UserError::internal)?
+    {
+        Some(existing) if existing.same_registration(&repository) => true,
+        Some(_) => {
+            let e = format!("Repository is registered with another URL or key: {name}");
+            return Err(UserError::already_exists(&format!("Repository {name}"), e).into());
+        }
+        None => false,
+    };
+
+    if let Err(e) = register(&app_state, &new_repository, repository.clone()).await {
+        // The database write is the commit point. It may have succeeded even if it reported an
+        // error, e.g. on a timeout.
+        let committed = matches!(
+            app_state.database.repositories.find(&name).await,
+            Ok(Some(existing)) if existing.same_registration(&repository)
+        );
+        if !committed {
+            if !registered {
+                compensate(&app_state, &repository).await;
+            }
+            return Err(AlreadyExistsError::downcast(e, &format!("Repository {name}")).into());
+        }
+    }
+
+    let kind = EventKind::RepositoryAdded {
+        repository: new_repository.name,
+    };
+    app_state.events.publish(None, kind).await;
+
+    Ok(HttpResponse::Ok().into())
+}
+
+/// Check that the name has the form `namespace/name` and that the credentials fit the protocol of
+/// the URL. Local paths are only accepted if they are allowed in the settings.
+fn validate(
+    new_repository: &NewRepository,
+    allow_file_urls: bool,
+) -> Result<Repository, UserError> {
+    let name = &new_repository.name;
+    let valid_segment = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    match name.split_once('/') {
+        Some((namespace, rest)) if valid_segment(namespace) && valid_segment(rest) => (),
+        _ => {
+            return Err(UserError::invalid_field(
+                "name",
+                "Name needs to have the form namespace/name",
+            ))
+        }
+    }
+
+    let url = match &new_repository.url {
+        Some(url) => url.clone(),
+        None => repository::github_url(name),
+    };
+    let transport = Transport::of(&url).ok_or_else(|| {
+        UserError::invalid_field("url", "URL needs to use SSH, HTTP(S) or file://")
+    })?;
+    // The schandler would read any path on its own filesystem
+    if transport == Transport::File && !allow_file_urls {
+        return Err(UserError::invalid_field(
+            "url",
+            "file:// URLs are disabled on this server",
+        ));
+    }
+    let keypair = &new_repository.keypair;
+    let token = &new_repository.token;
+    let auth = match (transport, keypair, token) {
+        (Transport::Ssh, Some(_), None) => Auth::Ssh,
+        (Transport::Ssh, None, _) => {
+            return Err(UserError::invalid_field(
+                "keypair",
+                "SSH URLs require a deploy key",
+            ))
+        }
+        (Transport::Http, None, Some(_)) => Auth::Token {
+            username: new_repository
+                .username
+                .clone()
+                .unwrap_or_else(|| String::from("oauth2")),
+        },
+        (Transport::Http | Transport::File, None, None) => Auth::Anonymous,
+        (_, Some(_), _) => {
+            return Err(UserError::invalid_field(
+                "keypair",
+                "Deploy keys require an SSH URL",
+            ))
+        }
+        (_, _, Some(_)) => {
+            return Err(UserError::invalid_field(
+                "token",
+                "Access tokens require an HTTP(S) URL",
+            ))
+        }
+    };
+    let public_key = keypair.as_ref().map(|k| k.public_key.clone());
+    Ok(Repository::new(name, &url, auth, public_key))
+}
+
+/// Store the credentials in secret storage and the argo namespace and finally the repository in
+/// the database. Every step can be repeated.
+async fn register(
+    app_state: &AppState,
+    new_repository: &NewRepository,
+    repository: Repository,
+) -> anyhow::Result<()> {
+    let name = &new_repository.name;
+    if let Some(keypair) = &new_repository.keypair {
+        app_state.secstore.store_ssh_key(keypair).await?;
+        app_state
+            .k8s_apiserver
+            .apply_ssh_secret(
+                name,
+                &keypair.public_key.fingerprint,
+                keypair.private_key.as_str(),
+            )
+            .await?;
+    }
+    if let (Auth::Token { username }, Some(token)) = (&repository.auth, &new_repository.token) {
+        app_state.secstore.store_git_token(name, token).await?;
+        app_state
+            .k8s_apiserver
+            .apply_git_token_secret(name, username, token)
+            .await?;
+    }
+    app_state.database.repositories.add(repository).await
+}
+
+/// Undo a failed registration. Anything left behind is found by the reconciler.
+async fn compensate(app_state: &AppState, repository: &Repository) {
+    let name = &repository.name;
+    tracing::warn!(repository = %name, "Registration failed, removing stored secrets");
+    if let Some(public_key) = &repository.public_key {
+        if let Err(e) = revoke_key(app_state, &public_key.fingerprint).await {
+            tracing::error!(error = %e, repository = %name, "Failed to remove stored secrets");
+        }
+    }
+    if let Auth::Token { .. } = repository.auth {
+        if let Err(e) = revoke_token(app_state, name).await {
+            tracing::error!(error = %e, repository = %name, "Failed to remove stored token");
+        }
+    }
+}
+
+/// Delete the access token of a repository from the argo namespace and secret storage
+async fn revoke_token(app_state: &AppState, name: &str) -> anyhow::Result<()> {
+    let secret = repository::token_secret_name(name);
+    app_state.k8s_apiserver.delete_argo_secret(&secret).await?;
+    app_state.secstore.delete_git_token(name).await
+}
 
+/// Delete a private key from the argo namespace and secret storage
+pub(super) async fn revoke_key(
+    app_state: &AppState,
+    fingerprint: &Fingerprint,
+) -> anyhow::Result<()> {
+    let secret = kubernetes::ssh_secret_name(fingerprint)?;
+    app_state.k8s_apiserver.delete_argo_secret(&secret).await?;
     app_state
         .secstore
-        .store_ssh_key(&new_repository.keypair)
+        .delete_ssh_key(fingerprint.as_str())
         .await
-        .map_err(UserError::internal)?;
-
-    Ok(HttpResponse::Ok().into())
 }
 
-#[put("/{organisation}/{repository}/last-commit")]
+#[utoipa::path(
+    put,
+    path = "/v1/repositories/{organisation}/{repository}/last-commit",
+    operation_id = "update_last_commit",
+    tag = "Repositories",
+    params(RepositoryPath, LastCommitQuery),
+    request_body(content = String, description = "ID of the last processed commit", content_type = "text/plain"),
+    responses(
+        (status = 200, description = "Last commit is updated"),
+        (status = 404, description = "Repository doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[put(
+    "/{organisation}/{repository}/last-commit",
+    name = "repository.update_last_commit"
+)]
 async fn update_last_commit(
-    path: web::Path<(String, String)>,
+    path: web::Path<RepositoryPath>,
+    query: web::Query<LastCommitQuery>,
     body: String,
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
     let name = extract_name(path);
+    let repositories = &app_state.database.repositories;
+
+    let result = match &query.reference {
+        Some(reference) => {
+            repositories
+                .update_tracked_ref(&name, reference, &body)
+                .await
+        }
+        None => repositories.update_last_commit(&name, &body).await,
+    };
+    result.map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}")))?;
+
+    Ok(HttpResponse::Ok().into())
+}
+
+/// Replace the rules for which branches, tags and changed paths start runs
+#[utoipa::path(
+    put,
+    path = "/v1/repositories/{organisation}/{repository}/triggers",
+    operation_id = "set_triggers",
+    tag = "Repositories",
+    params(RepositoryPath),
+    request_body = Triggers,
+    responses(
+        (status = 200, description = "Trigger rules are replaced"),
+        (status = 400, description = "Branch name or glob pattern is invalid", body = ErrorResponse),
+        (status = 404, description = "Repository doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[put(
+    "/{organisation}/{repository}/triggers",
+    name = "repository.set_triggers"
+)]
+async fn set_triggers(
+    path: web::Path<RepositoryPath>,
+    triggers: web::Json<Triggers>,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let name = extract_name(path);
+    let mut triggers = triggers.into_inner();
+    if triggers.branches.iter().any(|b| b.trim().is_empty()) {
+        return Err(UserError::invalid_field("branches", "Branch names must not be empty").into());
+    }
+    triggers
+        .validate()
+        .map_err(|e| UserError::invalid_field("triggers", e))?;
+
+    let repository = app_state
+        .database
+        .repositories
+        .show(&name)
+        .await
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}")))?;
+    // Only tags created from now on start runs, otherwise a new pattern would start a run for
+    // every existing release
+    triggers.tags_since = if triggers.tags == repository.triggers.tags {
+        repository.triggers.tags_since
+    } else {
+        Some(chrono::Utc::now())
+    };
 
     app_state
         .database
         .repositories
-        .update_last_commit(&name, &body)
+        .set_triggers(&name, &triggers)
         .await
-        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/repositories/{name}")))?;
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}")))?;
 
     Ok(HttpResponse::Ok().into())
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/repositories",
+    operation_id = "list_repositories",
+    tag = "Repositories",
+    responses((status = 200, description = "All repositories", body = [Repository])),
+    security(("token" = []))
+)]
 #[get("")]
 async fn list(app_state: web::Data<AppState>) -> Result<web::Json<Vec<Repository>>, Error> {
     let repositories = app_state
@@ -79,9 +339,21 @@ async fn list(app_state: web::Data<AppState>) -> Result<web::Json<Vec<Repository
     Ok(web::Json(repositories))
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/repositories/{organisation}/{repository}",
+    operation_id = "show_repository",
+    tag = "Repositories",
+    params(RepositoryPath),
+    responses(
+        (status = 200, description = "Repository", body = Repository),
+        (status = 404, description = "Repository doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
 #[get("/{organisation}/{repository}")]
 async fn show(
-    path: web::Path<(String, String)>,
+    path: web::Path<RepositoryPath>,
     app_state: web::Data<AppState>,
 ) -> Result<web::Json<Repository>, Error> {
     let name = extract_name(path);
@@ -91,15 +363,29 @@ async fn show(
         .repositories
         .show(&name)
         .await
-        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/repositories/{name}")))?;
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}")))?;
 
     Ok(web::Json(repository))
 }
 
+/// Private SSH key of the repository. Only accessible with Machine scope.
+#[utoipa::path(
+    get,
+    path = "/v1/repositories/{organisation}/{repository}/credentials",
+    operation_id = "repository_credentials",
+    tag = "Repositories",
+    params(RepositoryPath, CredentialsQuery),
+    responses(
+        (status = 200, description = "Private SSH key", content_type = "application/octet-stream", body = String),
+        (status = 404, description = "Repository or staged key doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
 #[get("/{organisation}/{repository}/credentials")]
 async fn credentials(
     req: HttpRequest,
-    path: web::Path<(String, String)>,
+    path: web::Path<RepositoryPath>,
+    query: web::Query<CredentialsQuery>,
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
     validate_scope(&req, Scope::Machine)?;
@@ -110,38 +396,242 @@ async fn credentials(
         .repositories
         .show(&name)
         .await
-        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/repositories/{name}")))?;
-    let fingerprint = repository.public_key.fingerprint.to_string();
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}")))?;
+    let public_key = match query.staged {
+        true => repository.staged_key,
+        false => repository.public_key,
+    };
 
-    let private_key = app_state
-        .secstore
-        .get_ssh_key(&fingerprint)
-        .await
-        .map_err(UserError::internal)?;
+    let credentials = match (&repository.auth, public_key) {
+        (Auth::Ssh, Some(public_key)) => app_state
+            .secstore
+            .get_ssh_key(public_key.fingerprint.as_str())
+            .await
+            .map_err(UserError::internal)?,
+        (Auth::Token { .. }, _) if !query.staged => app_state
+            .secstore
+            .get_git_token(&name)
+            .await
+            .map_err(UserError::internal)?,
+        _ => {
+            let path = format!("/v1/repositories/{name}/credentials");
+            return Err(UserError::not_found(&path, "Repository has no such credentials").into());
+        }
+    };
 
     Ok(HttpResponse::Ok()
         .insert_header(header::ContentType(mime::APPLICATION_OCTET_STREAM))
-        .body(private_key))
+        .body(credentials))
 }
 
-#[delete("/{organisation}/{repository}")]
+#[utoipa::path(
+    delete,
+    path = "/v1/repositories/{organisation}/{repository}",
+    operation_id = "remove_repository",
+    tag = "Repositories",
+    params(RepositoryPath, RemovalQuery),
+    responses(
+        (status = 200, description = "Repository is removed", body = RemovalReport),
+        (status = 404, description = "Repository doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[delete("/{organisation}/{repository}", name = "repository.remove")]
 async fn remove(
-    path: web::Path<(String, String)>,
+    path: web::Path<RepositoryPath>,
+    query: web::Query<RemovalQuery>,
     app_state: web::Data<AppState>,
-) -> Result<HttpResponse, Error> {
+) -> Result<web::Json<RemovalReport>, Error> {
     let name = extract_name(path);
 
-    app_state
+    // Once the repository is gone it's no longer polled, so its credentials can be revoked
+    let repository = app_state
         .database
         .repositories
         .remove(&name)
         .await
-        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/repositories/{name}")))?;
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}")))?;
 
-    Ok(HttpResponse::Ok().into())
+    let report = clean_up(&app_state, &repository, query.keep_history).await;
+    if !report.errors.is_empty() {
+        tracing::warn!(repository = %name, errors = ?report.errors, "Failed to clean up removed repository");
+    }
+
+    let kind = EventKind::RepositoryRemoved { repository: name };
+    app_state.events.publish(None, kind).await;
+
+    Ok(web::Json(report))
+}
+
+/// Revoke the credentials of a removed repository and stop its runs. Failures don't abort the
+/// cleanup but are reported.
+async fn clean_up(
+    app_state: &AppState,
+    repository: &Repository,
+    keep_history: bool,
+) -> RemovalReport {
+    let name = &repository.name;
+    let mut report = RemovalReport {
+        repository: name.clone(),
+        history_archived: keep_history,
+        ..Default::default()
+    };
+
+    if let Some(public_key) = &repository.public_key {
+        let fingerprint = &public_key.fingerprint;
+        match app_state
+            .secstore
+            .delete_ssh_key(fingerprint.as_str())
+            .await
+        {
+            Ok(()) => report.ssh_key = true,
+            Err(e) => report.errors.push(format!("Failed to delete SSH key: {e}")),
+        }
+
+        let secret = kubernetes::ssh_secret_name(fingerprint);
+        let result = match secret {
+            Ok(secret) => app_state.k8s_apiserver.delete_argo_secret(&secret).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => report.kubernetes_secret = true,
+            Err(e) => report
+                .errors
+                .push(format!("Failed to delete Kubernetes secret: {e}")),
+        }
+    }
+    if let Some(staged_key) = &repository.staged_key {
+        if let Err(e) = revoke_key(app_state, &staged_key.fingerprint).await {
+            report
+                .errors
+                .push(format!("Failed to delete staged key: {e}"));
+        }
+    }
+    for retired in &repository.retired_keys {
+        if let Err(e) = revoke_key(app_state, &retired.fingerprint).await {
+            report
+                .errors
+                .push(format!("Failed to delete retired key: {e}"));
+        }
+    }
+    if let Auth::Token { .. } = repository.auth {
+        match revoke_token(app_state, name).await {
+            Ok(()) => report.token = true,
+            Err(e) => report
+                .errors
+                .push(format!("Failed to delete access token: {e}")),
+        }
+    }
+    if repository.webhook {
+        if let Err(e) = app_state.secstore.delete_push_webhook_secret(name).await {
+            report
+                .errors
+                .push(format!("Failed to delete push webhook secret: {e}"));
+        }
+    }
+
+    match app_state.k8s_apiserver.list_workflows(name).await {
+        Ok(workflows) => {
+            for workflow in workflows {
+                let result = match (workflow.finished, keep_history) {
+                    (true, true) => {
+                        app_state
+                            .k8s_apiserver
+                            .archive_workflow(&workflow.name)
+                            .await
+                    }
+                    _ => {
+                        app_state
+                            .k8s_apiserver
+                            .delete_workflow(&workflow.name)
+                            .await
+                    }
+                };
+                match (result, workflow.finished) {
+                    (Ok(()), true) => report.finished_runs.push(workflow.name),
+                    (Ok(()), false) => report.stopped_runs.push(workflow.name),
+                    (Err(e), _) => report.errors.push(format!(
+                        "Failed to clean up workflow {}: {e}",
+                        workflow.name
+                    )),
+                }
+            }
+        }
+        Err(e) => report.errors.push(format!("Failed to list workflows: {e}")),
+    }
+
+    let notifications = &app_state.database.notifications;
+    match notifications.remove_channels(name).await {
+        Ok(channels) => {
+            for channel in channels {
+                if let Err(e) = app_state.secstore.delete_webhook_secret(&channel).await {
+                    report
+                        .errors
+                        .push(format!("Failed to delete webhook secret of {channel}: {e}"));
+                }
+                report.notification_channels.push(channel);
+            }
+        }
+        Err(e) => report
+            .errors
+            .push(format!("Failed to remove notification channels: {e}")),
+    }
+    if !keep_history {
+        if let Err(e) = notifications.remove_deliveries(name).await {
+            report
+                .errors
+                .push(format!("Failed to remove delivery log: {e}"));
+        }
+    }
+
+    let runs = &app_state.database.runs;
+    let result = if keep_history {
+        runs.archive(name).await
+    } else {
+        runs.remove(name).await
+    };
+    if let Err(e) = result {
+        report
+            .errors
+            .push(format!("Failed to clean up run records: {e}"));
+    }
+
+    report
+}
+
+/// Repositories are identified by a namespace, e.g. the organisation on the Git host, and a name
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub(super) struct RepositoryPath {
+    organisation: String,
+    repository: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct LastCommitQuery {
+    /// Full name of a watched branch or tag, e.g. `refs/heads/main`. Updates the last commit of
+    /// the default branch if omitted.
+    reference: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct CredentialsQuery {
+    /// Return the staged key that replaces the current one after a rotation
+    #[serde(default)]
+    staged: bool,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct RemovalQuery {
+    /// Keep completed runs, their records and the delivery log as archived history
+    #[serde(default)]
+    keep_history: bool,
 }
 
-fn extract_name(path: web::Path<(String, String)>) -> String {
+pub(super) fn extract_name(path: web::Path<RepositoryPath>) -> String {
     let path = path.into_inner();
-    format!("{}/{}", path.0, path.1)
+    format!("{}/{}", path.organisation, path.repository)
 }
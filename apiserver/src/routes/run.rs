@@ -0,0 +1,233 @@
+use actix_web::{get, post, put, web, Error, HttpRequest, HttpResponse};
+use recesser_core::run::{NewRun, Run, RunQuery, RunUpdate};
+use recesser_core::user::Scope;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use super::deploy_key::revoke_retired_keys;
+use super::repository::{extract_name, RepositoryPath};
+use crate::audit;
+use crate::auth::middleware::validate_scope;
+use crate::database::{AlreadyExistsError, DocumentNotFoundError};
+use crate::error::UserError;
+use crate::AppState;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(add)
+        .service(list)
+        .service(show)
+        .service(update)
+        .service(logs);
+}
+
+/// Routes below a repository
+pub fn repository_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(history);
+}
+
+/// Record a run before its workflow is submitted. Only accessible with Machine scope.
+#[utoipa::path(
+    post,
+    path = "/v1/runs",
+    operation_id = "add_run",
+    tag = "Runs",
+    request_body = NewRun,
+    responses(
+        (status = 200, description = "Run is recorded as pending", body = Run),
+        (status = 409, description = "Run with this ID exists", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[post("", name = "run.add")]
+async fn add(
+    req: HttpRequest,
+    new_run: web::Json<NewRun>,
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<Run>, Error> {
+    validate_scope(&req, Scope::Machine)?;
+    let run = Run::new(new_run.into_inner(), chrono::Utc::now());
+    audit::set_target(&req, &run.id);
+
+    app_state
+        .database
+        .runs
+        .add(&run)
+        .await
+        .map_err(|e| AlreadyExistsError::downcast(e, &format!("Run {}", run.id)))?;
+    Ok(web::Json(run))
+}
+
+/// Runs of all repositories, e.g. those that haven't completed yet
+#[utoipa::path(
+    get,
+    path = "/v1/runs",
+    operation_id = "list_runs",
+    tag = "Runs",
+    params(RunQuery),
+    responses((status = 200, description = "Runs from newest to oldest", body = [Run])),
+    security(("token" = []))
+)]
+#[get("")]
+async fn list(
+    query: web::Query<RunQuery>,
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<Vec<Run>>, Error> {
+    let runs = app_state
+        .database
+        .runs
+        .list(None, &query)
+        .await
+        .map_err(UserError::internal)?;
+    Ok(web::Json(runs))
+}
+
+/// Run history of a repository. Runs of a removed repository are only listed if they were
+/// archived and `archived` is set.
+#[utoipa::path(
+    get,
+    path = "/v1/repositories/{organisation}/{repository}/runs",
+    operation_id = "list_repository_runs",
+    tag = "Runs",
+    params(RepositoryPath, RunQuery),
+    responses((status = 200, description = "Runs from newest to oldest", body = [Run])),
+    security(("token" = []))
+)]
+#[get("/{organisation}/{repository}/runs")]
+async fn history(
+    path: web::Path<RepositoryPath>,
+    query: web::Query<RunQuery>,
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<Vec<Run>>, Error> {
+    let name = extract_name(path);
+    let runs = app_state
+        .database
+        .runs
+        .list(Some(&name), &query)
+        .await
+        .map_err(UserError::internal)?;
+    Ok(web::Json(runs))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/runs/{id}",
+    operation_id = "show_run",
+    tag = "Runs",
+    params(RunPath),
+    responses(
+        (status = 200, description = "Run", body = Run),
+        (status = 404, description = "Run doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[get("/{id}")]
+async fn show(
+    path: web::Path<RunPath>,
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<Run>, Error> {
+    let id = &path.id;
+    let run = app_state
+        .database
+        .runs
+        .find(id)
+        .await
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/runs/{id}")))?;
+    Ok(web::Json(run))
+}
+
+/// Report a change of the status of a run. Only accessible with Machine scope.
+#[utoipa::path(
+    put,
+    path = "/v1/runs/{id}/status",
+    operation_id = "update_run",
+    tag = "Runs",
+    params(RunPath),
+    request_body = RunUpdate,
+    responses(
+        (status = 200, description = "Updated run", body = Run),
+        (status = 400, description = "Run already completed", body = ErrorResponse),
+        (status = 404, description = "Run doesn't exist or is archived", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[put("/{id}/status", name = "run.update")]
+async fn update(
+    req: HttpRequest,
+    path: web::Path<RunPath>,
+    update: web::Json<RunUpdate>,
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<Run>, Error> {
+    validate_scope(&req, Scope::Machine)?;
+    let id = &path.id;
+    audit::set_target(&req, id);
+
+    let runs = &app_state.database.runs;
+    let mut run = runs
+        .find(id)
+        .await
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/runs/{id}")))?;
+    run.update(update.into_inner(), chrono::Utc::now())
+        .map_err(|e| UserError::invalid_field("status", e))?;
+    runs.replace(&run)
+        .await
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/runs/{id}")))?;
+
+    // The run may have been the last one to use a deploy key that was rotated in the meantime
+    if run.status.is_finished() {
+        if let Err(e) = revoke_retired_keys(&app_state, &run.repository).await {
+            tracing::error!(error = %e, repository = %run.repository, "Failed to retire previous deploy key");
+        }
+    }
+    Ok(web::Json(run))
+}
+
+/// Logs of the steps of a run as long as Argo keeps their pods
+#[utoipa::path(
+    get,
+    path = "/v1/runs/{id}/logs",
+    operation_id = "run_logs",
+    tag = "Runs",
+    params(RunPath),
+    responses(
+        (status = 200, description = "Logs of each step", content_type = "text/plain", body = String),
+        (status = 404, description = "Run doesn't exist or was never submitted", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[get("/{id}/logs")]
+async fn logs(
+    path: web::Path<RunPath>,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let id = &path.id;
+    let run = app_state
+        .database
+        .runs
+        .find(id)
+        .await
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/runs/{id}")))?;
+    let workflow = run.argo_workflow.ok_or_else(|| {
+        UserError::not_found(&format!("/v1/runs/{id}/logs"), "Run was never submitted")
+    })?;
+
+    let step_logs = app_state
+        .k8s_apiserver
+        .workflow_logs(&workflow)
+        .await
+        .map_err(UserError::internal)?;
+    let mut body = String::new();
+    for step_log in step_logs {
+        body.push_str(&format!("==> {} <==\n", step_log.step));
+        match step_log.log {
+            Ok(log) => body.push_str(&log),
+            Err(e) => body.push_str(&format!("Failed to retrieve logs: {e}\n")),
+        }
+    }
+    Ok(HttpResponse::Ok().content_type("text/plain").body(body))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub(super) struct RunPath {
+    id: String,
+}
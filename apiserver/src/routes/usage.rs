@@ -0,0 +1,47 @@
+use actix_web::{get, web, Error};
+use recesser_core::usage::UsageReport;
+
+use crate::database::UsageField;
+use crate::error::UserError;
+use crate::AppState;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(report);
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/usage",
+    operation_id = "storage_usage",
+    tag = "Administration",
+    responses((status = 200, description = "Storage usage by user and project", body = UsageReport)),
+    security(("token" = []))
+)]
+#[get("")]
+async fn report(app_state: web::Data<AppState>) -> Result<web::Json<UsageReport>, Error> {
+    let metadata_store = &app_state.database.metadata;
+    let quotas = app_state.quotas();
+
+    let total = metadata_store
+        .total_usage()
+        .await
+        .map_err(UserError::internal)?;
+
+    let mut users = metadata_store
+        .usage_by(UsageField::Owner)
+        .await
+        .map_err(UserError::internal)?;
+    users.iter_mut().for_each(|u| u.quota = quotas.user);
+
+    let mut projects = metadata_store
+        .usage_by(UsageField::Project)
+        .await
+        .map_err(UserError::internal)?;
+    projects.iter_mut().for_each(|p| p.quota = quotas.project);
+
+    Ok(web::Json(UsageReport {
+        total,
+        users,
+        projects,
+    }))
+}
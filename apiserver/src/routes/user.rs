@@ -1,7 +1,9 @@
-use actix_web::{delete, get, post, web, Error};
+use actix_web::{delete, get, post, web, Error, HttpRequest};
 use recesser_core::user::{NewUser, Scope, User};
 
+use crate::audit;
 use crate::auth;
+use crate::bootstrap;
 use crate::error::UserError;
 use crate::AppState;
 
@@ -9,8 +11,19 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(create).service(list).service(delete);
 }
 
-#[post("")]
+/// Create a user and return its token
+#[utoipa::path(
+    post,
+    path = "/v1/users",
+    operation_id = "create_user",
+    tag = "Users",
+    request_body = NewUser,
+    responses((status = 200, description = "Token of the new user", content_type = "text/plain", body = String)),
+    security(("token" = []))
+)]
+#[post("", name = "user.create")]
 async fn create(
+    req: HttpRequest,
     new_user: web::Json<NewUser>,
     app_state: web::Data<AppState>,
 ) -> Result<String, Error> {
@@ -25,11 +38,20 @@ async fn create(
         .create(&token.extract_user())
         .await
         .map_err(UserError::internal)?;
+    audit::set_target(&req, token.user_id());
 
     let serialized_token = token.to_string().map_err(UserError::internal)?;
     Ok(serialized_token)
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/users",
+    operation_id = "list_users",
+    tag = "Users",
+    responses((status = 200, description = "All users", body = [User])),
+    security(("token" = []))
+)]
 #[get("")]
 async fn list(app_state: web::Data<AppState>) -> Result<web::Json<Vec<User>>, Error> {
     let users = app_state
@@ -41,7 +63,16 @@ async fn list(app_state: web::Data<AppState>) -> Result<web::Json<Vec<User>>, Er
     Ok(web::Json(users))
 }
 
-#[delete("")]
+/// Rotate the signing key. This deletes all users and invalidates their tokens.
+#[utoipa::path(
+    delete,
+    path = "/v1/users",
+    operation_id = "rotate_key",
+    tag = "Users",
+    responses((status = 200, description = "Token of the new root admin", content_type = "text/plain", body = String)),
+    security(("token" = []))
+)]
+#[delete("", name = "user.rotate_key")]
 async fn delete(app_state: web::Data<AppState>) -> Result<String, Error> {
     // Delete all user records
     app_state
@@ -64,6 +95,19 @@ async fn delete(app_state: web::Data<AppState>) -> Result<String, Error> {
         .store_hmac_key(&key_value)
         .await
         .map_err(UserError::internal)?;
+    app_state
+        .database
+        .user
+        .create(&token.extract_user())
+        .await
+        .map_err(UserError::internal)?;
+
+    // Tokens of the schandler were revoked as well and need to be reissued
+    bootstrap::issue_machine_token(&hmac_key, &app_state.database, &app_state.k8s_apiserver)
+        .await
+        .map_err(UserError::internal)?;
+    tracing::warn!("Restart the schandler to pick up its new Machine token");
+
     *app_state.hmac_key.lock().unwrap() = hmac_key;
 
     Ok(serialized_token)
@@ -0,0 +1,179 @@
+use actix_web::{delete, post, web, Error, HttpRequest, HttpResponse};
+use recesser_core::encoding::hex;
+use recesser_core::event::EventKind;
+use recesser_core::repository::CreatedWebhook;
+use ring::rand::SecureRandom;
+
+use super::repository::{extract_name, RepositoryPath};
+use super::API_PREFIX;
+use crate::audit;
+use crate::database::DocumentNotFoundError;
+use crate::error::UserError;
+use crate::webhooks::{self, Delivery, WebhookError};
+use crate::AppState;
+
+/// Length of generated webhook secrets in bytes
+const SECRET_LEN: usize = 32;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(create).service(remove);
+}
+
+/// Set up a push webhook for a repository. Creating it again replaces the secret, which is only
+/// returned once.
+#[utoipa::path(
+    post,
+    path = "/v1/repositories/{organisation}/{repository}/webhook",
+    operation_id = "create_push_webhook",
+    tag = "Repositories",
+    params(RepositoryPath),
+    responses(
+        (status = 200, description = "Webhook is set up", body = CreatedWebhook),
+        (status = 404, description = "Repository doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[post(
+    "/{organisation}/{repository}/webhook",
+    name = "repository.create_webhook"
+)]
+async fn create(
+    path: web::Path<RepositoryPath>,
+    app_state: web::Data<AppState>,
+) -> Result<web::Json<CreatedWebhook>, Error> {
+    let name = extract_name(path);
+
+    app_state
+        .database
+        .repositories
+        .show(&name)
+        .await
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}")))?;
+
+    let mut buf = [0; SECRET_LEN];
+    app_state
+        .rng
+        .fill(&mut buf)
+        .map_err(|_| UserError::Internal)?;
+    let secret = hex::encode(&buf);
+    app_state
+        .secstore
+        .store_push_webhook_secret(&name, secret.as_bytes())
+        .await
+        .map_err(UserError::internal)?;
+    app_state
+        .database
+        .repositories
+        .set_webhook(&name, true)
+        .await
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}")))?;
+
+    Ok(web::Json(CreatedWebhook {
+        path: format!("{API_PREFIX}/webhooks/{name}"),
+        secret,
+    }))
+}
+
+/// Stop accepting push webhooks for a repository. It's only polled afterwards.
+#[utoipa::path(
+    delete,
+    path = "/v1/repositories/{organisation}/{repository}/webhook",
+    operation_id = "remove_push_webhook",
+    tag = "Repositories",
+    params(RepositoryPath),
+    responses(
+        (status = 200, description = "Webhook is removed"),
+        (status = 404, description = "Repository doesn't exist", body = ErrorResponse),
+    ),
+    security(("token" = []))
+)]
+#[delete(
+    "/{organisation}/{repository}/webhook",
+    name = "repository.remove_webhook"
+)]
+async fn remove(
+    path: web::Path<RepositoryPath>,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let name = extract_name(path);
+
+    app_state
+        .database
+        .repositories
+        .set_webhook(&name, false)
+        .await
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/repositories/{name}")))?;
+    app_state
+        .secstore
+        .delete_push_webhook_secret(&name)
+        .await
+        .map_err(UserError::internal)?;
+
+    Ok(HttpResponse::Ok().into())
+}
+
+/// Receive a push webhook from GitHub, GitLab or Gitea. The delivery is authenticated by its
+/// signature instead of an access token. Verified pushes make the schandler poll the repository
+/// immediately.
+#[utoipa::path(
+    post,
+    path = "/v1/webhooks/{organisation}/{repository}",
+    operation_id = "receive_push_webhook",
+    tag = "Repositories",
+    params(RepositoryPath),
+    request_body(content = String, description = "Payload of the Git host", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Event is not a push and was ignored"),
+        (status = 202, description = "Poll of the repository is enqueued"),
+        (status = 400, description = "Payload is invalid", body = ErrorResponse),
+        (status = 401, description = "Signature is missing or invalid", body = ErrorResponse),
+        (status = 404, description = "Repository doesn't exist or has no webhook", body = ErrorResponse),
+    )
+)]
+#[post("/webhooks/{organisation}/{repository}", name = "webhook.receive")]
+pub(super) async fn receive(
+    req: HttpRequest,
+    path: web::Path<RepositoryPath>,
+    body: web::Bytes,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let name = extract_name(path);
+    audit::set_target(&req, &name);
+
+    let repository = app_state
+        .database
+        .repositories
+        .show(&name)
+        .await
+        .map_err(|e| DocumentNotFoundError::downcast(e, &format!("/v1/webhooks/{name}")))?;
+    if !repository.webhook {
+        let e = format!("Repository has no push webhook: {name}");
+        return Err(UserError::not_found(&format!("/v1/webhooks/{name}"), e).into());
+    }
+    let secret = app_state
+        .secstore
+        .get_push_webhook_secret(&name)
+        .await
+        .map_err(UserError::internal)?;
+
+    let reference = match webhooks::receive(req.headers(), &body, &secret) {
+        Ok(Delivery::Push { reference }) => reference,
+        Ok(Delivery::Ignored { event }) => {
+            tracing::debug!(%event, repository = %name, "Ignored webhook event");
+            return Ok(HttpResponse::Ok().into());
+        }
+        Err(e @ WebhookError::InvalidPayload(_)) => return Err(UserError::bad_request(e).into()),
+        Err(e) => return Err(UserError::unauthorized(e).into()),
+    };
+
+    let kind = EventKind::PushReceived {
+        repository: name,
+        reference,
+    };
+    app_state
+        .events
+        .try_publish(None, kind)
+        .await
+        .map_err(UserError::internal)?;
+    Ok(HttpResponse::Accepted().into())
+}
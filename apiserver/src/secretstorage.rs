@@ -1,14 +1,16 @@
 use std::convert::TryInto;
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use recesser_core::encoding::base64;
 use recesser_core::repository::KeyPair;
 use reqwest::Client;
-use reqwest::{header, Response};
+use reqwest::{header, Response, StatusCode};
 use ring::digest::SHA256_OUTPUT_LEN;
 use serde::{Deserialize, Serialize};
 
 use crate::encryption::KEY_LEN;
+use crate::metrics::{count_error, SECRETSTORAGE_ERRORS};
 
 #[derive(Clone)]
 pub struct SecretStorage {
@@ -21,6 +23,26 @@ struct SecretResponse {
     data: Secret,
 }
 
+#[derive(Deserialize)]
+struct ListResponse {
+    data: Keys,
+}
+
+#[derive(Deserialize)]
+struct Keys {
+    keys: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct MetadataResponse {
+    data: SecretMetadata,
+}
+
+#[derive(Deserialize)]
+struct SecretMetadata {
+    created_time: String,
+}
+
 #[derive(Deserialize, Serialize)]
 struct Secret {
     data: Data,
@@ -70,6 +92,13 @@ impl SecretStorage {
         Ok(())
     }
 
+    pub async fn ping(&self) -> Result<()> {
+        // Only succeeds if Vault is initialized and unsealed
+        let resp = self.client.get(self.url("/sys/health")).send().await?;
+        check_body(resp).await?;
+        Ok(())
+    }
+
     pub async fn get_ssh_key(&self, fingerprint: &str) -> Result<String> {
         let base64_fingerprint = base64::encode(fingerprint.as_bytes());
         let key = self.get(&format!("ssh_keys/{base64_fingerprint}")).await?;
@@ -88,6 +117,68 @@ impl SecretStorage {
         Ok(())
     }
 
+    pub async fn delete_ssh_key(&self, fingerprint: &str) -> Result<()> {
+        let base64_fingerprint = base64::encode(fingerprint.as_bytes());
+        self.delete(&format!("ssh_keys/{base64_fingerprint}")).await
+    }
+
+    /// Fingerprints of all stored SSH keys
+    pub async fn list_ssh_keys(&self) -> Result<Vec<String>> {
+        let keys = self.list("ssh_keys").await?;
+        keys.iter()
+            .map(|key| Ok(String::from_utf8(base64::decode(key)?)?))
+            .collect()
+    }
+
+    pub async fn ssh_key_created(&self, fingerprint: &str) -> Result<DateTime<Utc>> {
+        let base64_fingerprint = base64::encode(fingerprint.as_bytes());
+        let resp = self
+            .client
+            .get(self.url(&format!("/secret/metadata/ssh_keys/{base64_fingerprint}")))
+            .send()
+            .await?;
+        let body = check_body(resp).await?;
+        let metadata: MetadataResponse = serde_json::from_slice(&body)?;
+        Ok(DateTime::parse_from_rfc3339(&metadata.data.created_time)?.with_timezone(&Utc))
+    }
+
+    /// Access token a repository is cloned with via HTTPS
+    pub async fn get_git_token(&self, repository: &str) -> Result<String> {
+        let base64_name = base64::encode(repository.as_bytes());
+        let token = self.get(&format!("git_tokens/{base64_name}")).await?;
+        Ok(String::from_utf8(token)?)
+    }
+
+    pub async fn store_git_token(&self, repository: &str, token: &str) -> Result<()> {
+        let base64_name = base64::encode(repository.as_bytes());
+        self.set(&format!("git_tokens/{base64_name}"), token.as_bytes())
+            .await
+    }
+
+    pub async fn delete_git_token(&self, repository: &str) -> Result<()> {
+        let base64_name = base64::encode(repository.as_bytes());
+        self.delete(&format!("git_tokens/{base64_name}")).await
+    }
+
+    /// Secret the Git host signs push webhooks of a repository with
+    pub async fn get_push_webhook_secret(&self, repository: &str) -> Result<Vec<u8>> {
+        let base64_name = base64::encode(repository.as_bytes());
+        self.get(&format!("push_webhook_secrets/{base64_name}"))
+            .await
+    }
+
+    pub async fn store_push_webhook_secret(&self, repository: &str, secret: &[u8]) -> Result<()> {
+        let base64_name = base64::encode(repository.as_bytes());
+        self.set(&format!("push_webhook_secrets/{base64_name}"), secret)
+            .await
+    }
+
+    pub async fn delete_push_webhook_secret(&self, repository: &str) -> Result<()> {
+        let base64_name = base64::encode(repository.as_bytes());
+        self.delete(&format!("push_webhook_secrets/{base64_name}"))
+            .await
+    }
+
     pub async fn get_hmac_key(&self) -> Result<[u8; SHA256_OUTPUT_LEN]> {
         let key = self.get("hmac_key").await?;
         Ok(key[..SHA256_OUTPUT_LEN].try_into()?)
@@ -107,7 +198,63 @@ impl SecretStorage {
         Ok(())
     }
 
+    pub async fn get_webhook_secret(&self, channel_id: &str) -> Result<Vec<u8>> {
+        self.get(&format!("webhook_secrets/{channel_id}")).await
+    }
+
+    pub async fn store_webhook_secret(&self, channel_id: &str, secret: &[u8]) -> Result<()> {
+        self.set(&format!("webhook_secrets/{channel_id}"), secret)
+            .await
+    }
+
+    pub async fn delete_webhook_secret(&self, channel_id: &str) -> Result<()> {
+        self.delete(&format!("webhook_secrets/{channel_id}")).await
+    }
+
     async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        count_error(&SECRETSTORAGE_ERRORS, "get", self.get_unmetered(key).await)
+    }
+
+    async fn set(&self, key: &str, value: &[u8]) -> Result<()> {
+        count_error(
+            &SECRETSTORAGE_ERRORS,
+            "set",
+            self.set_unmetered(key, value).await,
+        )
+    }
+
+    /// Delete all versions of a secret
+    async fn delete(&self, key: &str) -> Result<()> {
+        let resp = self
+            .client
+            .delete(self.url(&format!("/secret/metadata/{key}")))
+            .send()
+            .await;
+        let result = match resp {
+            Ok(resp) => check_body(resp).await.map(|_| ()),
+            Err(e) => Err(e.into()),
+        };
+        count_error(&SECRETSTORAGE_ERRORS, "delete", result)
+    }
+
+    /// Keys of the secrets below a path
+    async fn list(&self, path: &str) -> Result<Vec<String>> {
+        let resp = self
+            .client
+            .get(self.url(&format!("/secret/metadata/{path}")))
+            .query(&[("list", "true")])
+            .send()
+            .await?;
+        // Vault responds with 404 if there are no secrets below the path
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let body = count_error(&SECRETSTORAGE_ERRORS, "list", check_body(resp).await)?;
+        let list: ListResponse = serde_json::from_slice(&body)?;
+        Ok(list.data.keys)
+    }
+
+    async fn get_unmetered(&self, key: &str) -> Result<Vec<u8>> {
         let resp = self
             .client
             .get(self.url(&format!("/secret/data/{key}")))
@@ -118,7 +265,7 @@ impl SecretStorage {
         secret_response.data.to_vec()
     }
 
-    async fn set(&self, key: &str, value: &[u8]) -> Result<()> {
+    async fn set_unmetered(&self, key: &str, value: &[u8]) -> Result<()> {
         let resp = self
             .client
             .post(self.url(&format!("/secret/data/{key}")))
@@ -1,3 +1,6 @@
+use std::fmt;
+use std::path::PathBuf;
+
 use config::{Config, ConfigError, Environment, File};
 use serde::{Deserialize, Serialize};
 
@@ -8,17 +11,72 @@ pub struct Settings {
     pub database_addr: String,
     pub secretstorage_addr: String,
     pub log_level: String,
+    pub bootstrap_secret: Option<Redacted>,
+    // Storage quotas in bytes
+    pub user_quota: Option<u64>,
+    pub project_quota: Option<u64>,
+    // Requests per minute for each token of a scope
+    pub rate_limit_user: Option<u32>,
+    pub rate_limit_machine: Option<u32>,
+    pub rate_limit_admin: Option<u32>,
+    // Concurrent uploads and downloads for each user
+    pub max_concurrent_transfers: Option<usize>,
+    // Certificate chain and private key in PEM format. TLS is enabled if both are set.
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    // CA bundle that client certificates need to be signed by. Enables mutual TLS.
+    pub tls_client_ca: Option<PathBuf>,
+    // Seconds to wait for in-flight requests, e.g. uploads, to finish on shutdown
+    pub shutdown_timeout: u64,
+    // SMTP server as host:port and sender of notification emails. Email is disabled if unset.
+    pub smtp_addr: Option<String>,
+    pub smtp_from: Option<String>,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<Redacted>,
+    pub smtp_starttls: bool,
+    // Comma separated hosts that webhooks may target even though they are internal
+    pub notification_allowed_hosts: Option<String>,
+    // Minutes between reconciliations of repositories and their secrets. 0 disables them.
+    pub reconcile_interval: u64,
+    // Repair drift found by periodic reconciliations instead of only reporting it
+    pub reconcile_repair: bool,
+    // Accept file:// URLs of repositories, e.g. for testing
+    pub allow_file_urls: bool,
+}
+
+/// String that is hidden from debug output so it doesn't end up in the logs
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(transparent)]
+pub struct Redacted(String);
+
+impl Redacted {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[redacted]")
+    }
 }
 
 impl Settings {
     pub fn new() -> std::result::Result<Self, ConfigError> {
+        let config_file =
+            std::env::var("RECESSER_CONFIG_FILE").unwrap_or_else(|_| String::from("config.toml"));
         let config = Config::builder()
             .set_default("addr", "0.0.0.0:8080")?
             .set_default("objectstorage_addr", "http://minio.minio:9000")?
             .set_default("database_addr", "mongodb://mongo.mongo:27017")?
             .set_default("secretstorage_addr", "http://vault.vault:8200")?
             .set_default("log_level", "info")?
-            .add_source(File::with_name("config.toml").required(false))
+            .set_default("shutdown_timeout", 120)?
+            .set_default("smtp_starttls", true)?
+            .set_default("reconcile_interval", 60)?
+            .set_default("reconcile_repair", false)?
+            .set_default("allow_file_urls", false)?
+            .add_source(File::with_name(&config_file).required(false))
             .add_source(Environment::with_prefix("recesser"))
             .build()?;
         config.try_deserialize()
@@ -0,0 +1,139 @@
+use std::any::Any;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use actix_tls::accept::rustls::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use anyhow::{anyhow, Result};
+use recesser_core::tls::{read_certificates, read_private_key};
+use rustls::server::{AllowAnyAnonymousOrAuthenticatedClient, ClientHello, ResolvesServerCert};
+use rustls::sign::{self, CertifiedKey};
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+
+use crate::settings::Settings;
+
+/// Interval in which the certificate files are checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Build the TLS configuration of the server if a certificate is configured
+pub fn server_config(s: &Settings) -> Result<Option<(ServerConfig, Arc<ReloadingCertificate>)>> {
+    let (cert_path, key_path) = match (&s.tls_cert, &s.tls_key) {
+        (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+        (None, None) => return Ok(None),
+        _ => anyhow::bail!("TLS requires both a certificate and a private key"),
+    };
+    let certificate = Arc::new(ReloadingCertificate::new(cert_path, key_path)?);
+
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &s.tls_client_ca {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for der in read_certificates(ca_path)? {
+                roots
+                    .add(&Certificate(der))
+                    .map_err(|e| anyhow!("Invalid client CA certificate: {e:?}"))?;
+            }
+            // Probes, Prometheus and the steps of workflows can't present certificates, so they
+            // are only required by the authentication middleware for users and admins
+            builder.with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots))
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder.with_cert_resolver(certificate.clone());
+
+    tracing::info!(mutual = s.tls_client_ca.is_some(), "Enabled TLS");
+    Ok(Some((config, certificate)))
+}
+
+/// Marks connections whose client presented a certificate signed by the client CA
+pub struct ClientCertificate;
+
+/// Record whether the client of a new connection presented a certificate. Certificates that
+/// aren't signed by the client CA already fail the handshake.
+pub fn record_client_certificate(connection: &dyn Any, data: &mut Extensions) {
+    if let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() {
+        let (_, session) = stream.get_ref();
+        if matches!(session.peer_certificates(), Some(certificates) if !certificates.is_empty()) {
+            data.insert(ClientCertificate);
+        }
+    }
+}
+
+/// Whether users and admins need to present a client certificate
+pub fn client_certificates_required(s: &Settings) -> bool {
+    s.tls_cert.is_some() && s.tls_client_ca.is_some()
+}
+
+/// Certificate that is reloaded when its files change, e.g. when cert-manager renews it. New
+/// connections use the new certificate while existing connections are not interrupted.
+pub struct ReloadingCertificate {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<LoadedCertificate>,
+}
+
+struct LoadedCertificate {
+    key: Arc<CertifiedKey>,
+    modified: [SystemTime; 2],
+}
+
+impl ReloadingCertificate {
+    fn new(cert_path: &Path, key_path: &Path) -> Result<Self> {
+        let modified = [modified(cert_path)?, modified(key_path)?];
+        let key = load(cert_path, key_path)?;
+        Ok(Self {
+            cert_path: cert_path.to_path_buf(),
+            key_path: key_path.to_path_buf(),
+            current: RwLock::new(LoadedCertificate { key, modified }),
+        })
+    }
+
+    /// Periodically check the certificate files and reload them if they changed
+    pub async fn watch(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            match self.reload_if_changed() {
+                Ok(true) => tracing::info!("Reloaded TLS certificate"),
+                Ok(false) => (),
+                Err(e) => tracing::warn!(
+                    error = %e,
+                    "Failed to reload TLS certificate. Continuing to use the previous one"
+                ),
+            }
+        }
+    }
+
+    fn reload_if_changed(&self) -> Result<bool> {
+        let modified = [modified(&self.cert_path)?, modified(&self.key_path)?];
+        if modified == self.current.read().unwrap().modified {
+            return Ok(false);
+        }
+        let key = load(&self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap() = LoadedCertificate { key, modified };
+        Ok(true)
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertificate {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().key.clone())
+    }
+}
+
+fn load(cert_path: &Path, key_path: &Path) -> Result<Arc<CertifiedKey>> {
+    let chain = read_certificates(cert_path)?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    let key = sign::any_supported_type(&PrivateKey(read_private_key(key_path)?))
+        .map_err(|e| anyhow!("Unsupported private key: {e}"))?;
+    Ok(Arc::new(CertifiedKey::new(chain, key)))
+}
+
+fn modified(path: &Path) -> Result<SystemTime> {
+    Ok(fs::metadata(path)?.modified()?)
+}
@@ -0,0 +1,104 @@
+use actix_web::http::header::HeaderMap;
+use recesser_core::encoding::hex;
+use ring::{constant_time, hmac};
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Git host that sent a webhook, recognized by the headers it sets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Host {
+    GitHub,
+    GitLab,
+    Gitea,
+}
+
+/// Webhook delivery whose signature was verified
+#[derive(Debug, PartialEq)]
+pub enum Delivery {
+    /// Commits or a tag were pushed to the ref
+    Push { reference: Option<String> },
+    /// Any other event, e.g. the ping GitHub sends when a webhook is created
+    Ignored { event: String },
+}
+
+#[derive(Debug, Error)]
+pub enum WebhookError {
+    #[error("Request is not a GitHub, GitLab or Gitea webhook")]
+    UnknownHost,
+    #[error("Signature of the {0:?} webhook is missing or invalid")]
+    InvalidSignature(Host),
+    #[error("Payload of the push event is invalid: {0}")]
+    InvalidPayload(#[from] serde_json::Error),
+}
+
+#[derive(Deserialize)]
+struct Push {
+    #[serde(rename = "ref")]
+    reference: Option<String>,
+}
+
+/// Verify a webhook delivery with the secret of the repository and extract the pushed ref
+pub fn receive(headers: &HeaderMap, body: &[u8], secret: &[u8]) -> Result<Delivery, WebhookError> {
+    let (host, event) = identify(headers)?;
+    if !verify(host, headers, body, secret) {
+        return Err(WebhookError::InvalidSignature(host));
+    }
+
+    let is_push = match host {
+        Host::GitHub | Host::Gitea => event == "push",
+        Host::GitLab => event == "Push Hook" || event == "Tag Push Hook",
+    };
+    if !is_push {
+        return Ok(Delivery::Ignored { event });
+    }
+    let push: Push = serde_json::from_slice(body)?;
+    Ok(Delivery::Push {
+        reference: push.reference,
+    })
+}
+
+/// Gitea also sets the GitHub headers for compatibility, so it's checked first
+fn identify(headers: &HeaderMap) -> Result<(Host, String), WebhookError> {
+    let hosts = [
+        (Host::Gitea, "X-Gitea-Event"),
+        (Host::GitLab, "X-Gitlab-Event"),
+        (Host::GitHub, "X-GitHub-Event"),
+    ];
+    hosts
+        .iter()
+        .find_map(|(host, name)| Some((*host, header(headers, name)?.to_string())))
+        .ok_or(WebhookError::UnknownHost)
+}
+
+/// GitHub and Gitea sign the payload with an HMAC, GitLab sends the secret itself
+fn verify(host: Host, headers: &HeaderMap, body: &[u8], secret: &[u8]) -> bool {
+    let (provided, expected) = match host {
+        Host::GitHub => (
+            header(headers, "X-Hub-Signature-256"),
+            format!("sha256={}", hmac_sha256(secret, body)),
+        ),
+        Host::Gitea => (
+            header(headers, "X-Gitea-Signature"),
+            hmac_sha256(secret, body),
+        ),
+        Host::GitLab => (
+            header(headers, "X-Gitlab-Token"),
+            String::from_utf8_lossy(secret).into_owned(),
+        ),
+    };
+    match provided {
+        Some(provided) => {
+            constant_time::verify_slices_are_equal(provided.as_bytes(), expected.as_bytes()).is_ok()
+        }
+        None => false,
+    }
+}
+
+fn hmac_sha256(secret: &[u8], body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    hex::encode(hmac::sign(&key, body).as_ref())
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
@@ -0,0 +1,49 @@
+use chrono::Utc;
+use recesser_apiserver::database::Database;
+use recesser_core::audit::{AuditQuery, AuditRecord, Outcome};
+
+const RECORDS_PER_REPLICA: usize = 20;
+
+/// Two apiserver replicas share the database but not the lock that serializes their appends
+#[actix_web::test]
+#[ignore = "needs a MongoDB server at RECESSER_TEST_DATABASE_ADDR that may be written to"]
+async fn appends_concurrently_from_two_replicas() {
+    let addr = std::env::var("RECESSER_TEST_DATABASE_ADDR").unwrap();
+    let first = Database::new(&addr).await.unwrap();
+    let second = Database::new(&addr).await.unwrap();
+    let target = uuid::Uuid::new_v4().to_string();
+
+    let append_all = |database: Database, target: String| async move {
+        for _ in 0..RECORDS_PER_REPLICA {
+            database.audit.append(record(&target)).await.unwrap();
+        }
+    };
+    futures_util::join!(
+        append_all(first.clone(), target.clone()),
+        append_all(second, target.clone())
+    );
+
+    let all = first.audit.search(&AuditQuery::default()).await.unwrap();
+    AuditRecord::verify_chain(&all).unwrap();
+    let appended = all
+        .iter()
+        .filter(|r| r.target.as_deref() == Some(target.as_str()))
+        .count();
+    assert_eq!(appended, 2 * RECORDS_PER_REPLICA);
+}
+
+fn record(target: &str) -> AuditRecord {
+    AuditRecord {
+        sequence: 0,
+        timestamp: Utc::now(),
+        actor: None,
+        scope: None,
+        action: String::from("test.append"),
+        target: Some(String::from(target)),
+        outcome: Outcome::Success,
+        status: 200,
+        source_ip: None,
+        previous_hash: String::new(),
+        hash: String::new(),
+    }
+}
@@ -0,0 +1,279 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use chrono::Utc;
+use recesser_apiserver::notifications::{self, Notifier, SmtpConfig, MAX_ATTEMPTS};
+use recesser_core::event::{Event, EventKind};
+use recesser_core::notification::{Channel, Notification, Target, Trigger};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+const SECRET: &[u8] = b"0123456789abcdef";
+
+#[actix_web::test]
+async fn delivers_signed_webhook() {
+    let sink = Sink::start(0).await;
+    let channel = channel(Target::Webhook { url: sink.url() });
+    let notification = notification();
+
+    let delivery = notifier()
+        .deliver(&channel, Some(SECRET), &notification)
+        .await;
+
+    assert_eq!(delivery.error, None);
+    assert_eq!(delivery.attempts, 1);
+    let requests = sink.requests();
+    let (signature, body) = &requests[0];
+    assert_eq!(
+        signature.as_deref(),
+        Some(notifications::sign(SECRET, body).as_str())
+    );
+    let received: Notification = serde_json::from_slice(body).unwrap();
+    assert_eq!(received.trigger, Trigger::RunFailed);
+    assert_eq!(received.event.id, notification.event.id);
+}
+
+#[actix_web::test]
+async fn retries_failed_deliveries() {
+    let sink = Sink::start(2).await;
+    let channel = channel(Target::Slack { url: sink.url() });
+
+    let delivery = notifier().deliver(&channel, None, &notification()).await;
+
+    assert_eq!(delivery.error, None);
+    assert_eq!(delivery.attempts, 3);
+    let requests = sink.requests();
+    let body: serde_json::Value = serde_json::from_slice(&requests[2].1).unwrap();
+    assert_eq!(body["text"], notification().summary);
+}
+
+#[actix_web::test]
+async fn records_failure_after_last_attempt() {
+    let sink = Sink::start(MAX_ATTEMPTS).await;
+    let channel = channel(Target::Slack { url: sink.url() });
+
+    let delivery = notifier().deliver(&channel, None, &notification()).await;
+
+    assert!(delivery.error.is_some());
+    assert_eq!(delivery.attempts, MAX_ATTEMPTS);
+    assert_eq!(sink.requests().len(), MAX_ATTEMPTS as usize);
+}
+
+#[actix_web::test]
+async fn does_not_follow_redirects() {
+    let target = Sink::start(0).await;
+    // Only reachable as localhost, which would be rejected as internal
+    let redirect = Redirect::start(format!("http://localhost:{}/hook", target.addr.port())).await;
+    let channel = channel(Target::Webhook {
+        url: format!("http://{redirect}/hook"),
+    });
+
+    let delivery = notifier()
+        .deliver(&channel, Some(SECRET), &notification())
+        .await;
+
+    assert!(delivery.error.is_some());
+    assert!(target.requests().is_empty());
+}
+
+#[actix_web::test]
+async fn rejects_internal_webhook_targets() {
+    let notifier = Notifier::new(None).unwrap();
+    for url in [
+        "http://127.0.0.1:8080/hook",
+        "http://[::1]/hook",
+        "http://169.254.169.254/latest/meta-data",
+        "http://10.96.0.1/",
+        "http://[fd00::1]/",
+        "http://[::ffff:192.168.0.1]/",
+        "http://vault.vault.svc:8200/v1/secret",
+        "http://mongo.mongo.svc.cluster.local:27017",
+        "http://apiserver/v1/health",
+        "http://localhost/hook",
+    ] {
+        let url = reqwest::Url::parse(url).unwrap();
+        assert!(notifier.check_url(&url).await.is_err(), "{url} is allowed");
+    }
+    let url = reqwest::Url::parse("https://93.184.216.34/hook").unwrap();
+    assert!(notifier.check_url(&url).await.is_ok());
+
+    let notifier = notifier.with_allowed_hosts("mattermost.chat.svc, 10.96.0.1");
+    for url in ["http://mattermost.chat.svc/hooks/x", "http://10.96.0.1/"] {
+        let url = reqwest::Url::parse(url).unwrap();
+        assert!(notifier.check_url(&url).await.is_ok());
+    }
+}
+
+#[actix_web::test]
+async fn sends_email() {
+    let (addr, received) = fake_smtp_server().await;
+    let config = SmtpConfig {
+        addr: addr.to_string(),
+        from: String::from("recesser@example.com"),
+        username: None,
+        password: None,
+        starttls: false,
+    };
+    let notifier = Notifier::new(Some(config))
+        .unwrap()
+        .with_retry_delay(Duration::from_millis(10));
+    let channel = channel(Target::Email {
+        to: vec![String::from("student@example.com")],
+    });
+
+    let delivery = notifier.deliver(&channel, None, &notification()).await;
+
+    assert_eq!(delivery.error, None);
+    let transcript = received.lock().unwrap().clone();
+    assert!(transcript.contains("RCPT TO:<student@example.com>"));
+    assert!(
+        transcript.contains("Subject: Workflow train-x7k2p of recesser/example failed: OOMKilled")
+    );
+    // Lines starting with a dot are escaped
+    assert!(transcript.contains("\r\n..hidden\r\n"));
+}
+
+fn notifier() -> Notifier {
+    // The sinks listen on loopback which is otherwise rejected as internal
+    Notifier::new(None)
+        .unwrap()
+        .with_retry_delay(Duration::from_millis(10))
+        .with_allowed_hosts("127.0.0.1")
+}
+
+fn channel(target: Target) -> Channel {
+    Channel {
+        id: String::from("5f0c7a4e-channel"),
+        repository: String::from("recesser/example"),
+        target,
+        triggers: Trigger::ALL.to_vec(),
+    }
+}
+
+fn notification() -> Notification {
+    let event = Event {
+        id: 7,
+        timestamp: Utc::now(),
+        project: None,
+        kind: EventKind::WorkflowFailed {
+            repository: String::from("recesser/example"),
+            workflow: String::from("train-x7k2p"),
+            message: Some(String::from("OOMKilled\n.hidden")),
+        },
+    };
+    Notification::from_event(&event).unwrap()
+}
+
+/// Signature header and body of received requests
+type Requests = Arc<Mutex<Vec<(Option<String>, Vec<u8>)>>>;
+
+/// HTTP endpoint that records the signature header and body of all requests. The first requests
+/// are answered with an error.
+struct Sink {
+    addr: SocketAddr,
+    requests: Requests,
+}
+
+impl Sink {
+    async fn start(failures: u32) -> Self {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let state = web::Data::new((requests.clone(), failures));
+        let server = HttpServer::new(move || {
+            App::new().app_data(state.clone()).default_service(web::to(
+                |req: HttpRequest, body: web::Bytes, state: web::Data<(Requests, u32)>| async move {
+                    let signature = req
+                        .headers()
+                        .get("X-Recesser-Signature")
+                        .map(|v| v.to_str().unwrap().to_string());
+                    let mut requests = state.0.lock().unwrap();
+                    requests.push((signature, body.to_vec()));
+                    match requests.len() as u32 > state.1 {
+                        true => HttpResponse::Ok().finish(),
+                        false => HttpResponse::InternalServerError().finish(),
+                    }
+                },
+            ))
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        Self { addr, requests }
+    }
+
+    fn url(&self) -> String {
+        format!("http://{}/hook", self.addr)
+    }
+
+    fn requests(&self) -> Vec<(Option<String>, Vec<u8>)> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// HTTP endpoint that redirects all requests to the same location
+struct Redirect;
+
+impl Redirect {
+    async fn start(location: String) -> SocketAddr {
+        let server = HttpServer::new(move || {
+            let location = location.clone();
+            App::new().default_service(web::to(move || {
+                let location = location.clone();
+                async move {
+                    HttpResponse::Found()
+                        .insert_header(("Location", location))
+                        .finish()
+                }
+            }))
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        addr
+    }
+}
+
+/// SMTP server that accepts every message and records the complete session
+async fn fake_smtp_server() -> (SocketAddr, Arc<Mutex<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let transcript = Arc::new(Mutex::new(String::new()));
+    let received = transcript.clone();
+    actix_web::rt::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        stream.write_all(b"220 localhost ready\r\n").await.unwrap();
+        let mut in_data = false;
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            transcript.lock().unwrap().push_str(&line);
+            let reply: &[u8] = if in_data {
+                if line != ".\r\n" {
+                    continue;
+                }
+                in_data = false;
+                b"250 Queued\r\n"
+            } else if line.starts_with("EHLO") {
+                b"250-localhost\r\n250 8BITMIME\r\n"
+            } else if line.starts_with("DATA") {
+                in_data = true;
+                b"354 Go ahead\r\n"
+            } else if line.starts_with("QUIT") {
+                stream.write_all(b"221 Bye\r\n").await.unwrap();
+                break;
+            } else {
+                b"250 OK\r\n"
+            };
+            stream.write_all(reply).await.unwrap();
+        }
+    });
+    (addr, received)
+}
@@ -0,0 +1,181 @@
+use actix_web::dev::{ResourceMap, Service, ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{test, web, App, HttpMessage};
+use recesser_apiserver::auth::{HmacKey, Token};
+use recesser_apiserver::routes::{self, openapi::ApiDoc};
+use recesser_core::user::Scope;
+use utoipa::openapi::PathItemType;
+use utoipa::OpenApi;
+
+/// Methods that routes are probed with to find out which ones they serve
+const METHODS: [Method; 5] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::PATCH,
+];
+
+/// Every operation in the OpenAPI document needs to be served by a route with the same path and
+/// method. Authentication is left out so that requests reach the routes.
+#[actix_web::test]
+async fn routes_match_openapi_spec() {
+    let app = test::init_service(app()).await;
+
+    let spec = ApiDoc::openapi();
+    assert!(!spec.paths.paths.is_empty());
+
+    for (path, item) in &spec.paths.paths {
+        for operation in item.operations.keys() {
+            let method = to_method(operation);
+            let req = test::TestRequest::default()
+                .method(method.clone())
+                .uri(&fill_parameters(path))
+                .to_request();
+            let res = app.call(req).await.unwrap();
+
+            let matched = res.request().match_pattern();
+            assert_eq!(
+                matched.as_deref(),
+                Some(path.as_str()),
+                "{method} {path} is not routed"
+            );
+            assert_ne!(
+                res.status(),
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{method} {path} is not routed"
+            );
+        }
+    }
+}
+
+/// Every route that is registered needs to be documented with each method it serves. Routes that
+/// don't serve a method fall through to 404 or 405, all others reach their handler or fail in an
+/// extractor because the app has no state.
+#[actix_web::test]
+async fn openapi_spec_documents_all_routes() {
+    let app = test::init_service(app()).await;
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    let res = app.call(req).await.unwrap();
+    let patterns = registered_patterns(res.request().resource_map());
+    assert!(patterns.contains(&String::from("/v1/runs/{id}")));
+
+    let spec = ApiDoc::openapi();
+    let key = HmacKey::new(&[0; 32]);
+    for pattern in &patterns {
+        for method in METHODS {
+            let req = test::TestRequest::default()
+                .method(method.clone())
+                .uri(&fill_parameters(pattern))
+                .to_request();
+            // Admin scopes reject requests without a token before routing them
+            req.extensions_mut()
+                .insert(Token::create(Scope::Admin, &key).unwrap());
+            let res = app.call(req).await.unwrap();
+            let served = res.request().match_pattern().as_deref() == Some(pattern.as_str())
+                && !matches!(
+                    res.status(),
+                    StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+                );
+            if !served {
+                continue;
+            }
+            let documented = spec
+                .paths
+                .paths
+                .get(pattern)
+                .map(|item| item.operations.keys().any(|o| to_method(o) == method))
+                .unwrap_or(false);
+            assert!(documented, "{method} {pattern} is not documented");
+        }
+    }
+}
+
+#[actix_web::test]
+async fn serves_openapi_spec() {
+    let app = test::init_service(
+        App::new().service(web::scope(routes::API_PREFIX).configure(routes::public_api_config)),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .uri("/v1/openapi.json")
+        .to_request();
+    let spec: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(spec["paths"]["/v1/artifacts"]["put"].is_object());
+}
+
+fn app() -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    App::new().configure(routes::public_config).service(
+        web::scope(routes::API_PREFIX)
+            .configure(routes::public_api_config)
+            .configure(routes::config),
+    )
+}
+
+/// Full patterns of all resources. actix-web doesn't expose its resource tree, so it's read from
+/// the debug output. Each level of nesting is indented by 12 more spaces. Named resources are
+/// listed a second time without their parents and skipped.
+fn registered_patterns(resource_map: &ResourceMap) -> Vec<String> {
+    let debug = format!("{resource_map:#?}");
+    let mut lines = debug.lines();
+    let mut prefixes: Vec<String> = Vec::new();
+    let mut patterns = Vec::new();
+    while let Some(line) = lines.next() {
+        let indent = line.len() - line.trim_start().len();
+        match line.trim() {
+            "named: {" => {
+                let closing = format!("{}}}", " ".repeat(indent));
+                lines.by_ref().find(|l| l.starts_with(&closing));
+            }
+            "patterns: Single(" => {
+                let pattern = lines.next().unwrap().trim().trim_end_matches(',');
+                let pattern = pattern.trim_matches('"');
+                lines.next();
+                let is_prefix = lines.next().unwrap().trim() == "is_prefix: true,";
+
+                prefixes.truncate((indent - 8) / 12);
+                prefixes.push(String::from(pattern));
+                if !is_prefix {
+                    patterns.push(prefixes.concat());
+                }
+            }
+            _ => (),
+        }
+    }
+    patterns
+}
+
+fn fill_parameters(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if segment.starts_with('{') {
+                "placeholder"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn to_method(operation: &PathItemType) -> Method {
+    match operation {
+        PathItemType::Get => Method::GET,
+        PathItemType::Post => Method::POST,
+        PathItemType::Put => Method::PUT,
+        PathItemType::Delete => Method::DELETE,
+        PathItemType::Options => Method::OPTIONS,
+        PathItemType::Head => Method::HEAD,
+        PathItemType::Patch => Method::PATCH,
+        PathItemType::Trace => Method::TRACE,
+        PathItemType::Connect => Method::CONNECT,
+    }
+}
@@ -0,0 +1,76 @@
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use actix_web::body::{self, BodyStream, MessageBody};
+use actix_web::{test, web, Error, HttpMessage, HttpRequest};
+use futures_util::{future, stream};
+use recesser_apiserver::auth::{HmacKey, Token};
+use recesser_apiserver::ratelimit::{PermittedBody, RateLimiter, RateLimits, TransferLimiter};
+use recesser_core::user::Scope;
+
+#[actix_web::test]
+async fn drops_buckets_of_idle_users() {
+    let limiter = RateLimiter::new(RateLimits {
+        user: Some(2),
+        machine: None,
+        admin: None,
+    });
+    let start = Instant::now();
+    limiter.check_at("idle", &Scope::User, start).unwrap();
+    limiter.check_at("idle", &Scope::User, start).unwrap();
+    assert!(limiter.check_at("idle", &Scope::User, start).is_err());
+    assert_eq!(limiter.tracked_users(), 1);
+
+    // The bucket of the idle user is full again by now and doesn't need to be kept
+    let later = start + Duration::from_secs(61);
+    limiter.check_at("active", &Scope::User, later).unwrap();
+    assert_eq!(limiter.tracked_users(), 1);
+    limiter.check_at("idle", &Scope::User, later).unwrap();
+    limiter.check_at("idle", &Scope::User, later).unwrap();
+    assert!(limiter.check_at("idle", &Scope::User, later).is_err());
+}
+
+#[actix_web::test]
+async fn holds_transfer_permit_until_body_is_sent() {
+    let limiter = TransferLimiter::new(Some(1));
+    let req = request();
+
+    let chunks = vec![
+        Ok::<_, Error>(web::Bytes::from_static(b"first")),
+        Ok(web::Bytes::from_static(b"second")),
+    ];
+    let permit = limiter.acquire(&req).unwrap();
+    let mut download = PermittedBody::new(BodyStream::new(stream::iter(chunks)), permit);
+
+    // The download is still open after its first chunk
+    let chunk = future::poll_fn(|cx| Pin::new(&mut download).poll_next(cx)).await;
+    assert_eq!(chunk.unwrap().unwrap(), "first");
+    assert!(limiter.acquire(&req).is_err());
+
+    let rest = body::to_bytes(download).await.unwrap();
+    assert_eq!(rest, "second");
+    assert!(limiter.acquire(&req).unwrap().is_some());
+}
+
+#[actix_web::test]
+async fn releases_transfer_permit_when_client_disconnects() {
+    let limiter = TransferLimiter::new(Some(1));
+    let req = request();
+
+    let permit = limiter.acquire(&req).unwrap();
+    let download = PermittedBody::new(
+        BodyStream::new(stream::pending::<Result<_, Error>>()),
+        permit,
+    );
+    assert!(limiter.acquire(&req).is_err());
+
+    drop(download);
+    assert!(limiter.acquire(&req).unwrap().is_some());
+}
+
+fn request() -> HttpRequest {
+    let req = test::TestRequest::default().to_http_request();
+    let token = Token::create(Scope::User, &HmacKey::new(&[7; 32])).unwrap();
+    req.extensions_mut().insert(token);
+    req
+}
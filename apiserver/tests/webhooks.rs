@@ -0,0 +1,105 @@
+use actix_web::http::header::HeaderMap;
+use actix_web::test::TestRequest;
+use recesser_apiserver::webhooks::{self, Delivery, Host, WebhookError};
+
+const SECRET: &[u8] = b"0123456789abcdef";
+
+/// Recorded deliveries of the Git hosts, signed with `SECRET`
+const GITHUB_PUSH: &[u8] = include_bytes!("fixtures/github_push.json");
+const GITHUB_PUSH_SIGNATURE: &str =
+    "sha256=b91a93281ba6202078584632bc591b448ea517146e3d39364809a449e97e024d";
+const GITHUB_PING: &[u8] = include_bytes!("fixtures/github_ping.json");
+const GITHUB_PING_SIGNATURE: &str =
+    "sha256=a92c845461575702f231f427be08ed7eae1d2a2eccb9817338417fbe2c82da65";
+const GITLAB_TAG_PUSH: &[u8] = include_bytes!("fixtures/gitlab_tag_push.json");
+const GITEA_PUSH: &[u8] = include_bytes!("fixtures/gitea_push.json");
+const GITEA_PUSH_SIGNATURE: &str =
+    "86a0f506440fdb59c89634e535f5ac04475b149731129a6431110b5583871d11";
+
+#[test]
+fn accepts_signed_pushes() {
+    let github = headers(&[
+        ("X-GitHub-Event", "push"),
+        ("X-Hub-Signature-256", GITHUB_PUSH_SIGNATURE),
+    ]);
+    assert_eq!(
+        webhooks::receive(&github, GITHUB_PUSH, SECRET).unwrap(),
+        push("refs/heads/main")
+    );
+
+    let gitlab = headers(&[
+        ("X-Gitlab-Event", "Tag Push Hook"),
+        ("X-Gitlab-Token", "0123456789abcdef"),
+    ]);
+    assert_eq!(
+        webhooks::receive(&gitlab, GITLAB_TAG_PUSH, SECRET).unwrap(),
+        push("refs/tags/v1.0.0")
+    );
+
+    // Gitea also sends the GitHub headers, but signs without prefix
+    let gitea = headers(&[
+        ("X-Gitea-Event", "push"),
+        ("X-Gitea-Signature", GITEA_PUSH_SIGNATURE),
+        ("X-GitHub-Event", "push"),
+    ]);
+    assert_eq!(
+        webhooks::receive(&gitea, GITEA_PUSH, SECRET).unwrap(),
+        push("refs/heads/experiments")
+    );
+}
+
+#[test]
+fn ignores_other_events() {
+    let github = headers(&[
+        ("X-GitHub-Event", "ping"),
+        ("X-Hub-Signature-256", GITHUB_PING_SIGNATURE),
+    ]);
+    assert_eq!(
+        webhooks::receive(&github, GITHUB_PING, SECRET).unwrap(),
+        Delivery::Ignored {
+            event: String::from("ping")
+        }
+    );
+}
+
+#[test]
+fn rejects_invalid_signatures() {
+    let github = headers(&[
+        ("X-GitHub-Event", "push"),
+        ("X-Hub-Signature-256", GITHUB_PUSH_SIGNATURE),
+    ]);
+    let tampered = String::from_utf8_lossy(GITHUB_PUSH).replace("main", "release");
+    assert!(matches!(
+        webhooks::receive(&github, tampered.as_bytes(), SECRET),
+        Err(WebhookError::InvalidSignature(Host::GitHub))
+    ));
+    assert!(matches!(
+        webhooks::receive(&github, GITHUB_PUSH, b"another secret"),
+        Err(WebhookError::InvalidSignature(Host::GitHub))
+    ));
+
+    let gitlab = headers(&[("X-Gitlab-Event", "Tag Push Hook")]);
+    assert!(matches!(
+        webhooks::receive(&gitlab, GITLAB_TAG_PUSH, SECRET),
+        Err(WebhookError::InvalidSignature(Host::GitLab))
+    ));
+
+    assert!(matches!(
+        webhooks::receive(&headers(&[]), GITHUB_PUSH, SECRET),
+        Err(WebhookError::UnknownHost)
+    ));
+}
+
+fn push(reference: &str) -> Delivery {
+    Delivery::Push {
+        reference: Some(String::from(reference)),
+    }
+}
+
+fn headers(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut req = TestRequest::default();
+    for header in headers {
+        req = req.insert_header(*header);
+    }
+    req.to_http_request().headers().clone()
+}
@@ -1,12 +1,20 @@
 mod artifact;
+mod audit;
+mod event;
+mod init;
+mod reconcile;
 mod repository;
+mod run;
+mod usage;
 mod user;
 
 use std::io::Write;
 
 use anyhow::Result;
+use recesser_core::audit::AuditQuery;
+use recesser_core::event::EventFilter;
 
-use crate::http::Client;
+use crate::http::{Client, Tls};
 use crate::parser::{AdminCommands, Cli, Commands};
 
 pub struct Global {
@@ -36,31 +44,83 @@ impl Cli {
         };
 
         let token = match self.token {
-            Some(token) => token,
-            None => std::env::var("RECESSER_TOKEN").map_err(|_| {
-                anyhow::anyhow!(
-                    "Access token needs to be specified via environment or as command line argument"
-                )
-            })?,
+            Some(token) => Some(token),
+            None => std::env::var("RECESSER_TOKEN").ok(),
+        };
+        if token.is_none() && self.commands.requires_token() {
+            anyhow::bail!(
+                "Access token needs to be specified via environment or as command line argument"
+            )
+        }
+
+        let tls = Tls {
+            ca_cert: self
+                .ca_cert
+                .or_else(|| std::env::var_os("RECESSER_CA_CERT").map(Into::into)),
+            client_cert: self
+                .client_cert
+                .or_else(|| std::env::var_os("RECESSER_CLIENT_CERT").map(Into::into)),
         };
 
         let global = Global {
-            http: Client::new(&addr, token),
+            http: Client::new(&addr, token, &tls)?,
         };
 
         match self.commands {
             Commands::Artifact(cmd) => cmd.call(global)?,
             Commands::Repository(cmd) => cmd.call(global)?,
+            Commands::Run(cmd) => cmd.call(global)?,
+            Commands::Watch { types, project } => {
+                let filter = EventFilter {
+                    types: match types.is_empty() {
+                        true => None,
+                        false => Some(types.join(",")),
+                    },
+                    project,
+                };
+                event::watch(global, filter)?
+            }
             Commands::Admin(cmd) => cmd.call(global)?,
         };
         Ok(())
     }
 }
 
+impl Commands {
+    fn requires_token(&self) -> bool {
+        !matches!(self, Commands::Admin(AdminCommands::Init { .. }))
+    }
+}
+
 impl AdminCommands {
     pub fn call(self, global: Global) -> Result<()> {
         match self {
+            AdminCommands::Init { bootstrap_secret } => init::init(global, bootstrap_secret)?,
             AdminCommands::User(cmd) => cmd.call(global)?,
+            AdminCommands::Usage => usage::usage(global)?,
+            AdminCommands::Reconcile { repair } => reconcile::reconcile(global, repair)?,
+            AdminCommands::Audit {
+                actor,
+                action,
+                target,
+                since,
+                until,
+                limit,
+                verify,
+            } => {
+                let query = AuditQuery {
+                    actor,
+                    action,
+                    target,
+                    since,
+                    until,
+                    limit,
+                };
+                match verify {
+                    true => audit::verify(global)?,
+                    false => audit::search(global, query)?,
+                }
+            }
         }
         Ok(())
     }
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::{self, BufWriter, Write};
 use std::path::{Path, PathBuf};
@@ -14,8 +15,29 @@ impl ArtifactCommands {
     pub fn call(self, global: Global) -> Result<()> {
         match self {
             ArtifactCommands::Hash { file } => hash(file)?,
-            ArtifactCommands::Upload { file, metadata } => upload(global, &file, metadata)?,
-            ArtifactCommands::List {} => list(global)?,
+            ArtifactCommands::Upload {
+                file,
+                metadata,
+                project,
+                repository,
+            } => upload(global, &file, metadata, project, repository)?,
+            ArtifactCommands::UploadOutputs {
+                dir,
+                provenance,
+                parameters,
+                project,
+                repository,
+                handles,
+            } => upload_outputs(
+                global,
+                &dir,
+                &provenance,
+                &parameters,
+                project,
+                repository,
+                handles,
+            )?,
+            ArtifactCommands::List => list(global)?,
             ArtifactCommands::Download { handles } => download(global, handles)?,
             ArtifactCommands::Delete { handles } => delete(global, handles)?,
         }
@@ -29,7 +51,13 @@ fn hash(filepath: PathBuf) -> Result<()> {
     Ok(())
 }
 
-fn upload(g: Global, filepath: &Path, metadata_path: Option<PathBuf>) -> Result<()> {
+fn upload(
+    g: Global,
+    filepath: &Path,
+    metadata_path: Option<PathBuf>,
+    project: Option<String>,
+    repository: Option<String>,
+) -> Result<()> {
     let object_handle = Handle::compute_from_file(filepath)?;
     log::debug!("Object handle: {object_handle:#?}");
 
@@ -41,13 +69,84 @@ fn upload(g: Global, filepath: &Path, metadata_path: Option<PathBuf>) -> Result<
     log::debug!("{metadata:#?}");
 
     let artifact_handle = Handle::compute_from_buf(&serde_json::to_vec(&metadata)?);
-    g.http
-        .upload_file(&artifact_handle.to_string(), metadata, filepath)?;
+    g.http.upload_file(
+        &artifact_handle.to_string(),
+        metadata,
+        project.as_deref(),
+        repository.as_deref(),
+        filepath,
+    )?;
     println!("{artifact_handle}");
 
     Ok(())
 }
 
+fn upload_outputs(
+    g: Global,
+    dir: &Path,
+    provenance: &str,
+    parameters: &[String],
+    project: Option<String>,
+    repository: Option<String>,
+    handles_path: Option<PathBuf>,
+) -> Result<()> {
+    let provenance: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(provenance)
+            .map_err(|e| anyhow::anyhow!("Provenance is not a JSON object: {e}"))?;
+    let parameters = parameters
+        .iter()
+        .map(|parameter| match parameter.split_once('=') {
+            Some((name, value)) => Ok((name.to_string(), serde_json::Value::from(value))),
+            None => anyhow::bail!("Parameter {parameter} is not given as NAME=VALUE"),
+        })
+        .collect::<Result<serde_json::Map<_, _>>>()?;
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.sort();
+
+    let mut handles = BTreeMap::new();
+    for filepath in files {
+        let output = filepath.strip_prefix(dir)?.to_string_lossy().into_owned();
+        let mut provenance = provenance.clone();
+        provenance.insert(String::from("output"), output.clone().into());
+        let metadata = Metadata {
+            object_handle: Handle::compute_from_file(&filepath)?,
+            custom: Some(match parameters.is_empty() {
+                true => serde_json::json!({ "provenance": provenance }),
+                false => serde_json::json!({ "provenance": provenance, "parameters": parameters }),
+            }),
+        };
+
+        let artifact_handle = Handle::compute_from_buf(&serde_json::to_vec(&metadata)?);
+        g.http.upload_file(
+            &artifact_handle.to_string(),
+            metadata,
+            project.as_deref(),
+            repository.as_deref(),
+            &filepath,
+        )?;
+        println!("{artifact_handle} {output}");
+        handles.insert(output, artifact_handle.to_string());
+    }
+
+    if let Some(handles_path) = handles_path {
+        fs::write(handles_path, serde_json::to_vec(&handles)?)?;
+    }
+    Ok(())
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
 fn read_custom_metadata(filepath: PathBuf) -> Result<serde_json::Value> {
     let file = fs::File::open(filepath)?;
     Ok(serde_json::from_reader(file)?)
@@ -0,0 +1,37 @@
+use anyhow::Result;
+use recesser_core::audit::{format_timestamp, AuditQuery, AuditRecord};
+
+use crate::commands::Global;
+use crate::http::AuditEndpoints;
+
+pub fn search(g: Global, query: AuditQuery) -> Result<()> {
+    let records = g.http.search(&query)?;
+    for record in records {
+        println!(
+            "{} {} {} {:?} {} {} {:?} {} {}",
+            record.sequence,
+            format_timestamp(&record.timestamp),
+            record.actor.as_deref().unwrap_or("-"),
+            record.scope,
+            record.action,
+            record.target.as_deref().unwrap_or("-"),
+            record.outcome,
+            record.status,
+            record.source_ip.as_deref().unwrap_or("-"),
+        );
+    }
+    Ok(())
+}
+
+pub fn verify(g: Global) -> Result<()> {
+    let records = g.http.search(&AuditQuery::default())?;
+    match records.first() {
+        Some(first) if first.sequence != 0 => {
+            anyhow::bail!("Audit records before {} are missing", first.sequence)
+        }
+        _ => (),
+    }
+    AuditRecord::verify_chain(&records)?;
+    println!("Verified audit log with {} records", records.len());
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use anyhow::Result;
+use recesser_core::audit::format_timestamp;
+use recesser_core::event::{Event, EventFilter, EventKind};
+
+use crate::commands::Global;
+use crate::http::EventEndpoints;
+
+pub fn watch(g: Global, filter: EventFilter) -> Result<()> {
+    g.http
+        .watch(&filter, |event| println!("{}", format_event(&event)))
+}
+
+fn format_event(event: &Event) -> String {
+    let subject = match &event.kind {
+        EventKind::ArtifactUploaded {
+            handle,
+            repository: Some(repository),
+        } => format!("{handle} {repository}"),
+        EventKind::ArtifactUploaded { handle, .. } | EventKind::ArtifactDeleted { handle } => {
+            handle.clone()
+        }
+        EventKind::RepositoryAdded { repository }
+        | EventKind::RepositoryRemoved { repository }
+        | EventKind::DeployKeyRotated { repository } => repository.clone(),
+        EventKind::PushReceived {
+            repository,
+            reference,
+        } => match reference {
+            Some(reference) => format!("{repository} {reference}"),
+            None => repository.clone(),
+        },
+        EventKind::CommitDetected { repository, commit } => format!("{repository} {commit}"),
+        EventKind::WorkflowSubmitted {
+            repository,
+            workflow,
+        }
+        | EventKind::WorkflowSucceeded {
+            repository,
+            workflow,
+            ..
+        } => format!("{repository} {workflow}"),
+        EventKind::WorkflowFailed {
+            repository,
+            workflow,
+            message,
+        } => format!(
+            "{repository} {workflow} {}",
+            message.as_deref().unwrap_or("-")
+        ),
+    };
+    format!(
+        "{} {} {} {} {}",
+        event.id,
+        format_timestamp(&event.timestamp),
+        event.kind.name(),
+        event.project.as_deref().unwrap_or("-"),
+        subject
+    )
+}
@@ -0,0 +1,11 @@
+use anyhow::Result;
+use recesser_core::user::Initialization;
+
+use crate::commands::Global;
+use crate::http::UserEndpoints;
+
+pub fn init(g: Global, bootstrap_secret: Option<String>) -> Result<()> {
+    let admin_token = g.http.init(&Initialization { bootstrap_secret })?;
+    println!("{admin_token}");
+    Ok(())
+}
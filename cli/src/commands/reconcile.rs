@@ -0,0 +1,37 @@
+use std::io::{self, BufWriter, Write};
+
+use anyhow::Result;
+use recesser_core::reconciliation::Resolution;
+
+use crate::commands::Global;
+use crate::http::ReconciliationEndpoints;
+
+pub fn reconcile(g: Global, repair: bool) -> Result<()> {
+    let report = g.http.reconcile(repair)?;
+    if report.drift.is_empty() {
+        log::info!("No drift detected");
+        return Ok(());
+    }
+
+    let mut writer = BufWriter::new(io::stdout());
+    for drift in &report.drift {
+        let resolution = match drift.resolution {
+            Resolution::Reported => "reported",
+            Resolution::Repaired => "repaired",
+            Resolution::Failed => "failed",
+            Resolution::Unrepairable => "unrepairable",
+        };
+        write!(
+            writer,
+            "{} {} ({resolution})",
+            drift.kind.name(),
+            drift.subject
+        )?;
+        if let Some(error) = &drift.error {
+            write!(writer, ": {error}")?;
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -1,38 +1,142 @@
 use std::io::{self, BufWriter, Write};
 
 use anyhow::Result;
-use recesser_core::repository::NewRepository;
+use recesser_core::audit::format_timestamp;
+use recesser_core::notification::{NewChannel, Target};
+use recesser_core::repository::{NewRepository, Transport, Triggers};
 
 use crate::commands::Global;
-use crate::http::RepositoryEndpoints;
-use crate::parser::{self, RepositoryCommands};
+use crate::http::{NotificationEndpoints, RepositoryEndpoints};
+use crate::parser::{self, NotificationCommands, RepositoryCommands};
 use crate::ssh::{self, KeyGen};
 
 impl RepositoryCommands {
     pub fn call(self, global: Global) -> Result<()> {
         match self {
-            RepositoryCommands::Add { name } => add(global, &name)?,
+            RepositoryCommands::Add {
+                name,
+                url,
+                token,
+                username,
+            } => add(global, name, url, token, username)?,
             RepositoryCommands::List => list(global)?,
             RepositoryCommands::Show { name } => show(global, &name)?,
-            RepositoryCommands::Remove { names } => remove(global, names)?,
+            RepositoryCommands::Remove {
+                names,
+                keep_history,
+            } => remove(global, names, keep_history)?,
+            RepositoryCommands::RotateKey { name, abort } => match abort {
+                true => global.http.discard_key(&name)?,
+                false => rotate_key(global, &name)?,
+            },
+            RepositoryCommands::Triggers {
+                name,
+                branches,
+                tags,
+                include,
+                exclude,
+            } => {
+                let triggers = Triggers {
+                    branches,
+                    tags,
+                    include,
+                    exclude,
+                    tags_since: None,
+                };
+                global.http.set_triggers(&name, &triggers)?
+            }
+            RepositoryCommands::Webhook { name, remove } => match remove {
+                true => global.http.remove_webhook(&name)?,
+                false => create_webhook(global, &name)?,
+            },
+            RepositoryCommands::Notification(cmd) => cmd.call(global)?,
         }
         Ok(())
     }
 }
 
-fn add(g: Global, name: &str) -> Result<()> {
-    let keypair = ssh::KeyPair::generate()?;
-    let pub_key = keypair.public_key.public_key.clone();
+impl NotificationCommands {
+    pub fn call(self, global: Global) -> Result<()> {
+        match self {
+            NotificationCommands::Add {
+                name,
+                webhook,
+                slack,
+                email,
+                triggers,
+            } => {
+                let target = match (webhook, slack, email.is_empty()) {
+                    (Some(url), None, true) => Target::Webhook { url },
+                    (None, Some(url), true) => Target::Slack { url },
+                    (None, None, false) => Target::Email { to: email },
+                    _ => anyhow::bail!("Specify exactly one of --webhook, --slack or --email"),
+                };
+                let new_channel = NewChannel {
+                    target,
+                    triggers: match triggers.is_empty() {
+                        true => None,
+                        false => Some(triggers),
+                    },
+                };
+                add_channel(global, &name, &new_channel)?
+            }
+            NotificationCommands::List { name } => list_channels(global, &name)?,
+            NotificationCommands::Remove { name, id } => global.http.remove_channel(&name, &id)?,
+            NotificationCommands::Deliveries { name, limit } => deliveries(global, &name, limit)?,
+        }
+        Ok(())
+    }
+}
+
+fn add(
+    g: Global,
+    name: String,
+    url: Option<String>,
+    token: Option<String>,
+    username: Option<String>,
+) -> Result<()> {
+    // A deploy key is only needed for SSH URLs, which is also the default
+    let transport = match &url {
+        Some(url) => Transport::of(url),
+        None => Some(Transport::Ssh),
+    };
+    let keypair = match transport {
+        Some(Transport::Ssh) => Some(ssh::KeyPair::generate()?),
+        _ => None,
+    };
+    let pub_key = keypair.as_ref().map(|k| k.public_key.public_key.clone());
     let new_repository = NewRepository {
-        name: String::from(name),
+        name,
+        url,
         keypair,
+        token,
+        username,
     };
 
     g.http.add(&new_repository)?;
+    if let Some(pub_key) = pub_key {
+        print!("{pub_key}");
+    }
+    Ok(())
+}
+
+fn rotate_key(g: Global, name: &str) -> Result<()> {
+    let keypair = ssh::KeyPair::generate()?;
+    let pub_key = keypair.public_key.public_key.clone();
+
+    g.http.stage_key(name, &keypair)?;
+    log::info!("Install the new public key as deploy key. The current key is retired after the next successful poll.");
     print!("{pub_key}");
     Ok(())
 }
 
+fn create_webhook(g: Global, name: &str) -> Result<()> {
+    let webhook = g.http.create_webhook(name)?;
+    println!("URL: {}", g.http.absolute_url(&webhook.path));
+    println!("Secret: {}", webhook.secret);
+    Ok(())
+}
+
 fn list(g: Global) -> Result<()> {
     let mut writer = BufWriter::new(io::stdout());
 
@@ -51,14 +155,86 @@ fn show(g: Global, name: &str) -> Result<()> {
     Ok(())
 }
 
-fn remove(g: Global, names: Vec<String>) -> Result<()> {
+fn add_channel(g: Global, name: &str, new_channel: &NewChannel) -> Result<()> {
+    let created = g.http.add_channel(name, new_channel)?;
+    println!("{}", created.channel.id);
+    if let Some(secret) = created.secret {
+        println!("Secret: {secret}");
+    }
+    Ok(())
+}
+
+fn list_channels(g: Global, name: &str) -> Result<()> {
+    let mut writer = BufWriter::new(io::stdout());
+
+    for channel in g.http.list_channels(name)? {
+        let target = match channel.target {
+            Target::Webhook { url } => format!("webhook {url}"),
+            Target::Slack { url } => format!("slack {url}"),
+            Target::Email { to } => format!("email {}", to.join(",")),
+        };
+        let triggers: Vec<&str> = channel.triggers.iter().map(|t| t.name()).collect();
+        writeln!(writer, "{} {} {}", channel.id, target, triggers.join(","))?
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn deliveries(g: Global, name: &str, limit: Option<i64>) -> Result<()> {
+    let mut writer = BufWriter::new(io::stdout());
+
+    for delivery in g.http.deliveries(name, limit)? {
+        writeln!(
+            writer,
+            "{} {} {} {} {} attempts {}",
+            format_timestamp(&delivery.timestamp),
+            delivery.channel,
+            delivery.event,
+            delivery.trigger.name(),
+            delivery.attempts,
+            delivery.error.as_deref().unwrap_or("delivered"),
+        )?
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn remove(g: Global, names: Vec<String>, keep_history: bool) -> Result<()> {
     let names = parser::read_lines_from_stdin_if_emtpy(names);
     let mut writer = BufWriter::new(io::stdout());
 
     for name in names {
-        match g.http.delete(&name) {
-            Ok(_) => writeln!(writer, "Removed {name}")?,
-            Err(_) => writeln!(writer, "Failed to remove {name}")?,
+        let report = match g.http.delete(&name, keep_history) {
+            Ok(report) => report,
+            Err(_) => {
+                writeln!(writer, "Failed to remove {name}")?;
+                continue;
+            }
+        };
+        writeln!(writer, "Removed {name}")?;
+        if report.ssh_key {
+            writeln!(writer, "  deleted SSH key")?;
+        }
+        if report.kubernetes_secret {
+            writeln!(writer, "  deleted Kubernetes secret")?;
+        }
+        for run in &report.stopped_runs {
+            writeln!(writer, "  stopped run {run}")?;
+        }
+        let verb = match report.history_archived {
+            true => "archived",
+            false => "deleted",
+        };
+        for run in &report.finished_runs {
+            writeln!(writer, "  {verb} run {run}")?;
+        }
+        for channel in &report.notification_channels {
+            writeln!(writer, "  removed notification channel {channel}")?;
+        }
+        for error in &report.errors {
+            writeln!(writer, "  {error}")?;
         }
     }
 
@@ -0,0 +1,97 @@
+use std::io::{self, BufWriter, Write};
+
+use anyhow::Result;
+use recesser_core::audit::format_timestamp;
+use recesser_core::run::{Run, RunQuery};
+
+use crate::commands::Global;
+use crate::http::RunEndpoints;
+use crate::parser::RunCommands;
+
+impl RunCommands {
+    pub fn call(self, global: Global) -> Result<()> {
+        match self {
+            RunCommands::List {
+                name,
+                limit,
+                archived,
+            } => {
+                let query = RunQuery {
+                    active: false,
+                    archived,
+                    limit,
+                };
+                list(global, &name, &query)?
+            }
+            RunCommands::Show { id } => show(global, &id)?,
+            RunCommands::Logs { id } => print!("{}", global.http.run_logs(&id)?),
+        }
+        Ok(())
+    }
+}
+
+fn list(g: Global, name: &str, query: &RunQuery) -> Result<()> {
+    let mut writer = BufWriter::new(io::stdout());
+
+    for run in g.http.list_runs(name, query)? {
+        writeln!(
+            writer,
+            "{} {} {} {} {}",
+            format_timestamp(&run.created),
+            run.id,
+            run.status.name(),
+            run.commit,
+            run.reference.as_deref().unwrap_or("default"),
+        )?
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn show(g: Global, id: &str) -> Result<()> {
+    let run = g.http.show_run(id)?;
+    let mut writer = BufWriter::new(io::stdout());
+    write_run(&mut writer, &run)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_run(writer: &mut impl Write, run: &Run) -> Result<()> {
+    writeln!(writer, "Run {}", run.id)?;
+    writeln!(writer, "  repository {}", run.repository)?;
+    writeln!(writer, "  commit {}", run.commit)?;
+    if let Some(reference) = &run.reference {
+        writeln!(writer, "  reference {reference}")?;
+    }
+    writeln!(writer, "  status {}", run.status.name())?;
+    if let Some(message) = &run.message {
+        writeln!(writer, "  message {message}")?;
+    }
+    if let Some(workflow) = &run.argo_workflow {
+        writeln!(writer, "  argo workflow {workflow}")?;
+    }
+    writeln!(writer, "  workflow hash {}", run.workflow_hash)?;
+    writeln!(writer, "  manifest hash {}", run.manifest_hash)?;
+    writeln!(writer, "  created {}", format_timestamp(&run.created))?;
+    if let Some(started) = &run.started {
+        writeln!(writer, "  started {}", format_timestamp(started))?;
+    }
+    if let Some(finished) = &run.finished {
+        writeln!(writer, "  finished {}", format_timestamp(finished))?;
+    }
+    if run.archived {
+        writeln!(writer, "  archived")?;
+    }
+    for input in &run.inputs {
+        writeln!(writer, "  input {input}")?;
+    }
+    for output in &run.outputs {
+        write!(writer, "  output {} {}", output.path, output.handle)?;
+        for (name, value) in &output.parameters {
+            write!(writer, " {name}={value}")?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
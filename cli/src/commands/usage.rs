@@ -0,0 +1,56 @@
+use std::io::{self, BufWriter, Write};
+
+use anyhow::Result;
+use recesser_core::usage::Usage;
+
+use crate::commands::Global;
+use crate::http::UsageEndpoints;
+
+pub fn usage(g: Global) -> Result<()> {
+    let report = g.http.usage()?;
+    let mut writer = BufWriter::new(io::stdout());
+
+    writeln!(writer, "Total")?;
+    write_usage(&mut writer, &report.total)?;
+    writeln!(writer, "\nUsers")?;
+    for usage in &report.users {
+        write_usage(&mut writer, usage)?;
+    }
+    writeln!(writer, "\nProjects")?;
+    for usage in &report.projects {
+        write_usage(&mut writer, usage)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_usage(writer: &mut impl Write, usage: &Usage) -> Result<()> {
+    let quota = match usage.quota {
+        Some(quota) => format_bytes(quota),
+        None => String::from("-"),
+    };
+    writeln!(
+        writer,
+        "{} {} {} objects (quota: {})",
+        usage.name,
+        format_bytes(usage.bytes),
+        usage.objects,
+        quota
+    )?;
+    Ok(())
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{value:.1} {}", UNITS[unit]),
+    }
+}
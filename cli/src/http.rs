@@ -1,56 +1,125 @@
 use std::fs;
-use std::path::Path;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use recesser_core::audit::{AuditQuery, AuditRecord};
+use recesser_core::error::{ApiError, ErrorCode};
+use recesser_core::event::{Event, EventFilter};
 use recesser_core::metadata::Metadata;
-use recesser_core::repository::{NewRepository, Repository};
-use recesser_core::user::{NewUser, Scope, User};
-use reqwest::blocking::{self, multipart, Response};
-use reqwest::header;
-use reqwest::StatusCode;
+use recesser_core::notification::{Channel, CreatedChannel, Delivery, NewChannel};
+use recesser_core::reconciliation::ReconciliationReport;
+use recesser_core::repository::{
+    CreatedWebhook, KeyPair, NewRepository, RemovalReport, Repository, Triggers,
+};
+use recesser_core::run::{Run, RunQuery};
+use recesser_core::tls::read_certificates;
+use recesser_core::usage::UsageReport;
+use recesser_core::user::{Initialization, NewUser, Scope, User};
+use reqwest::blocking::{self, multipart, ClientBuilder, RequestBuilder, Response};
+use reqwest::{header, Certificate, Identity, StatusCode};
 
+/// Prefix of the version of the apiserver API used by the CLI
+const API: &str = "/v1";
 const A: &str = "/artifacts";
 const R: &str = "/repositories";
 const U: &str = "/users";
+const I: &str = "/init";
+const AU: &str = "/audit";
+const US: &str = "/usage";
+const E: &str = "/events";
+const RC: &str = "/reconciliation";
+const RU: &str = "/runs";
+
+/// Maximum number of attempts for requests that are rate limited
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Delay before reconnecting to the event stream
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
 
 pub struct Client {
     addr: String,
     client: blocking::Client,
+    /// Client without a timeout for long-lived event streams
+    stream_client: blocking::Client,
+}
+
+/// TLS options of the client
+pub struct Tls {
+    /// CA bundle that is trusted in addition to the system roots
+    pub ca_cert: Option<PathBuf>,
+    /// Client certificate and private key in a single PEM file
+    pub client_cert: Option<PathBuf>,
 }
 
 impl Client {
-    pub fn new(addr: &str, token: String) -> Self {
+    pub fn new(addr: &str, token: Option<String>, tls: &Tls) -> Result<Self> {
         let mut headers = header::HeaderMap::new();
-        headers.insert(
-            header::AUTHORIZATION,
-            format!("Bearer {}", token)
-                .try_into()
-                .expect("Failed to set Authorization header"),
-        );
-        let cb = blocking::Client::builder().default_headers(headers);
-        Self {
-            addr: String::from(addr),
-            client: cb.build().expect("Failed to create client"),
+        if let Some(token) = token {
+            headers.insert(
+                header::AUTHORIZATION,
+                format!("Bearer {}", token)
+                    .try_into()
+                    .expect("Failed to set Authorization header"),
+            );
         }
+        Ok(Self {
+            addr: String::from(addr),
+            client: builder(&headers, tls)?.build()?,
+            stream_client: builder(&headers, tls)?.timeout(None).build()?,
+        })
     }
 
     fn download_and_save_file(&self, url: &str, filepath: &Path) -> Result<()> {
-        let mut resp = self.client.get(url).send()?;
+        let mut resp = self.send(|| Ok(self.client.get(url)))?;
         if !resp.status().is_success() {
-            anyhow::bail!("{}", resp.text()?);
+            return Err(api_error(resp)?.into());
         }
         let mut file = fs::File::create(filepath)?;
         resp.copy_to(&mut file)?;
         Ok(())
     }
 
+    /// Send request and retry it as long as the apiserver responds with 429 and a `Retry-After`
+    /// header, up to a maximum number of attempts
+    fn send(&self, request: impl Fn() -> Result<RequestBuilder>) -> Result<Response> {
+        let mut attempt = 1;
+        loop {
+            let resp = request()?.send()?;
+            let retry_after = match retry_after(&resp) {
+                Some(retry_after) if attempt < MAX_ATTEMPTS => retry_after,
+                _ => return Ok(resp),
+            };
+            log::info!(
+                "Rate limited by apiserver. Retrying in {} seconds",
+                retry_after.as_secs()
+            );
+            thread::sleep(retry_after);
+            attempt += 1;
+        }
+    }
+
     fn url(&self, path: &str) -> String {
+        format!("{addr}{API}{path}", addr = self.addr)
+    }
+
+    /// URL of an absolute path returned by the apiserver
+    pub fn absolute_url(&self, path: &str) -> String {
         format!("{addr}{path}", addr = self.addr)
     }
 }
 
 pub trait ArtifactEndpoints {
-    fn upload_file(&self, handle: &str, metadata: Metadata, filepath: &Path) -> Result<()>;
+    fn upload_file(
+        &self,
+        handle: &str,
+        metadata: Metadata,
+        project: Option<&str>,
+        repository: Option<&str>,
+        filepath: &Path,
+    ) -> Result<()>;
     fn list(&self) -> Result<Vec<String>>;
     fn download_file(&self, handle: &str, filepath: &Path) -> Result<()>;
     fn download_metadata(&self, handle: &str, filepath: &Path) -> Result<()>;
@@ -58,21 +127,39 @@ pub trait ArtifactEndpoints {
 }
 
 impl ArtifactEndpoints for Client {
-    fn upload_file(&self, handle: &str, metadata: Metadata, filepath: &Path) -> Result<()> {
-        let file = fs::File::open(filepath)?;
-
-        let form = multipart::Form::new()
-            .text("handle", String::from(handle))
-            .text("metadata", serde_json::to_string(&metadata)?)
-            .part("file", multipart::Part::reader(file));
+    fn upload_file(
+        &self,
+        handle: &str,
+        metadata: Metadata,
+        project: Option<&str>,
+        repository: Option<&str>,
+        filepath: &Path,
+    ) -> Result<()> {
+        let serialized_metadata = serde_json::to_string(&metadata)?;
 
-        let resp = self.client.put(self.url(A)).multipart(form).send()?;
+        // The form is rebuilt for every attempt because the file can only be streamed once
+        let resp = self.send(|| {
+            let file = fs::File::open(filepath)?;
+            let mut form = multipart::Form::new()
+                .text("handle", String::from(handle))
+                .text("metadata", serialized_metadata.clone());
+            // The file needs to be the last field because the project is required to enforce
+            // quotas
+            if let Some(project) = project {
+                form = form.text("project", String::from(project));
+            }
+            if let Some(repository) = repository {
+                form = form.text("repository", String::from(repository));
+            }
+            let form = form.part("file", multipart::Part::reader(file));
+            Ok(self.client.put(self.url(A)).multipart(form))
+        })?;
         check_body(resp)?;
         Ok(())
     }
 
     fn list(&self) -> Result<Vec<String>> {
-        let resp = self.client.get(self.url(A)).send()?;
+        let resp = self.send(|| Ok(self.client.get(self.url(A))))?;
         let body = check_body(resp)?;
         let list: Vec<String> = serde_json::from_slice(&body)?;
         Ok(list)
@@ -89,14 +176,14 @@ impl ArtifactEndpoints for Client {
     }
 
     fn delete(&self, handle: &str) -> Result<()> {
-        let resp = self
-            .client
-            .delete(self.url(&format!("{A}/{handle}")))
-            .send()?;
-        match resp.status() {
-            StatusCode::ACCEPTED => Ok(()),
-            StatusCode::NOT_FOUND => anyhow::bail!("Artifact {handle} doesn't exist."),
-            _ => anyhow::bail!("Internal error: {}", resp.text()?),
+        let resp = self.send(|| Ok(self.client.delete(self.url(&format!("{A}/{handle}")))))?;
+        if resp.status().is_success() {
+            return Ok(());
+        }
+        let e = api_error(resp)?;
+        match e.code() {
+            ErrorCode::NotFound => anyhow::bail!("Artifact {handle} doesn't exist."),
+            _ => Err(e.into()),
         }
     }
 }
@@ -105,84 +192,342 @@ pub trait RepositoryEndpoints {
     fn add(&self, new_repository: &NewRepository) -> Result<()>;
     fn list(&self) -> Result<Vec<Repository>>;
     fn show(&self, name: &str) -> Result<Repository>;
-    fn credentials(&self, name: &str) -> Result<()>;
-    fn delete(&self, name: &str) -> Result<()>;
+    fn delete(&self, name: &str, keep_history: bool) -> Result<RemovalReport>;
+    fn stage_key(&self, name: &str, keypair: &KeyPair) -> Result<()>;
+    fn discard_key(&self, name: &str) -> Result<()>;
+    fn set_triggers(&self, name: &str, triggers: &Triggers) -> Result<()>;
+    fn create_webhook(&self, name: &str) -> Result<CreatedWebhook>;
+    fn remove_webhook(&self, name: &str) -> Result<()>;
 }
 
 impl RepositoryEndpoints for Client {
     fn add(&self, new_repository: &NewRepository) -> Result<()> {
-        let resp = self.client.put(self.url(R)).json(new_repository).send()?;
+        let resp = self.send(|| Ok(self.client.put(self.url(R)).json(new_repository)))?;
         check_body(resp)?;
         Ok(())
     }
 
     fn list(&self) -> Result<Vec<Repository>> {
-        let resp = self.client.get(self.url(R)).send()?;
+        let resp = self.send(|| Ok(self.client.get(self.url(R))))?;
         let body = check_body(resp)?;
         let repos: Vec<Repository> = serde_json::from_slice(&body)?;
         Ok(repos)
     }
 
     fn show(&self, name: &str) -> Result<Repository> {
-        let resp = self.client.get(self.url(&format!("{R}/{name}"))).send()?;
+        let resp = self.send(|| Ok(self.client.get(self.url(&format!("{R}/{name}")))))?;
         let body = check_body(resp)?;
         let repo: Repository = serde_json::from_slice(&body)?;
         Ok(repo)
     }
 
-    fn credentials(&self, name: &str) -> Result<()> {
-        let _resp = self
-            .client
-            .get(self.url(&format!("{R}/{name}/credentials")))
-            .send()?;
+    fn delete(&self, name: &str, keep_history: bool) -> Result<RemovalReport> {
+        let resp = self.send(|| {
+            Ok(self
+                .client
+                .delete(self.url(&format!("{R}/{name}")))
+                .query(&[("keep_history", keep_history)]))
+        })?;
+        let body = check_body(resp)?;
+        let report: RemovalReport = serde_json::from_slice(&body)?;
+        Ok(report)
+    }
+
+    fn stage_key(&self, name: &str, keypair: &KeyPair) -> Result<()> {
+        let resp = self.send(|| {
+            Ok(self
+                .client
+                .put(self.url(&format!("{R}/{name}/staged-key")))
+                .json(keypair))
+        })?;
+        check_body(resp)?;
+        Ok(())
+    }
+
+    fn discard_key(&self, name: &str) -> Result<()> {
+        let resp = self.send(|| {
+            Ok(self
+                .client
+                .delete(self.url(&format!("{R}/{name}/staged-key"))))
+        })?;
+        check_body(resp)?;
+        Ok(())
+    }
+
+    fn set_triggers(&self, name: &str, triggers: &Triggers) -> Result<()> {
+        let resp = self.send(|| {
+            Ok(self
+                .client
+                .put(self.url(&format!("{R}/{name}/triggers")))
+                .json(triggers))
+        })?;
+        check_body(resp)?;
+        Ok(())
+    }
+
+    fn create_webhook(&self, name: &str) -> Result<CreatedWebhook> {
+        let resp = self.send(|| Ok(self.client.post(self.url(&format!("{R}/{name}/webhook")))))?;
+        let body = check_body(resp)?;
+        let webhook: CreatedWebhook = serde_json::from_slice(&body)?;
+        Ok(webhook)
+    }
+
+    fn remove_webhook(&self, name: &str) -> Result<()> {
+        let resp =
+            self.send(|| Ok(self.client.delete(self.url(&format!("{R}/{name}/webhook")))))?;
+        check_body(resp)?;
         Ok(())
     }
+}
+
+pub trait NotificationEndpoints {
+    fn add_channel(&self, repository: &str, new_channel: &NewChannel) -> Result<CreatedChannel>;
+    fn list_channels(&self, repository: &str) -> Result<Vec<Channel>>;
+    fn remove_channel(&self, repository: &str, id: &str) -> Result<()>;
+    fn deliveries(&self, repository: &str, limit: Option<i64>) -> Result<Vec<Delivery>>;
+}
+
+impl NotificationEndpoints for Client {
+    fn add_channel(&self, repository: &str, new_channel: &NewChannel) -> Result<CreatedChannel> {
+        let url = self.url(&format!("{R}/{repository}/notifications"));
+        let resp = self.send(|| Ok(self.client.post(&url).json(new_channel)))?;
+        let body = check_body(resp)?;
+        Ok(serde_json::from_slice(&body)?)
+    }
 
-    fn delete(&self, name: &str) -> Result<()> {
-        let resp = self
-            .client
-            .delete(self.url(&format!("{R}/{name}")))
-            .send()?;
+    fn list_channels(&self, repository: &str) -> Result<Vec<Channel>> {
+        let url = self.url(&format!("{R}/{repository}/notifications"));
+        let resp = self.send(|| Ok(self.client.get(&url)))?;
+        let body = check_body(resp)?;
+        Ok(serde_json::from_slice(&body)?)
+    }
 
+    fn remove_channel(&self, repository: &str, id: &str) -> Result<()> {
+        let url = self.url(&format!("{R}/{repository}/notifications/{id}"));
+        let resp = self.send(|| Ok(self.client.delete(&url)))?;
         check_body(resp)?;
         Ok(())
     }
+
+    fn deliveries(&self, repository: &str, limit: Option<i64>) -> Result<Vec<Delivery>> {
+        let url = self.url(&format!("{R}/{repository}/deliveries"));
+        let resp = self.send(|| Ok(self.client.get(&url).query(&[("limit", limit)])))?;
+        let body = check_body(resp)?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+pub trait RunEndpoints {
+    fn list_runs(&self, repository: &str, query: &RunQuery) -> Result<Vec<Run>>;
+    fn show_run(&self, id: &str) -> Result<Run>;
+    fn run_logs(&self, id: &str) -> Result<String>;
+}
+
+impl RunEndpoints for Client {
+    fn list_runs(&self, repository: &str, query: &RunQuery) -> Result<Vec<Run>> {
+        let url = self.url(&format!("{R}/{repository}/runs"));
+        let resp = self.send(|| Ok(self.client.get(&url).query(query)))?;
+        let body = check_body(resp)?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    fn show_run(&self, id: &str) -> Result<Run> {
+        let url = self.url(&format!("{RU}/{id}"));
+        let resp = self.send(|| Ok(self.client.get(&url)))?;
+        let body = check_body(resp)?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    fn run_logs(&self, id: &str) -> Result<String> {
+        let url = self.url(&format!("{RU}/{id}/logs"));
+        let resp = self.send(|| Ok(self.client.get(&url)))?;
+        let body = check_body(resp)?;
+        Ok(String::from_utf8(body)?)
+    }
 }
 
 pub trait UserEndpoints {
+    fn init(&self, initialization: &Initialization) -> Result<String>;
     fn create(&self, scope: Scope) -> Result<String>;
     fn list(&self) -> Result<Vec<User>>;
     fn rotate_key(&self) -> Result<String>;
 }
 
 impl UserEndpoints for Client {
+    fn init(&self, initialization: &Initialization) -> Result<String> {
+        let resp = self.send(|| Ok(self.client.post(self.url(I)).json(initialization)))?;
+        let body = check_body(resp)?;
+        Ok(String::from_utf8(body)?)
+    }
+
     fn create(&self, scope: Scope) -> Result<String> {
-        let resp = self
-            .client
-            .post(self.url(U))
-            .json(&NewUser::new(scope))
-            .send()?;
+        let new_user = NewUser::new(scope);
+        let resp = self.send(|| Ok(self.client.post(self.url(U)).json(&new_user)))?;
         let body = check_body(resp)?;
         Ok(String::from_utf8(body)?)
     }
 
     fn list(&self) -> Result<Vec<User>> {
-        let resp = self.client.get(self.url(U)).send()?;
+        let resp = self.send(|| Ok(self.client.get(self.url(U))))?;
         let body = check_body(resp)?;
         let users: Vec<User> = serde_json::from_slice(&body)?;
         Ok(users)
     }
 
     fn rotate_key(&self) -> Result<String> {
-        let resp = self.client.delete(self.url(U)).send()?;
+        let resp = self.send(|| Ok(self.client.delete(self.url(U))))?;
         let body = check_body(resp)?;
         Ok(String::from_utf8(body)?)
     }
 }
 
+pub trait AuditEndpoints {
+    fn search(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>>;
+}
+
+impl AuditEndpoints for Client {
+    fn search(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>> {
+        let resp = self.send(|| Ok(self.client.get(self.url(AU)).query(query)))?;
+        let body = check_body(resp)?;
+        let records: Vec<AuditRecord> = serde_json::from_slice(&body)?;
+        Ok(records)
+    }
+}
+
+pub trait UsageEndpoints {
+    fn usage(&self) -> Result<UsageReport>;
+}
+
+impl UsageEndpoints for Client {
+    fn usage(&self) -> Result<UsageReport> {
+        let resp = self.send(|| Ok(self.client.get(self.url(US))))?;
+        let body = check_body(resp)?;
+        let report: UsageReport = serde_json::from_slice(&body)?;
+        Ok(report)
+    }
+}
+
+pub trait ReconciliationEndpoints {
+    fn reconcile(&self, repair: bool) -> Result<ReconciliationReport>;
+}
+
+impl ReconciliationEndpoints for Client {
+    fn reconcile(&self, repair: bool) -> Result<ReconciliationReport> {
+        let resp = self.send(|| {
+            let url = self.url(RC);
+            Ok(match repair {
+                true => self.client.post(url),
+                false => self.client.get(url),
+            })
+        })?;
+        let body = check_body(resp)?;
+        let report: ReconciliationReport = serde_json::from_slice(&body)?;
+        Ok(report)
+    }
+}
+
+pub trait EventEndpoints {
+    fn watch(&self, filter: &EventFilter, on_event: impl FnMut(Event)) -> Result<()>;
+}
+
+impl EventEndpoints for Client {
+    /// Follow the event stream and reconnect when the connection is lost, resuming after the
+    /// last received event
+    fn watch(&self, filter: &EventFilter, mut on_event: impl FnMut(Event)) -> Result<()> {
+        let mut last_event_id: Option<String> = None;
+        let mut connected = false;
+        let mut attempt = 1;
+        loop {
+            let mut request = self.stream_client.get(self.url(E)).query(filter);
+            if let Some(id) = &last_event_id {
+                request = request.header("Last-Event-ID", id);
+            }
+            let resp = match request.send() {
+                Ok(resp) => resp,
+                Err(e) if connected && attempt < MAX_ATTEMPTS => {
+                    log::info!("Failed to reconnect to event stream: {e}");
+                    thread::sleep(RECONNECT_DELAY);
+                    attempt += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if !resp.status().is_success() {
+                return Err(api_error(resp)?.into());
+            }
+            connected = true;
+            attempt = 1;
+
+            let mut id = None;
+            let mut data = String::new();
+            for line in BufReader::new(resp).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => {
+                        log::debug!("Event stream interrupted: {e}");
+                        break;
+                    }
+                };
+                // A blank line dispatches the event. Comments and event names are ignored.
+                if line.is_empty() {
+                    if !data.is_empty() {
+                        on_event(serde_json::from_str(&data)?);
+                        if let Some(id) = id.take() {
+                            last_event_id = Some(id);
+                        }
+                    }
+                    data.clear();
+                } else if let Some(value) = line.strip_prefix("id:") {
+                    id = Some(String::from(value.trim_start()));
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data.push_str(value.trim_start());
+                }
+            }
+
+            log::info!("Lost connection to event stream. Reconnecting");
+            thread::sleep(RECONNECT_DELAY);
+        }
+    }
+}
+
+fn builder(headers: &header::HeaderMap, tls: &Tls) -> Result<ClientBuilder> {
+    let mut cb = blocking::Client::builder().default_headers(headers.clone());
+    if let Some(path) = &tls.ca_cert {
+        for der in read_certificates(path)? {
+            cb = cb.add_root_certificate(Certificate::from_der(&der)?);
+        }
+    }
+    if let Some(path) = &tls.client_cert {
+        let pem = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        cb = cb.identity(Identity::from_pem(&pem)?);
+    }
+    Ok(cb)
+}
+
+fn retry_after(resp: &Response) -> Option<Duration> {
+    if resp.status() != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let seconds = resp
+        .headers()
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
 fn check_body(resp: Response) -> Result<Vec<u8>> {
     if !resp.status().is_success() {
-        anyhow::bail!(resp.text()?)
+        return Err(api_error(resp)?.into());
     }
     Ok(resp.bytes()?.to_vec())
 }
+
+/// Parse the error envelope of an unsuccessful response
+fn api_error(resp: Response) -> Result<ApiError> {
+    let status = resp.status().as_u16();
+    let body = resp.bytes()?;
+    Ok(ApiError::new(status, &body))
+}
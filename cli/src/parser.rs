@@ -1,7 +1,9 @@
 use std::io::{self, BufRead};
 use std::path::PathBuf;
 
+use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
+use recesser_core::notification::Trigger;
 use recesser_core::user::Scope;
 
 #[derive(Parser, Debug)]
@@ -19,6 +21,12 @@ pub struct Cli {
     /// Access token
     #[clap(short, long)]
     pub token: Option<String>,
+    /// CA bundle to verify the certificate of the system
+    #[clap(long)]
+    pub ca_cert: Option<PathBuf>,
+    /// Client certificate and private key in a single PEM file for mutual TLS
+    #[clap(long)]
+    pub client_cert: Option<PathBuf>,
     #[clap(subcommand)]
     pub commands: Commands,
 }
@@ -31,6 +39,18 @@ pub enum Commands {
     /// Manage repositories
     #[clap(subcommand)]
     Repository(RepositoryCommands),
+    /// Inspect runs of repositories
+    #[clap(subcommand)]
+    Run(RunCommands),
+    /// Follow events such as uploads and workflow completions
+    Watch {
+        /// Only show events of this type (e.g. workflow_failed). Can be repeated.
+        #[clap(long = "type", use_value_delimiter = true)]
+        types: Vec<String>,
+        /// Only show events of this project
+        #[clap(short, long)]
+        project: Option<String>,
+    },
     /// Administrate system
     #[clap(subcommand)]
     Admin(AdminCommands),
@@ -46,6 +66,32 @@ pub enum ArtifactCommands {
 
         #[clap(short, long)]
         metadata: Option<PathBuf>,
+
+        /// Project the artifact is accounted to
+        #[clap(short, long)]
+        project: Option<String>,
+
+        /// Repository whose workflow produced the artifact. Notifies its channels.
+        #[clap(short, long)]
+        repository: Option<String>,
+    },
+    /// Upload every file in a directory with provenance metadata. Used by workflows to upload
+    /// their outputs.
+    UploadOutputs {
+        dir: PathBuf,
+        /// JSON object describing the run. The path of each file is added as `output`.
+        #[clap(long)]
+        provenance: String,
+        /// Tag the outputs with a parameter value given as NAME=VALUE. Can be repeated.
+        #[clap(long = "parameter")]
+        parameters: Vec<String>,
+        #[clap(short, long)]
+        project: Option<String>,
+        #[clap(short, long)]
+        repository: Option<String>,
+        /// Write the handles by path as JSON to this file
+        #[clap(long)]
+        handles: Option<PathBuf>,
     },
     /// List all artifacts
     List,
@@ -57,21 +103,163 @@ pub enum ArtifactCommands {
 
 #[derive(Subcommand, Debug)]
 pub enum RepositoryCommands {
-    /// Add repository
-    Add { name: String },
+    /// Add repository. Prints the public deploy key for repositories cloned via SSH.
+    Add {
+        /// Identifies the repository as namespace/name
+        name: String,
+        /// Clone URL (SSH, HTTP(S) or file://). Defaults to the GitHub repository with the same
+        /// name.
+        #[clap(long)]
+        url: Option<String>,
+        /// Access token for HTTPS URLs
+        #[clap(long)]
+        token: Option<String>,
+        /// User the access token is sent with
+        #[clap(long, requires = "token")]
+        username: Option<String>,
+    },
     /// List all repositories
     List,
     /// Display information about repository
     Show { name: String },
-    /// Remove repository
-    Remove { names: Vec<String> },
+    /// Remove repository and revoke its credentials
+    Remove {
+        names: Vec<String>,
+        /// Archive completed runs and the notification log instead of deleting them
+        #[clap(long)]
+        keep_history: bool,
+    },
+    /// Generate a new deploy key. The current key is retired once the repository could be fetched
+    /// with the new one.
+    RotateKey {
+        name: String,
+        /// Discard the staged key instead and keep the current one
+        #[clap(long)]
+        abort: bool,
+    },
+    /// Replace the rules for which branches, tags and changed paths start runs. Without any
+    /// option, every commit to the default branch starts a run.
+    Triggers {
+        name: String,
+        /// Watch this branch instead of the default branch. Can be repeated.
+        #[clap(long = "branch")]
+        branches: Vec<String>,
+        /// Start a release run for new tags matching this glob. Can be repeated.
+        #[clap(long = "tag")]
+        tags: Vec<String>,
+        /// Only start runs for commits that change paths matching this glob (e.g. analysis/).
+        /// Can be repeated.
+        #[clap(long)]
+        include: Vec<String>,
+        /// Ignore changes to paths matching this glob. Can be repeated.
+        #[clap(long)]
+        exclude: Vec<String>,
+    },
+    /// Set up a push webhook so that pushes start runs without waiting for the next poll. Prints
+    /// the URL and secret to configure on the Git host.
+    Webhook {
+        name: String,
+        /// Remove the webhook instead. The repository is only polled afterwards.
+        #[clap(long)]
+        remove: bool,
+    },
+    /// Manage notifications about runs and outputs of a repository
+    #[clap(subcommand)]
+    Notification(NotificationCommands),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum NotificationCommands {
+    /// Add notification channel. Prints the secret webhook payloads are signed with.
+    Add {
+        name: String,
+        /// URL of a generic webhook
+        #[clap(long)]
+        webhook: Option<String>,
+        /// URL of a Slack or Mattermost incoming webhook
+        #[clap(long)]
+        slack: Option<String>,
+        /// Email addresses to notify
+        #[clap(long, use_value_delimiter = true)]
+        email: Vec<String>,
+        /// Only notify on these triggers (run_succeeded, run_failed, output_artifact)
+        #[clap(long = "on", use_value_delimiter = true)]
+        triggers: Vec<Trigger>,
+    },
+    /// List notification channels
+    List { name: String },
+    /// Remove notification channel
+    Remove { name: String, id: String },
+    /// Show log of deliveries
+    Deliveries {
+        name: String,
+        /// Only show the newest deliveries
+        #[clap(long)]
+        limit: Option<i64>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RunCommands {
+    /// List runs of a repository from newest to oldest
+    List {
+        name: String,
+        /// Only show the newest runs
+        #[clap(long)]
+        limit: Option<i64>,
+        /// Include runs that were archived when the repository was removed
+        #[clap(long)]
+        archived: bool,
+    },
+    /// Display the status, inputs and outputs of a run
+    Show { id: String },
+    /// Print the logs of every step of a run
+    Logs { id: String },
 }
 
 #[derive(Subcommand, Debug)]
 pub enum AdminCommands {
+    /// Initialize system and create the first admin
+    Init {
+        /// Secret the apiserver was configured with to protect initialization
+        #[clap(long)]
+        bootstrap_secret: Option<String>,
+    },
     /// Manage users
     #[clap(subcommand)]
     User(UserCommands),
+    /// Show storage usage per user and project
+    Usage,
+    /// Compare repositories with their keys in secret storage and secrets in Kubernetes
+    Reconcile {
+        /// Restore missing secrets and delete orphaned ones instead of only reporting them
+        #[clap(long)]
+        repair: bool,
+    },
+    /// Show audit log of mutating operations
+    Audit {
+        /// Only show operations by this user
+        #[clap(long)]
+        actor: Option<String>,
+        /// Only show this action (e.g. artifact.delete)
+        #[clap(long)]
+        action: Option<String>,
+        /// Only show operations on this target
+        #[clap(long)]
+        target: Option<String>,
+        /// Only show operations at or after this time (RFC 3339)
+        #[clap(long)]
+        since: Option<DateTime<Utc>>,
+        /// Only show operations at or before this time (RFC 3339)
+        #[clap(long)]
+        until: Option<DateTime<Utc>>,
+        /// Only show the newest operations
+        #[clap(long)]
+        limit: Option<i64>,
+        /// Verify that the complete audit log has not been tampered with
+        #[clap(long, conflicts_with_all = &["actor", "action", "target", "since", "until", "limit"])]
+        verify: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
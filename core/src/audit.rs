@@ -0,0 +1,147 @@
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::encoding;
+use crate::hash::hash_buf;
+use crate::user::Scope;
+
+/// Entry in the append-only audit log of mutating operations.
+///
+/// Every record contains the hash of its predecessor so that modifying or removing a record
+/// breaks the chain.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AuditRecord {
+    pub sequence: u64,
+    #[serde(with = "timestamp")]
+    #[cfg_attr(feature = "openapi", schema(value_type = String, format = DateTime))]
+    pub timestamp: DateTime<Utc>,
+    pub actor: Option<String>,
+    pub scope: Option<Scope>,
+    pub action: String,
+    pub target: Option<String>,
+    pub outcome: Outcome,
+    pub status: u16,
+    pub source_ip: Option<String>,
+    pub previous_hash: String,
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+/// Filters for querying the audit log
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    #[cfg_attr(feature = "openapi", param(value_type = Option<String>, format = DateTime))]
+    pub since: Option<DateTime<Utc>>,
+    #[cfg_attr(feature = "openapi", param(value_type = Option<String>, format = DateTime))]
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+impl AuditRecord {
+    /// Compute hash over all fields of the record except the hash itself
+    pub fn compute_hash(&self) -> Result<String> {
+        let unhashed = Self {
+            hash: String::new(),
+            ..self.clone()
+        };
+        let buf = serde_json::to_vec(&unhashed)?;
+        Ok(encoding::base64::encode(&hash_buf(&buf)))
+    }
+
+    /// Verify that contiguous records are unmodified and correctly chained
+    pub fn verify_chain(records: &[AuditRecord]) -> Result<()> {
+        let mut previous: Option<&AuditRecord> = None;
+        for record in records {
+            if record.compute_hash()? != record.hash {
+                anyhow::bail!("Audit record {} was modified", record.sequence);
+            }
+            let expected_previous_hash = match previous {
+                Some(previous) if record.sequence != previous.sequence + 1 => anyhow::bail!(
+                    "Audit records between {} and {} are missing",
+                    previous.sequence,
+                    record.sequence
+                ),
+                Some(previous) => previous.hash.as_str(),
+                None if record.sequence == 0 => "",
+                // The predecessor of the first record is unknown
+                None => record.previous_hash.as_str(),
+            };
+            if record.previous_hash != expected_previous_hash {
+                anyhow::bail!(
+                    "Audit record {} is not chained to its predecessor",
+                    record.sequence
+                );
+            }
+            previous = Some(record);
+        }
+        Ok(())
+    }
+}
+
+/// Format timestamp with a fixed width so that the lexicographic order of serialized timestamps
+/// matches their chronological order
+pub fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+pub(crate) mod timestamp {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::format_timestamp;
+
+    pub fn serialize<S: Serializer>(timestamp: &DateTime<Utc>, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&format_timestamp(timestamp))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<DateTime<Utc>, D::Error> {
+        let s = String::deserialize(d)?;
+        DateTime::parse_from_rfc3339(&s)
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(serde::de::Error::custom)
+    }
+
+    pub mod optional {
+        use chrono::{DateTime, Utc};
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        use super::super::format_timestamp;
+
+        pub fn serialize<S: Serializer>(
+            timestamp: &Option<DateTime<Utc>>,
+            s: S,
+        ) -> Result<S::Ok, S::Error> {
+            match timestamp {
+                Some(timestamp) => s.serialize_some(&format_timestamp(timestamp)),
+                None => s.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            d: D,
+        ) -> Result<Option<DateTime<Utc>>, D::Error> {
+            match Option::<String>::deserialize(d)? {
+                Some(s) => DateTime::parse_from_rfc3339(&s)
+                    .map(|t| Some(t.with_timezone(&Utc)))
+                    .map_err(serde::de::Error::custom),
+                None => Ok(None),
+            }
+        }
+    }
+}
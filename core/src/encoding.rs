@@ -25,6 +25,14 @@ pub mod hex {
     use anyhow::Result;
     use std::fmt::Write;
 
+    pub fn encode(input: &[u8]) -> String {
+        let mut buf = String::with_capacity(input.len() * 2);
+        for byte in input {
+            let _ = write!(&mut buf, "{:02x}", byte);
+        }
+        buf
+    }
+
     pub fn encode_str(s: &str) -> Result<String> {
         let mut buf = String::new();
         for &byte in s.as_bytes() {
@@ -0,0 +1,117 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Stable machine-readable codes of errors returned by the apiserver
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, strum_macros::Display)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    MissingField,
+    InvalidField,
+    IntegrityMismatch,
+    Unauthorized,
+    AlreadyInitialized,
+    AlreadyExists,
+    NotFound,
+    QuotaExceeded,
+    TooManyRequests,
+    Internal,
+    /// Code introduced by a newer apiserver or response that is not an error envelope
+    #[serde(other)]
+    Unknown,
+}
+
+/// Body of every error response of the apiserver
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ErrorResponse {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<FieldError>,
+    /// Identifies the request in the logs of the apiserver
+    pub request_id: Option<String>,
+}
+
+/// Problem with a single field of a request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl ErrorResponse {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: Vec::new(),
+            request_id: None,
+        }
+    }
+
+    pub fn with_detail(mut self, field: &str, message: impl Into<String>) -> Self {
+        self.details.push(FieldError {
+            field: String::from(field),
+            message: message.into(),
+        });
+        self
+    }
+}
+
+impl ErrorCode {
+    /// Fallback for errors that don't carry their own code
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 | 403 => ErrorCode::Unauthorized,
+            404 => ErrorCode::NotFound,
+            429 => ErrorCode::TooManyRequests,
+            400..=499 => ErrorCode::BadRequest,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+/// Error returned by the apiserver as seen by its clients
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: u16,
+    pub response: ErrorResponse,
+}
+
+impl ApiError {
+    /// Parse the error envelope from a response body. Bodies that are not an envelope (e.g. from a
+    /// proxy in front of the apiserver) are kept as message.
+    pub fn new(status: u16, body: &[u8]) -> Self {
+        let response = serde_json::from_slice(body).unwrap_or_else(|_| {
+            ErrorResponse::new(
+                ErrorCode::from_status(status),
+                String::from_utf8_lossy(body),
+            )
+        });
+        Self { status, response }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.response.code
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.response.message)?;
+        for detail in &self.response.details {
+            write!(f, "\n  {}: {}", detail.field, detail.message)?;
+        }
+        if let Some(request_id) = &self.response.request_id {
+            write!(f, "\n(code: {}, request ID: {request_id})", self.code())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApiError {}
@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Change in the system. Events are numbered consecutively so that clients can resume after the
+/// last event they have seen.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Event {
+    pub id: u64,
+    #[cfg_attr(feature = "openapi", schema(value_type = String, format = DateTime))]
+    pub timestamp: DateTime<Utc>,
+    /// Project of the artifact the event concerns, if any
+    pub project: Option<String>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// Type of an event and the data that comes with it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, strum_macros::IntoStaticStr)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum EventKind {
+    ArtifactUploaded {
+        handle: String,
+        /// Repository whose workflow produced the artifact
+        repository: Option<String>,
+    },
+    ArtifactDeleted {
+        handle: String,
+    },
+    RepositoryAdded {
+        repository: String,
+    },
+    RepositoryRemoved {
+        repository: String,
+    },
+    DeployKeyRotated {
+        repository: String,
+    },
+    /// The Git host reported a push via webhook
+    PushReceived {
+        repository: String,
+        /// Full name of the pushed branch or tag
+        reference: Option<String>,
+    },
+    CommitDetected {
+        repository: String,
+        commit: String,
+    },
+    WorkflowSubmitted {
+        repository: String,
+        workflow: String,
+    },
+    WorkflowSucceeded {
+        repository: String,
+        workflow: String,
+        /// Artifacts uploaded as outputs of the workflow
+        #[serde(default)]
+        outputs: Vec<Output>,
+    },
+    WorkflowFailed {
+        repository: String,
+        workflow: String,
+        message: Option<String>,
+    },
+}
+
+/// Artifact a workflow uploaded as one of its outputs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Output {
+    /// Path of the file relative to the declared outputs
+    pub path: String,
+    pub handle: String,
+    /// Values of the matrix parameters of the run that produced the output
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, String>,
+}
+
+/// Event reported to the apiserver by other components
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NewEvent {
+    pub project: Option<String>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// Filters for subscribing to events
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct EventFilter {
+    /// Comma separated list of event types
+    pub types: Option<String>,
+    pub project: Option<String>,
+}
+
+impl EventKind {
+    /// Name of the event type, e.g. `artifact_uploaded`
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+
+    /// Events about workflows and commits are reported by the schandler rather than emitted by
+    /// the apiserver itself
+    pub fn is_reported(&self) -> bool {
+        matches!(
+            self,
+            EventKind::CommitDetected { .. }
+                | EventKind::WorkflowSubmitted { .. }
+                | EventKind::WorkflowSucceeded { .. }
+                | EventKind::WorkflowFailed { .. }
+        )
+    }
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &Event) -> bool {
+        let type_matches = match &self.types {
+            Some(types) => types.split(',').any(|t| t.trim() == event.kind.name()),
+            None => true,
+        };
+        let project_matches = match &self.project {
+            Some(project) => event.project.as_ref() == Some(project),
+            None => true,
+        };
+        type_matches && project_matches
+    }
+}
@@ -13,13 +13,13 @@ pub fn hash_file(filepath: &Path) -> Result<[u8; DIGEST_LEN]> {
     let mut file = File::open(filepath)?;
     let mut hasher = blake3::Hasher::new();
 
+    let mut buf = vec![0; BUF_LEN];
     loop {
-        let mut buf = [0; BUF_LEN];
         let n = file.read(&mut buf)?;
-        hasher.update_rayon(&buf);
         if n == 0 {
             break;
         }
+        hasher.update_rayon(&buf[..n]);
     }
     Ok(hasher.finalize().into())
 }
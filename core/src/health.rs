@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Time after which a dependency is considered unavailable
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Readiness of a service together with the status of each of its dependencies
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Readiness {
+    pub ready: bool,
+    pub dependencies: BTreeMap<String, DependencyStatus>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DependencyStatus {
+    pub healthy: bool,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+impl Readiness {
+    /// A service is ready if all of its dependencies are healthy
+    pub fn new<'a>(dependencies: impl IntoIterator<Item = (&'a str, DependencyStatus)>) -> Self {
+        let dependencies: BTreeMap<String, DependencyStatus> = dependencies
+            .into_iter()
+            .map(|(name, status)| (String::from(name), status))
+            .collect();
+        Self {
+            ready: dependencies.values().all(|d| d.healthy),
+            dependencies,
+        }
+    }
+}
+
+impl DependencyStatus {
+    pub fn new(result: Result<()>, latency: Duration) -> Self {
+        Self {
+            healthy: result.is_ok(),
+            latency_ms: latency.as_millis().try_into().unwrap_or(u64::MAX),
+            error: result.err().map(|e| format!("{e:#}")),
+        }
+    }
+
+    /// Measure how long a dependency takes to respond to a ping
+    pub async fn check(ping: impl Future<Output = Result<()>>) -> Self {
+        let start = Instant::now();
+        let result = match tokio::time::timeout(CHECK_TIMEOUT, ping).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!(
+                "Timed out after {} seconds",
+                CHECK_TIMEOUT.as_secs()
+            )),
+        };
+        Self::new(result, start.elapsed())
+    }
+}
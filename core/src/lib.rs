@@ -1,8 +1,17 @@
 #![forbid(unsafe_code)]
 
+pub mod audit;
 pub mod encoding;
+pub mod error;
+pub mod event;
 pub mod handle;
 pub mod hash;
+pub mod health;
 pub mod metadata;
+pub mod notification;
+pub mod reconciliation;
 pub mod repository;
+pub mod run;
+pub mod tls;
+pub mod usage;
 pub mod user;
@@ -4,7 +4,10 @@ use crate::handle::Handle;
 
 #[serde_with::skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Metadata {
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     pub object_handle: Handle,
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<Object>))]
     pub custom: Option<serde_json::Value>,
 }
@@ -0,0 +1,154 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::event::{Event, EventKind};
+
+/// Destination that is notified about runs and outputs of a repository
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Channel {
+    pub id: String,
+    pub repository: String,
+    #[serde(flatten)]
+    pub target: Target,
+    pub triggers: Vec<Trigger>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NewChannel {
+    #[serde(flatten)]
+    pub target: Target,
+    /// Defaults to all triggers
+    pub triggers: Option<Vec<Trigger>>,
+}
+
+/// Channel as returned on creation. The secret of a webhook is only shown once.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreatedChannel {
+    #[serde(flatten)]
+    pub channel: Channel,
+    /// Key the payloads of a webhook are signed with
+    pub secret: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Target {
+    /// Generic HTTP endpoint receiving signed JSON payloads
+    Webhook {
+        url: String,
+    },
+    /// Slack or Mattermost incoming webhook
+    Slack {
+        url: String,
+    },
+    Email {
+        to: Vec<String>,
+    },
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    strum_macros::EnumString,
+    strum_macros::IntoStaticStr,
+)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Trigger {
+    RunSucceeded,
+    RunFailed,
+    OutputArtifact,
+}
+
+/// Payload of a generic webhook
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Notification {
+    pub trigger: Trigger,
+    pub repository: String,
+    pub summary: String,
+    pub event: Event,
+}
+
+/// Entry in the log of attempted deliveries
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Delivery {
+    pub channel: String,
+    pub repository: String,
+    pub event: u64,
+    pub trigger: Trigger,
+    #[serde(with = "crate::audit::timestamp")]
+    #[cfg_attr(feature = "openapi", schema(value_type = String, format = DateTime))]
+    pub timestamp: DateTime<Utc>,
+    pub attempts: u32,
+    /// Last error if the delivery ultimately failed
+    pub error: Option<String>,
+}
+
+impl Trigger {
+    pub const ALL: [Trigger; 3] = [
+        Trigger::RunSucceeded,
+        Trigger::RunFailed,
+        Trigger::OutputArtifact,
+    ];
+
+    /// Name of the trigger, e.g. `run_failed`
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+}
+
+impl Notification {
+    /// Notification about an event if it concerns a repository and channels can subscribe to it
+    pub fn from_event(event: &Event) -> Option<Self> {
+        let (trigger, repository, summary) = match &event.kind {
+            EventKind::WorkflowSucceeded {
+                repository,
+                workflow,
+                ..
+            } => (
+                Trigger::RunSucceeded,
+                repository,
+                format!("Workflow {workflow} of {repository} succeeded"),
+            ),
+            EventKind::WorkflowFailed {
+                repository,
+                workflow,
+                message,
+            } => (
+                Trigger::RunFailed,
+                repository,
+                match message {
+                    Some(message) => {
+                        format!("Workflow {workflow} of {repository} failed: {message}")
+                    }
+                    None => format!("Workflow {workflow} of {repository} failed"),
+                },
+            ),
+            EventKind::ArtifactUploaded {
+                handle,
+                repository: Some(repository),
+            } => (
+                Trigger::OutputArtifact,
+                repository,
+                format!("New output artifact {handle} of {repository}"),
+            ),
+            _ => return None,
+        };
+        Some(Self {
+            trigger,
+            repository: repository.clone(),
+            summary,
+            event: event.clone(),
+        })
+    }
+}
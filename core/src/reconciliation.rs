@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+/// Differences between the repositories in the database and their credentials in secret storage
+/// and Kubernetes
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ReconciliationReport {
+    pub drift: Vec<Drift>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Drift {
+    pub kind: DriftKind,
+    /// Name of the repository, fingerprint of the SSH key or name of the Kubernetes secret
+    pub subject: String,
+    pub resolution: Resolution,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, strum_macros::IntoStaticStr)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum DriftKind {
+    /// Private key of a repository is missing from secret storage
+    MissingSshKey,
+    /// Secret with the private key of a repository is missing from the argo namespace
+    MissingKubernetesSecret,
+    /// Private key in secret storage doesn't belong to any repository
+    OrphanedSshKey,
+    /// Secret in the argo namespace doesn't belong to any repository
+    OrphanedKubernetesSecret,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// Only reported because repairs were not requested
+    Reported,
+    Repaired,
+    /// Repair was attempted but failed
+    Failed,
+    /// Cannot be repaired automatically, e.g. because the private key is lost
+    Unrepairable,
+}
+
+impl DriftKind {
+    pub const ALL: [DriftKind; 4] = [
+        DriftKind::MissingSshKey,
+        DriftKind::MissingKubernetesSecret,
+        DriftKind::OrphanedSshKey,
+        DriftKind::OrphanedKubernetesSecret,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+}
@@ -1,22 +1,151 @@
 use std::fmt;
 
+use chrono::{DateTime, Utc};
+use glob::{MatchOptions, Pattern, PatternError};
 use serde::{Deserialize, Serialize};
 
+use crate::encoding::hex;
+use crate::hash::hash_buf;
+
+/// Label that marks the workflows of a repository in Kubernetes
+pub const REPOSITORY_LABEL: &str = "recesser.io/repository";
+
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct NewRepository {
+    /// Identifies the repository as `namespace/name` independently of where it's hosted
     pub name: String,
-    pub keypair: KeyPair,
+    /// Clone URL. Defaults to the SSH URL of the GitHub repository with the same name.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Deploy key, required for SSH URLs
+    #[serde(default)]
+    pub keypair: Option<KeyPair>,
+    /// Access token for HTTPS URLs. Public repositories and local paths need no credentials.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// User the access token is sent with. Defaults to `oauth2`, which GitLab, Gitea and GitHub
+    /// all accept.
+    #[serde(default)]
+    pub username: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Repository {
     pub name: String,
     pub url: String,
-    pub public_key: PublicKey,
+    /// Repositories registered before other hosts were supported are cloned via SSH
+    #[serde(default = "ssh_auth")]
+    pub auth: Auth,
+    /// Deploy key of repositories that are cloned via SSH
+    pub public_key: Option<PublicKey>,
     pub last_commit: CommitID,
+    /// New deploy key that replaces the current one once the schandler fetched with it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub staged_key: Option<PublicKey>,
+    /// Previous deploy keys that are revoked once no run of the repository may still use them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retired_keys: Vec<PublicKey>,
+    #[serde(default)]
+    pub triggers: Triggers,
+    /// Last processed commit of the watched branches and tags. The default branch is tracked
+    /// in `last_commit` instead.
+    #[serde(default)]
+    pub refs: Vec<TrackedRef>,
+    /// The Git host reports pushes via webhook. Polling continues as fallback.
+    #[serde(default)]
+    pub webhook: bool,
+}
+
+/// Which changes of a repository start runs
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Triggers {
+    /// Branches whose new commits start runs. Only the default branch is watched if empty.
+    #[serde(default)]
+    pub branches: Vec<String>,
+    /// Glob patterns of tags that start release runs
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Only commits that change a path matching one of these globs start runs. A trailing
+    /// slash matches everything below a directory. All paths match if empty.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Commits that only change paths matching one of these globs don't start runs
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Tags created before the tag patterns were last changed don't start runs. Set by the
+    /// apiserver.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>, format = DateTime))]
+    pub tags_since: Option<DateTime<Utc>>,
+}
+
+/// Branch or tag together with the last commit that was processed for it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TrackedRef {
+    /// Full name, e.g. `refs/heads/main` or `refs/tags/v1.0`
+    pub name: String,
+    pub commit: CommitID,
+}
+
+/// How the schandler and workflows authenticate to the Git host
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Auth {
+    /// Deploy key
+    Ssh,
+    /// Access token sent as password over HTTPS
+    Token { username: String },
+    /// Public repositories and local `file://` paths
+    Anonymous,
+}
+
+/// Protocol a repository is cloned with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport {
+    Ssh,
+    Http,
+    File,
 }
 
+/// Everything that was cleaned up when a repository was removed
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RemovalReport {
+    pub repository: String,
+    /// Private key was deleted from secret storage
+    pub ssh_key: bool,
+    /// Secret with the private key was deleted from the argo namespace
+    pub kubernetes_secret: bool,
+    /// Workflows that were still pending or running and have been stopped
+    pub stopped_runs: Vec<String>,
+    /// Completed workflows that were deleted or, if the history is kept, archived
+    pub finished_runs: Vec<String>,
+    /// Access token was deleted from secret storage and the argo namespace
+    pub token: bool,
+    /// Notification channels that were removed together with their webhook secrets
+    pub notification_channels: Vec<String>,
+    pub history_archived: bool,
+    /// Cleanup steps that failed. Leftover secrets are found by the reconciler.
+    pub errors: Vec<String>,
+}
+
+/// Push webhook to configure on the Git host
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreatedWebhook {
+    /// Path of the receiver on the apiserver
+    pub path: String,
+    /// Secret the Git host signs payloads with. Only returned once.
+    pub secret: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct KeyPair {
     pub private_key: PrivateKey,
     pub public_key: PublicKey,
@@ -24,32 +153,145 @@ pub struct KeyPair {
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(transparent)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct PrivateKey(String);
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct PublicKey {
     pub public_key: String,
     pub fingerprint: Fingerprint,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Fingerprint(String);
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct CommitID(Option<String>);
 
 impl Repository {
-    pub fn new(name: &str, public_key: PublicKey) -> Self {
-        let url = format!("git@github.com:{}.git", name);
+    pub fn new(name: &str, url: &str, auth: Auth, public_key: Option<PublicKey>) -> Self {
         Self {
             name: name.to_string(),
-            url,
+            url: url.to_string(),
+            auth,
             public_key,
             last_commit: CommitID::new(None),
+            staged_key: None,
+            retired_keys: Vec::new(),
+            triggers: Triggers::default(),
+            refs: Vec::new(),
+            webhook: false,
+        }
+    }
+
+    /// Last processed commit of a branch or tag
+    pub fn tracked_commit(&self, reference: &str) -> CommitID {
+        self.refs
+            .iter()
+            .find(|r| r.name == reference)
+            .map(|r| r.commit.clone())
+            .unwrap_or_else(|| CommitID::new(None))
+    }
+
+    /// Whether both are registered with the same URL, authentication and deploy key
+    pub fn same_registration(&self, other: &Repository) -> bool {
+        let fingerprint = |r: &Repository| {
+            r.public_key
+                .as_ref()
+                .map(|k| k.fingerprint.as_str().to_string())
+        };
+        self.url == other.url && self.auth == other.auth && fingerprint(self) == fingerprint(other)
+    }
+}
+
+/// SSH URL of the GitHub repository with the same name
+pub fn github_url(name: &str) -> String {
+    format!("git@github.com:{}.git", name)
+}
+
+/// Name of the secret with the access token of a repository in the argo namespace
+pub fn token_secret_name(name: &str) -> String {
+    format!("git-token-{}", label_value(name))
+}
+
+fn ssh_auth() -> Auth {
+    Auth::Ssh
+}
+
+impl Triggers {
+    /// Check that all patterns are valid globs
+    pub fn validate(&self) -> Result<(), PatternError> {
+        for glob in self.tags.iter().chain(&self.include).chain(&self.exclude) {
+            pattern(glob)?;
+        }
+        Ok(())
+    }
+
+    pub fn matches_tag(&self, tag: &str) -> bool {
+        matches_any(&self.tags, tag)
+    }
+
+    /// Whether a changed path is relevant for runs
+    pub fn matches_path(&self, path: &str) -> bool {
+        (self.include.is_empty() || matches_any(&self.include, path))
+            && !matches_any(&self.exclude, path)
+    }
+
+    /// Whether commits are filtered by the paths they change
+    pub fn filters_paths(&self) -> bool {
+        !self.include.is_empty() || !self.exclude.is_empty()
+    }
+}
+
+fn pattern(glob: &str) -> Result<Pattern, PatternError> {
+    match glob.strip_suffix('/') {
+        Some(directory) => Pattern::new(&format!("{directory}/**")),
+        None => Pattern::new(glob),
+    }
+}
+
+/// Invalid patterns are rejected when triggers are stored and never match
+fn matches_any(globs: &[String], s: &str) -> bool {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    globs
+        .iter()
+        .filter_map(|glob| pattern(glob).ok())
+        .any(|pattern| pattern.matches_with(s, options))
+}
+
+impl Transport {
+    /// Protocol of a clone URL. Besides `ssh://` URLs, the scp-like `user@host:path` syntax is
+    /// recognized as SSH.
+    pub fn of(url: &str) -> Option<Self> {
+        if let Some((scheme, _)) = url.split_once("://") {
+            return match scheme {
+                "ssh" | "git+ssh" => Some(Transport::Ssh),
+                "http" | "https" => Some(Transport::Http),
+                "file" => Some(Transport::File),
+                _ => None,
+            };
+        }
+        match url.split_once(':') {
+            Some((host, path)) if !host.contains('/') && !path.is_empty() => Some(Transport::Ssh),
+            _ => None,
         }
     }
 }
 
+/// Value of the label that marks the Kubernetes objects of a repository. Names can contain
+/// characters that are not allowed in label values, so the name is hashed.
+pub fn label_value(name: &str) -> String {
+    let mut value = hex::encode(&hash_buf(name.as_bytes()));
+    value.truncate(32);
+    value
+}
+
 impl PrivateKey {
     pub fn new(s: String) -> Self {
         Self(s)
@@ -86,6 +328,10 @@ impl CommitID {
     pub fn new(s: Option<String>) -> Self {
         Self(s)
     }
+
+    pub fn as_str(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
 }
 
 impl fmt::Display for CommitID {
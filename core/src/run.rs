@@ -0,0 +1,150 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::audit::timestamp;
+use crate::event::Output;
+
+/// Execution of the workflow of a repository for one commit. Recorded by the schandler before
+/// it submits the workflow to Argo and updated until the workflow completed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Run {
+    pub id: String,
+    pub repository: String,
+    pub commit: String,
+    /// Branch or tag the commit was detected on. `None` for the default branch.
+    pub reference: Option<String>,
+    /// Hex encoded hash of the recesser.yaml
+    pub workflow_hash: String,
+    /// Hex encoded hash of the Argo workflow as it was rendered for submission
+    pub manifest_hash: String,
+    /// Name Argo generated for the workflow. `None` until it's submitted.
+    pub argo_workflow: Option<String>,
+    /// Handles of the artifacts the workflow downloads
+    pub inputs: Vec<String>,
+    pub status: RunStatus,
+    /// Why the run failed
+    pub message: Option<String>,
+    #[serde(with = "timestamp")]
+    #[cfg_attr(feature = "openapi", schema(value_type = String, format = DateTime))]
+    pub created: DateTime<Utc>,
+    #[serde(default, with = "timestamp::optional")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>, format = DateTime))]
+    pub started: Option<DateTime<Utc>>,
+    #[serde(default, with = "timestamp::optional")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>, format = DateTime))]
+    pub finished: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub outputs: Vec<Output>,
+    /// Kept as history of a removed repository
+    #[serde(default)]
+    pub archived: bool,
+}
+
+/// Run reported by the schandler before it submits the workflow
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NewRun {
+    pub id: String,
+    pub repository: String,
+    pub commit: String,
+    pub reference: Option<String>,
+    pub workflow_hash: String,
+    pub manifest_hash: String,
+    pub inputs: Vec<String>,
+}
+
+/// Change of the status of a run reported by the schandler
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RunUpdate {
+    pub status: RunStatus,
+    /// Set once the workflow is submitted
+    pub argo_workflow: Option<String>,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub outputs: Vec<Output>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, strum_macros::IntoStaticStr)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum RunStatus {
+    /// Recorded but not yet submitted to Argo
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// Filters for listing runs
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct RunQuery {
+    /// Only list runs that haven't completed yet
+    #[serde(default)]
+    pub active: bool,
+    /// Include runs that were archived when their repository was removed
+    #[serde(default)]
+    pub archived: bool,
+    /// Only list the newest runs
+    pub limit: Option<i64>,
+}
+
+impl Run {
+    pub fn new(new_run: NewRun, created: DateTime<Utc>) -> Self {
+        Self {
+            id: new_run.id,
+            repository: new_run.repository,
+            commit: new_run.commit,
+            reference: new_run.reference,
+            workflow_hash: new_run.workflow_hash,
+            manifest_hash: new_run.manifest_hash,
+            argo_workflow: None,
+            inputs: new_run.inputs,
+            status: RunStatus::Pending,
+            message: None,
+            created,
+            started: None,
+            finished: None,
+            outputs: Vec::new(),
+            archived: false,
+        }
+    }
+
+    /// Apply a change of status. Completed runs can't change anymore.
+    pub fn update(&mut self, update: RunUpdate, now: DateTime<Utc>) -> anyhow::Result<()> {
+        if self.status.is_finished() {
+            anyhow::bail!("Run {} already completed", self.id);
+        }
+        if update.argo_workflow.is_some() {
+            self.argo_workflow = update.argo_workflow;
+        }
+        if update.status != RunStatus::Pending && self.started.is_none() {
+            self.started = Some(now);
+        }
+        if update.status.is_finished() {
+            self.finished = Some(now);
+        }
+        self.status = update.status;
+        self.message = update.message;
+        self.outputs = update.outputs;
+        Ok(())
+    }
+}
+
+impl RunStatus {
+    /// Name of the status, e.g. `succeeded`
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, RunStatus::Succeeded | RunStatus::Failed)
+    }
+}
@@ -0,0 +1,32 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rustls_pemfile::Item;
+
+/// Read all certificates of a PEM file, e.g. a CA bundle, as DER
+pub fn read_certificates(path: &Path) -> Result<Vec<Vec<u8>>> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+    );
+    let certificates = rustls_pemfile::certs(&mut reader)?;
+    if certificates.is_empty() {
+        anyhow::bail!("{} doesn't contain any certificates", path.display());
+    }
+    Ok(certificates)
+}
+
+/// Read the first private key of a PEM file as DER
+pub fn read_private_key(path: &Path) -> Result<Vec<u8>> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+    );
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => return Ok(key),
+            _ => continue,
+        }
+    }
+    anyhow::bail!("{} doesn't contain a private key", path.display())
+}
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// Storage used by a user or a project. Objects referenced by multiple artifacts are counted once.
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Usage {
+    pub name: String,
+    pub bytes: u64,
+    pub objects: u64,
+    pub quota: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UsageReport {
+    /// Storage used by the whole system. Objects shared between users or projects are counted
+    /// once.
+    pub total: Usage,
+    pub users: Vec<Usage>,
+    pub projects: Vec<Usage>,
+}
@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use strum_macros::EnumString;
 
 #[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct NewUser {
     pub scope: Scope,
 }
@@ -12,13 +13,21 @@ impl NewUser {
     }
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Initialization {
+    pub bootstrap_secret: Option<String>,
+}
+
 #[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct User {
     pub id: String,
     pub scope: Scope,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, EnumString)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum Scope {
     User,
     Admin,
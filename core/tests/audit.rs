@@ -0,0 +1,57 @@
+use anyhow::Result;
+use chrono::Utc;
+use recesser_core::audit::{AuditRecord, Outcome};
+use recesser_core::user::Scope;
+
+#[test]
+fn intact_chain_verifies() -> Result<()> {
+    let records = mock_chain(3)?;
+    AuditRecord::verify_chain(&records)
+}
+
+#[test]
+fn detects_modified_record() -> Result<()> {
+    let mut records = mock_chain(3)?;
+    records[1].actor = Some("someoneElse".into());
+    assert!(AuditRecord::verify_chain(&records).is_err());
+    Ok(())
+}
+
+#[test]
+fn detects_removed_record() -> Result<()> {
+    let mut records = mock_chain(3)?;
+    records.remove(1);
+    assert!(AuditRecord::verify_chain(&records).is_err());
+    Ok(())
+}
+
+#[test]
+fn detects_rehashed_record() -> Result<()> {
+    let mut records = mock_chain(3)?;
+    records[1].action = "artifact.delete".into();
+    records[1].hash = records[1].compute_hash()?;
+    assert!(AuditRecord::verify_chain(&records).is_err());
+    Ok(())
+}
+
+fn mock_chain(len: u64) -> Result<Vec<AuditRecord>> {
+    let mut records: Vec<AuditRecord> = Vec::new();
+    for sequence in 0..len {
+        let mut record = AuditRecord {
+            sequence,
+            timestamp: Utc::now(),
+            actor: Some("mockUser".into()),
+            scope: Some(Scope::User),
+            action: "artifact.upload".into(),
+            target: Some("notAHandle".into()),
+            outcome: Outcome::Success,
+            status: 200,
+            source_ip: Some("127.0.0.1".into()),
+            previous_hash: records.last().map(|r| r.hash.clone()).unwrap_or_default(),
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash()?;
+        records.push(record);
+    }
+    Ok(records)
+}
@@ -0,0 +1,42 @@
+use anyhow::Result;
+use chrono::Utc;
+use recesser_core::event::{Event, EventFilter, EventKind};
+
+#[test]
+fn serializes_type_next_to_fields() -> Result<()> {
+    let event = mock_event(Some("thesis"));
+    let value = serde_json::to_value(&event)?;
+    assert_eq!(value["type"], "workflow_failed");
+    assert_eq!(value["workflow"], "train-x7k2p");
+    let deserialized: Event = serde_json::from_value(value)?;
+    assert_eq!(deserialized.kind, event.kind);
+    Ok(())
+}
+
+#[test]
+fn filters_by_type_and_project() {
+    let event = mock_event(Some("thesis"));
+    let filter = |types: Option<&str>, project: Option<&str>| EventFilter {
+        types: types.map(String::from),
+        project: project.map(String::from),
+    };
+    assert!(filter(None, None).matches(&event));
+    assert!(filter(Some("workflow_succeeded, workflow_failed"), None).matches(&event));
+    assert!(filter(None, Some("thesis")).matches(&event));
+    assert!(!filter(Some("artifact_uploaded"), None).matches(&event));
+    assert!(!filter(None, Some("other")).matches(&event));
+    assert!(!filter(None, Some("thesis")).matches(&mock_event(None)));
+}
+
+fn mock_event(project: Option<&str>) -> Event {
+    Event {
+        id: 42,
+        timestamp: Utc::now(),
+        project: project.map(String::from),
+        kind: EventKind::WorkflowFailed {
+            repository: "recesser/example".into(),
+            workflow: "train-x7k2p".into(),
+            message: Some("child failed".into()),
+        },
+    }
+}
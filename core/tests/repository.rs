@@ -0,0 +1,43 @@
+use recesser_core::repository::{Transport, Triggers};
+
+#[test]
+fn detects_transport_of_clone_urls() {
+    let cases = [
+        ("git@github.com:recesser/recesser.git", Some(Transport::Ssh)),
+        (
+            "ssh://git@gitlab.example.edu:2222/ml/thesis.git",
+            Some(Transport::Ssh),
+        ),
+        (
+            "https://gitlab.example.edu/ml/thesis.git",
+            Some(Transport::Http),
+        ),
+        ("file:///srv/git/example.git", Some(Transport::File)),
+        ("ftp://example.com/repo.git", None),
+        ("/srv/git/example.git", None),
+    ];
+    for (url, expected) in cases {
+        assert_eq!(Transport::of(url), expected, "{url}");
+    }
+}
+
+#[test]
+fn filters_paths_and_tags() {
+    let triggers = Triggers {
+        tags: vec!["v*".into()],
+        include: vec!["analysis/".into(), "requirements.txt".into()],
+        exclude: vec!["**/*.md".into()],
+        ..Default::default()
+    };
+    assert!(triggers.validate().is_ok());
+
+    assert!(triggers.matches_path("analysis/train.py"));
+    assert!(triggers.matches_path("analysis/models/cnn.py"));
+    assert!(triggers.matches_path("requirements.txt"));
+    assert!(!triggers.matches_path("analysis/README.md"));
+    assert!(!triggers.matches_path("README.md"));
+    assert!(!triggers.matches_path("docs/requirements.txt"));
+
+    assert!(triggers.matches_tag("v1.0"));
+    assert!(!triggers.matches_tag("nightly"));
+}
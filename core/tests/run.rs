@@ -0,0 +1,69 @@
+use anyhow::Result;
+use chrono::{Duration, TimeZone, Utc};
+use recesser_core::run::{NewRun, Run, RunStatus, RunUpdate};
+
+#[test]
+fn tracks_lifecycle_of_run() -> Result<()> {
+    let created = Utc.ymd(2022, 5, 1).and_hms(12, 0, 0);
+    let mut run = mock_run(created);
+    assert_eq!(run.status, RunStatus::Pending);
+    assert!(run.started.is_none());
+
+    let submitted = created + Duration::seconds(2);
+    run.update(
+        update(RunStatus::Running, Some("analysis-x7k2p")),
+        submitted,
+    )?;
+    assert_eq!(run.started, Some(submitted));
+    assert_eq!(run.argo_workflow.as_deref(), Some("analysis-x7k2p"));
+
+    // Later updates don't need to repeat the workflow name
+    let completed = submitted + Duration::minutes(5);
+    run.update(update(RunStatus::Succeeded, None), completed)?;
+    assert_eq!(run.started, Some(submitted));
+    assert_eq!(run.finished, Some(completed));
+    assert_eq!(run.argo_workflow.as_deref(), Some("analysis-x7k2p"));
+
+    let result = run.update(update(RunStatus::Failed, None), completed);
+    assert!(result.is_err());
+    assert_eq!(run.status, RunStatus::Succeeded);
+    Ok(())
+}
+
+#[test]
+fn serializes_timestamps_of_run() -> Result<()> {
+    let created = Utc.ymd(2022, 5, 1).and_hms(12, 0, 0);
+    let mut run = mock_run(created);
+    let json = serde_json::to_value(&run)?;
+    assert_eq!(json["status"], "pending");
+    assert!(json["started"].is_null());
+
+    run.update(update(RunStatus::Failed, None), created)?;
+    let json = serde_json::to_string(&run)?;
+    let deserialized: Run = serde_json::from_str(&json)?;
+    assert_eq!(deserialized, run);
+    assert_eq!(deserialized.finished, Some(created));
+    Ok(())
+}
+
+fn mock_run(created: chrono::DateTime<Utc>) -> Run {
+    let new_run = NewRun {
+        id: "5f0c8a9e-7d1b-4c36-9a51-1f2d3e4b5c6d".into(),
+        repository: "recesser/analysis".into(),
+        commit: "1b4e28ba2fa1d3b4c6e7d8f9a0b1c2d3e4f5a6b7".into(),
+        reference: None,
+        workflow_hash: "ab".repeat(32),
+        manifest_hash: "cd".repeat(32),
+        inputs: vec!["e3b0c44298fc1c149afbf4c8996fb924".into()],
+    };
+    Run::new(new_run, created)
+}
+
+fn update(status: RunStatus, argo_workflow: Option<&str>) -> RunUpdate {
+    RunUpdate {
+        status,
+        argo_workflow: argo_workflow.map(String::from),
+        message: None,
+        outputs: Vec::new(),
+    }
+}
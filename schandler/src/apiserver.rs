@@ -1,20 +1,26 @@
 use anyhow::Result;
-use recesser_core::repository::{CommitID, Repository};
+use recesser_core::error::ApiError;
+use recesser_core::event::NewEvent;
+use recesser_core::repository::{CommitID, Fingerprint, Repository};
+use recesser_core::run::{NewRun, Run, RunUpdate};
 use reqwest::{header, Client, Response};
 
+use crate::tls::ClientTls;
+
+#[derive(Clone)]
 pub struct Apiserver {
     addr: String,
     client: Client,
 }
 
 impl Apiserver {
-    pub fn new(addr: &str, token: &str) -> Result<Self> {
+    pub fn new(addr: &str, token: &str, tls: &ClientTls) -> Result<Self> {
         let mut headers = header::HeaderMap::new();
         headers.insert(
             header::AUTHORIZATION,
             format!("Bearer {}", token).try_into()?,
         );
-        let cb = Client::builder().default_headers(headers);
+        let cb = tls.apply(Client::builder().default_headers(headers))?;
         Ok(Self {
             addr: String::from(addr),
             client: cb.build()?,
@@ -25,17 +31,73 @@ impl Apiserver {
         format!("{addr}{path}", addr = self.addr)
     }
 
+    pub async fn ping(&self) -> Result<()> {
+        let resp = self.client.get(self.url("/healthz")).send().await?;
+        check_body(resp).await?;
+        Ok(())
+    }
+
     pub async fn list_repositories(&self) -> Result<Vec<Repository>> {
-        let resp = self.client.get(self.url("/repositories")).send().await?;
+        let resp = self.client.get(self.url("/v1/repositories")).send().await?;
         let body = check_body(resp).await?;
         let repos: Vec<Repository> = serde_json::from_slice(&body)?;
         Ok(repos)
     }
 
+    pub async fn get_repository(&self, name: &str) -> Result<Repository> {
+        let resp = self
+            .client
+            .get(self.url(&format!("/v1/repositories/{name}")))
+            .send()
+            .await?;
+        let body = check_body(resp).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Open a stream of Server-Sent Events of the given types that resumes after an event
+    pub async fn subscribe_events(
+        &self,
+        types: &str,
+        last_event_id: Option<u64>,
+    ) -> Result<Response> {
+        let mut request = self
+            .client
+            .get(self.url("/v1/events"))
+            .query(&[("types", types)]);
+        if let Some(id) = last_event_id {
+            request = request.header("Last-Event-ID", id);
+        }
+        let resp = request.send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status().as_u16();
+            let body = resp.bytes().await?;
+            return Err(ApiError::new(status, &body).into());
+        }
+        Ok(resp)
+    }
+
     pub async fn update_last_commit(&self, name: &str, new_commit: &CommitID) -> Result<()> {
         let resp = self
             .client
-            .put(self.url(&format!("/repositories/{name}/last-commit")))
+            .put(self.url(&format!("/v1/repositories/{name}/last-commit")))
+            .body(new_commit.to_string())
+            .send()
+            .await?;
+        check_body(resp).await?;
+        Ok(())
+    }
+
+    /// Update the last processed commit of a watched branch or tag
+    pub async fn update_tracked_ref(
+        &self,
+        name: &str,
+        reference: &str,
+        new_commit: &CommitID,
+    ) -> Result<()> {
+        let resp = self
+            .client
+            .put(self.url(&format!("/v1/repositories/{name}/last-commit")))
+            .query(&[("reference", reference)])
             .body(new_commit.to_string())
             .send()
             .await?;
@@ -43,20 +105,91 @@ impl Apiserver {
         Ok(())
     }
 
-    pub async fn get_ssh_key(&self, name: &str) -> Result<String> {
+    /// Private deploy key or access token, depending on how the repository is cloned
+    pub async fn get_credentials(&self, name: &str) -> Result<String> {
+        self.credentials(name, false).await
+    }
+
+    /// Private key that is staged to replace the current deploy key
+    pub async fn get_staged_ssh_key(&self, name: &str) -> Result<String> {
+        self.credentials(name, true).await
+    }
+
+    /// Confirm that the repository could be fetched with the staged key
+    pub async fn confirm_staged_key(&self, name: &str, fingerprint: &Fingerprint) -> Result<()> {
         let resp = self
             .client
-            .get(self.url(&format!("/repositories/{name}/credentials")))
+            .post(self.url(&format!("/v1/repositories/{name}/staged-key/confirm")))
+            .body(fingerprint.to_string())
+            .send()
+            .await?;
+        check_body(resp).await?;
+        Ok(())
+    }
+
+    async fn credentials(&self, name: &str, staged: bool) -> Result<String> {
+        let resp = self
+            .client
+            .get(self.url(&format!("/v1/repositories/{name}/credentials")))
+            .query(&[("staged", staged)])
             .send()
             .await?;
         let body = check_body(resp).await?;
         Ok(String::from_utf8(body)?)
     }
+
+    pub async fn report_event(&self, event: &NewEvent) -> Result<()> {
+        let resp = self
+            .client
+            .post(self.url("/v1/events"))
+            .json(event)
+            .send()
+            .await?;
+        check_body(resp).await?;
+        Ok(())
+    }
+
+    /// Record a run before its workflow is submitted
+    pub async fn add_run(&self, run: &NewRun) -> Result<()> {
+        let resp = self
+            .client
+            .post(self.url("/v1/runs"))
+            .json(run)
+            .send()
+            .await?;
+        check_body(resp).await?;
+        Ok(())
+    }
+
+    pub async fn update_run(&self, id: &str, update: &RunUpdate) -> Result<()> {
+        let resp = self
+            .client
+            .put(self.url(&format!("/v1/runs/{id}/status")))
+            .json(update)
+            .send()
+            .await?;
+        check_body(resp).await?;
+        Ok(())
+    }
+
+    /// Runs of all repositories that haven't completed yet
+    pub async fn list_active_runs(&self) -> Result<Vec<Run>> {
+        let resp = self
+            .client
+            .get(self.url("/v1/runs"))
+            .query(&[("active", true)])
+            .send()
+            .await?;
+        let body = check_body(resp).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
 }
 
 async fn check_body(resp: Response) -> Result<Vec<u8>> {
     if !resp.status().is_success() {
-        anyhow::bail!(resp.text().await?)
+        let status = resp.status().as_u16();
+        let body = resp.bytes().await?;
+        return Err(ApiError::new(status, &body).into());
     }
     Ok(resp.bytes().await?.to_vec())
 }
@@ -1,19 +1,31 @@
+mod matrix;
+mod outputs;
+mod pipeline;
 mod template;
 
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::path::Path;
 
 use anyhow::Result;
 use recesser_core::encoding::hex;
-use recesser_core::repository::Repository;
-use reqwest::{header, Client};
+use recesser_core::event::Output;
+use recesser_core::hash::hash_buf;
+use recesser_core::repository::{self, Auth, Repository};
+use reqwest::{header, Client, StatusCode};
 use serde::{Deserialize, Serialize};
 
+use crate::tls::ClientTls;
 use crate::workflow::{Kind, Workflow};
 
-use template::{construct_from_template, Template};
+use outputs::{Executor, Provenance};
+use template::{construct_from_template, Manifest};
 
 const TOKEN_PATH: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
 
+/// Template of the step that uploads the outputs and reports their handles
+const UPLOAD_TEMPLATE: &str = "upload-outputs";
+
 /// HTTP client for Argo Workflows server
 #[derive(Clone)]
 pub struct ArgoWorkflowsServer {
@@ -22,20 +34,34 @@ pub struct ArgoWorkflowsServer {
 }
 
 impl ArgoWorkflowsServer {
-    pub fn new(addr: &str) -> Result<Self> {
+    pub fn new(addr: &str, tls: &ClientTls) -> Result<Self> {
         let token = fs::read_to_string(TOKEN_PATH)?;
         let mut headers = header::HeaderMap::new();
         headers.insert(header::AUTHORIZATION, format!("Bearer {token}").try_into()?);
-        let cb = Client::builder()
-            .default_headers(headers)
-            .danger_accept_invalid_certs(true);
+        let cb = tls.apply(Client::builder().default_headers(headers))?;
         Ok(Self {
             addr: String::from(addr),
             client: cb.build()?,
         })
     }
 
-    pub async fn submit(&self, workflow: &ArgoWorkflow) -> Result<()> {
+    pub async fn ping(&self) -> Result<()> {
+        let resp = self
+            .client
+            .get(format!("{}/api/v1/version", self.addr))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            anyhow::bail!(
+                "Argo Workflows server responded with status {}",
+                resp.status()
+            );
+        }
+        Ok(())
+    }
+
+    /// Submit a workflow and return the name Argo generated for it
+    pub async fn submit(&self, workflow: &ArgoWorkflow) -> Result<String> {
         tracing::debug!(message = "Submitting workflow", workflow = ?workflow);
         let result = self
             .client
@@ -44,40 +70,323 @@ impl ArgoWorkflowsServer {
             .send()
             .await?;
         tracing::debug!(message = "Result from argo", result = ?result);
-        Ok(())
+        if !result.status().is_success() {
+            anyhow::bail!(
+                "Argo Workflows server responded with status {}",
+                result.status()
+            );
+        }
+        let submitted: SubmittedWorkflow = result.json().await?;
+        Ok(submitted.metadata.name)
+    }
+
+    /// Status of a workflow or `None` if it was deleted, e.g. because its repository was removed
+    pub async fn status(&self, name: &str) -> Result<Option<WorkflowStatus>> {
+        let resp = self
+            .client
+            .get(format!("{}/api/v1/workflows/argo/{name}", self.addr))
+            .send()
+            .await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            anyhow::bail!(
+                "Argo Workflows server responded with status {}",
+                resp.status()
+            );
+        }
+        let workflow: SubmittedWorkflow = resp.json().await?;
+        Ok(Some(workflow.status))
+    }
+}
+
+#[derive(Deserialize)]
+struct SubmittedWorkflow {
+    metadata: SubmittedMetadata,
+    #[serde(default)]
+    status: WorkflowStatus,
+}
+
+#[derive(Deserialize)]
+struct SubmittedMetadata {
+    name: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct WorkflowStatus {
+    /// Newly created workflows don't have a phase yet
+    pub phase: Option<Phase>,
+    pub message: Option<String>,
+    /// Steps and pods of the workflow by their ID
+    #[serde(default)]
+    pub nodes: HashMap<String, Node>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Node {
+    pub template_name: Option<String>,
+    pub inputs: Option<Parameters>,
+    pub outputs: Option<Parameters>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Parameters {
+    #[serde(default)]
+    pub parameters: Vec<Parameter>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl WorkflowStatus {
+    /// Outputs as reported by the upload steps, one for each cell of a matrix. Empty if the
+    /// workflow has no outputs.
+    pub fn uploaded_outputs(&self) -> Result<Vec<Output>> {
+        let mut outputs = Vec::new();
+        for node in self.nodes.values() {
+            if node.template_name.as_deref() != Some(UPLOAD_TEMPLATE) {
+                continue;
+            }
+            let handles = match node.outputs.as_ref().and_then(|o| o.value("handles")) {
+                Some(handles) => handles,
+                None => continue,
+            };
+            let handles: BTreeMap<String, String> = serde_json::from_str(handles)?;
+            let parameters: BTreeMap<String, String> = node
+                .inputs
+                .iter()
+                .flat_map(|inputs| &inputs.parameters)
+                .filter_map(|p| Some((p.name.clone(), p.value.clone()?)))
+                .collect();
+            outputs.extend(handles.into_iter().map(|(path, handle)| Output {
+                path,
+                handle,
+                parameters: parameters.clone(),
+            }));
+        }
+        outputs.sort_by(|a, b| (&a.parameters, &a.path).cmp(&(&b.parameters, &b.path)));
+        Ok(outputs)
     }
 }
 
+impl Parameters {
+    fn value(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.value.as_deref())
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub enum Phase {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Error,
+    #[serde(other)]
+    Unknown,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(transparent)]
 pub struct ArgoWorkflow(serde_json::Value);
 
+/// Immutable source revision a workflow runs
+#[derive(Serialize, Debug)]
+pub struct Source {
+    /// Full ID of the commit that is checked out
+    pub commit: String,
+    /// Branch or tag the commit was detected on. `None` for the default branch.
+    pub reference: Option<String>,
+}
+
+/// In-cluster build of the image a custom workflow runs
+#[derive(Serialize, Debug)]
+pub struct Build {
+    /// Reference of the image in the registry. Tagged with the hash of the build context.
+    pub image: String,
+    /// Paths of the Dockerfile and the directory it's in relative to the repository root
+    pub dockerfile: String,
+    pub context: String,
+    /// Whether the registry is reached over plain HTTP
+    pub insecure: bool,
+    /// Whether the registry already has the image, so that it isn't built again
+    pub cached: bool,
+}
+
 impl ArgoWorkflow {
-    pub fn from_workflow(workflow: Workflow, repository: Repository) -> Result<Self> {
+    /// Hex encoded hash of the manifest as it's submitted
+    pub fn hash(&self) -> Result<String> {
+        let manifest = serde_json::to_vec(&self.0)?;
+        Ok(hex::encode(&hash_buf(&manifest)))
+    }
+
+    /// Construct the workflow of a run that checks out exactly the commit of `source`.
+    /// Workflows that build images need a build for each of their Dockerfiles.
+    pub fn from_workflow(
+        workflow: Workflow,
+        repository: Repository,
+        source: &Source,
+        run_id: &str,
+        builds: &[Build],
+    ) -> Result<Self> {
         let metadata = workflow.metadata;
-        // Mostly an ugly hack to keep to the Kubernetes constraint of volume names not being
-        // allowed to exceed 63 characters
-        let short_fingerprint: String = repository
-            .public_key
-            .fingerprint
-            .to_string()
-            .chars()
-            .take(20)
-            .collect();
+        let workflow_hash = workflow.hash;
+        // Label values are limited to 63 characters
+        let mut workflow_hash_label = workflow_hash.clone();
+        workflow_hash_label.truncate(32);
+        let ssh_key_secret = match &repository.public_key {
+            Some(public_key) => {
+                // Mostly an ugly hack to keep to the Kubernetes constraint of volume names not
+                // being allowed to exceed 63 characters
+                let short_fingerprint: String = public_key
+                    .fingerprint
+                    .to_string()
+                    .chars()
+                    .take(20)
+                    .collect();
+                Some(hex::encode_str(&short_fingerprint)?)
+            }
+            None => None,
+        };
+        let token_secret = match repository.auth {
+            Auth::Token { .. } => Some(repository::token_secret_name(&repository.name)),
+            _ => None,
+        };
+        let provenance = |inputs, executors| Provenance {
+            repository: &repository.name,
+            repository_url: &repository.url,
+            commit: &source.commit,
+            reference: source.reference.as_deref(),
+            run_id,
+            argo_workflow: "{{workflow.name}}",
+            workflow_hash: &workflow_hash,
+            inputs,
+            executors,
+        };
+        let repository_context = minijinja::context!(
+            label => repository::label_value(&repository.name),
+            name => repository.name,
+            url => repository.url,
+            ssh_key_fingerprint => ssh_key_secret,
+            token_secret
+        );
         let workflow = match workflow.kind {
-            Kind::TemplateWorkflow(workflow) => construct_from_template(
-                Template::TemplateWorkflow,
-                minijinja::context!(
-                    metadata,
-                    workflow,
-                    repository => minijinja::context!(
-                        name => repository.name,
-                        url => repository.url,
-                        ssh_key_fingerprint => hex::encode_str(&short_fingerprint)?
-                    )
-                ),
-            )?,
-            _ => return Err(anyhow::anyhow!("CustomTemplate is not yet implemented")),
+            Kind::TemplateWorkflow(workflow) => {
+                let uploads = outputs::file_uploads(&workflow.outputs)?;
+                let executors = vec![Executor {
+                    step: None,
+                    template: Some(&workflow.template),
+                    image: outputs::template_image(&workflow.template),
+                }];
+                let inputs = workflow.inputs.clone().unwrap_or_default();
+                let provenance = provenance(&inputs, executors).to_literal()?;
+                let matrix = workflow.matrix.as_ref().map(matrix::cells).transpose()?;
+                construct_from_template(
+                    Manifest::TemplateWorkflow,
+                    minijinja::context!(
+                        metadata,
+                        workflow,
+                        source,
+                        run_id,
+                        workflow_hash,
+                        workflow_hash_label,
+                        repository => repository_context,
+                        uploads,
+                        provenance,
+                        matrix
+                    ),
+                )?
+            }
+            Kind::CustomWorkflow(workflow) => {
+                let build = match workflow.dockerfile()? {
+                    Some(dockerfile) => Some(
+                        builds
+                            .iter()
+                            .find(|b| Path::new(&b.dockerfile) == dockerfile)
+                            .ok_or_else(|| {
+                                anyhow::anyhow!(
+                                    "Custom workflow builds its image but no build was planned"
+                                )
+                            })?,
+                    ),
+                    None => None,
+                };
+                let image = match (&workflow.image, build) {
+                    (Some(image), _) => image.clone(),
+                    (None, Some(build)) => build.image.clone(),
+                    (None, None) => anyhow::bail!("Custom workflow has no image"),
+                };
+                let build = build.filter(|b| !b.cached);
+                let uploads = outputs::file_uploads(&workflow.outputs)?;
+                let executors = vec![Executor {
+                    step: None,
+                    template: None,
+                    image: image.clone(),
+                }];
+                let provenance = provenance(&workflow.inputs, executors).to_literal()?;
+                let matrix = workflow.matrix.as_ref().map(matrix::cells).transpose()?;
+                construct_from_template(
+                    Manifest::CustomWorkflow,
+                    minijinja::context!(
+                        metadata,
+                        workflow,
+                        source,
+                        run_id,
+                        workflow_hash,
+                        workflow_hash_label,
+                        repository => repository_context,
+                        image,
+                        build,
+                        uploads,
+                        provenance,
+                        matrix
+                    ),
+                )?
+            }
+            Kind::Pipeline(workflow) => {
+                let (builds, tasks) = pipeline::tasks(&workflow, builds)?;
+                let uploads = outputs::step_uploads(&workflow.outputs);
+                let mut upload_dependencies: Vec<&String> =
+                    uploads.iter().filter_map(|u| u.step.as_ref()).collect();
+                upload_dependencies.sort();
+                upload_dependencies.dedup();
+                let executors = workflow
+                    .steps
+                    .iter()
+                    .zip(&tasks)
+                    .map(|(step, task)| Executor {
+                        step: Some(&step.name),
+                        template: step.template.as_ref(),
+                        image: task.image.clone(),
+                    })
+                    .collect();
+                let provenance = provenance(&workflow.inputs, executors).to_literal()?;
+                construct_from_template(
+                    Manifest::Pipeline,
+                    minijinja::context!(
+                        metadata,
+                        workflow,
+                        source,
+                        run_id,
+                        workflow_hash,
+                        workflow_hash_label,
+                        repository => repository_context,
+                        builds,
+                        tasks,
+                        uploads,
+                        upload_dependencies,
+                        provenance
+                    ),
+                )?
+            }
         };
 
         Ok(workflow)
@@ -0,0 +1,41 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::workflow::Matrix;
+
+/// Cells of a matrix as items of the step that runs them
+#[derive(Serialize, Debug)]
+pub struct MatrixCells {
+    pub parameters: Vec<MatrixParameter>,
+    /// Parameter values of each cell as JSON objects, which are also valid YAML
+    pub items: Vec<String>,
+    pub parallelism: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MatrixParameter {
+    pub name: String,
+    /// Environment variable the main container receives the value in
+    pub env: String,
+}
+
+pub fn cells(matrix: &Matrix) -> Result<MatrixCells> {
+    let parameters = matrix
+        .parameters
+        .keys()
+        .map(|name| MatrixParameter {
+            name: name.clone(),
+            env: format!("MATRIX_{}", name.to_ascii_uppercase()),
+        })
+        .collect();
+    let items = matrix
+        .cells()
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<Result<_, _>>()?;
+    Ok(MatrixCells {
+        parameters,
+        items,
+        parallelism: matrix.parallelism,
+    })
+}
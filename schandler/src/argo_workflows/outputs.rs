@@ -0,0 +1,89 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::workflow::{self, Template};
+
+/// Output of a workflow that the upload step receives as artifact
+#[derive(Serialize, Debug)]
+pub struct Upload {
+    pub name: String,
+    /// Path below `/outputs` in the upload step. Recorded as `output` in the provenance.
+    pub path: String,
+    /// Path in the main container or, for pipelines, name of the output of the step
+    pub source: String,
+    /// Step of a pipeline that produced the output
+    pub step: Option<String>,
+}
+
+/// Metadata that links uploaded outputs to the run that produced them
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Provenance<'a> {
+    pub repository: &'a str,
+    pub repository_url: &'a str,
+    pub commit: &'a str,
+    pub reference: Option<&'a str>,
+    pub run_id: &'a str,
+    /// Name Argo generated for the workflow. Substituted by Argo when the step runs.
+    pub argo_workflow: &'static str,
+    pub workflow_hash: &'a str,
+    pub inputs: &'a [String],
+    pub executors: Vec<Executor<'a>>,
+}
+
+/// Template executor or image that ran the workflow or a step of a pipeline
+#[derive(Serialize, Debug)]
+pub struct Executor<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<&'a Template>,
+    pub image: String,
+}
+
+impl<'a> Provenance<'a> {
+    /// Serialize as a quoted JSON string that is also a valid YAML scalar
+    pub fn to_literal(&self) -> Result<String> {
+        Ok(serde_json::to_string(&serde_json::to_string(self)?)?)
+    }
+}
+
+/// Uploads of the files and directories the main container of a workflow produced
+pub fn file_uploads(outputs: &[String]) -> Result<Vec<Upload>> {
+    outputs
+        .iter()
+        .enumerate()
+        .map(|(i, output)| {
+            let path = workflow::output_path(output)?.display().to_string();
+            let source = match output.starts_with('/') {
+                true => output.clone(),
+                false => format!("/src/{path}"),
+            };
+            Ok(Upload {
+                name: format!("output-{i}"),
+                path,
+                source,
+                step: None,
+            })
+        })
+        .collect()
+}
+
+/// Uploads of the outputs of pipeline steps given as `<step>.<output>`
+pub fn step_uploads(outputs: &[String]) -> Vec<Upload> {
+    outputs
+        .iter()
+        .filter_map(|output| output.split_once('.'))
+        .map(|(step, output)| Upload {
+            name: format!("{step}-{output}"),
+            path: format!("{step}/{output}"),
+            source: output.to_string(),
+            step: Some(step.to_string()),
+        })
+        .collect()
+}
+
+/// Image of a template executor
+pub fn template_image(template: &Template) -> String {
+    format!("recesser/{}-template:{}", template.name, template.version)
+}
@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use super::outputs::template_image;
+use super::Build;
+use crate::workflow::{self, Input, Pipeline, Step};
+
+/// Task that downloads the artifacts of the pipeline
+const DOWNLOAD_TASK: &str = "download-artifacts";
+
+/// Task of the DAG that builds an image
+#[derive(Serialize, Debug)]
+pub struct BuildTask<'a> {
+    pub name: String,
+    pub build: &'a Build,
+}
+
+/// Task of the DAG that runs a step
+#[derive(Serialize, Debug)]
+pub struct StepTask {
+    pub name: String,
+    pub image: String,
+    pub command: Vec<String>,
+    pub args: Vec<String>,
+    pub working_dir: Option<String>,
+    pub inputs: Vec<TaskInput>,
+    pub outputs: Vec<String>,
+    /// Tasks that need to succeed before this one starts
+    pub dependencies: Vec<String>,
+}
+
+/// Artifact a task receives from the output of another task
+#[derive(Serialize, Debug)]
+pub struct TaskInput {
+    pub name: String,
+    pub path: String,
+    pub task: String,
+    pub artifact: String,
+}
+
+/// Tasks for the images that need to be built and for the steps of a pipeline
+pub fn tasks<'a>(
+    pipeline: &Pipeline,
+    builds: &'a [Build],
+) -> Result<(Vec<BuildTask<'a>>, Vec<StepTask>)> {
+    let build_tasks: Vec<BuildTask> = builds
+        .iter()
+        .enumerate()
+        .filter(|(_, build)| !build.cached)
+        .map(|(i, build)| BuildTask {
+            name: build_task(i),
+            build,
+        })
+        .collect();
+
+    let mut step_tasks = Vec::new();
+    for step in &pipeline.steps {
+        let mut dependencies = step.depends_on.clone();
+        let image = match (&step.template, &step.image, &step.build) {
+            (Some(template), _, _) => template_image(template),
+            (None, Some(image), _) => image.clone(),
+            (None, None, Some(dockerfile)) => {
+                let dockerfile = workflow::relative_path(dockerfile)?;
+                let i = builds
+                    .iter()
+                    .position(|b| Path::new(&b.dockerfile) == dockerfile)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Step {} builds its image but no build was planned",
+                            step.name
+                        )
+                    })?;
+                if !builds[i].cached {
+                    dependencies.push(build_task(i));
+                }
+                builds[i].image.clone()
+            }
+            (None, None, None) => anyhow::bail!("Step {} has no image", step.name),
+        };
+
+        let inputs = task_inputs(step);
+        if inputs.iter().any(|i| i.task == DOWNLOAD_TASK) {
+            dependencies.push(String::from(DOWNLOAD_TASK));
+        }
+        let paths = inputs.iter().map(|i| i.path.clone());
+        let mut args = step.args.clone().unwrap_or_default();
+        let (command, args, working_dir) = match &step.template {
+            // Template executors take their arguments after the script
+            Some(_) => {
+                let mut command = vec![
+                    String::from("bash"),
+                    String::from("entrypoint.sh"),
+                    step.dependencies.clone().unwrap_or_default(),
+                ];
+                command.extend(step.entrypoint.iter().cloned());
+                command.extend(args);
+                command.extend(paths);
+                (command, Vec::new(), None)
+            }
+            None => {
+                args.extend(paths);
+                let working_dir = step
+                    .working_dir
+                    .clone()
+                    .unwrap_or_else(|| String::from("/src"));
+                (step.entrypoint.clone(), args, Some(working_dir))
+            }
+        };
+
+        step_tasks.push(StepTask {
+            name: step.name.clone(),
+            image,
+            command,
+            args,
+            working_dir,
+            inputs,
+            outputs: step.outputs.clone(),
+            dependencies,
+        });
+    }
+    Ok((build_tasks, step_tasks))
+}
+
+fn task_inputs(step: &Step) -> Vec<TaskInput> {
+    step.inputs()
+        .map(|input| match input {
+            Input::Artifact(handle) => TaskInput {
+                name: handle.to_string(),
+                path: format!("/tmp/{handle}"),
+                task: String::from(DOWNLOAD_TASK),
+                artifact: handle.to_string(),
+            },
+            Input::Output { step, output } => TaskInput {
+                name: format!("{step}-{output}"),
+                path: format!("/tmp/{step}/{output}"),
+                task: step.to_string(),
+                artifact: output.to_string(),
+            },
+        })
+        .collect()
+}
+
+fn build_task(i: usize) -> String {
+    format!("build-image-{}", i + 1)
+}
@@ -4,29 +4,59 @@ use anyhow::Result;
 
 lazy_static::lazy_static! {
     static ref TEMPLATES: minijinja::Environment<'static> = {
+        // Nothing is escaped by default. Values from recesser.yaml need to be rendered with
+        // `tojson`, which quotes them as JSON strings that are also valid YAML scalars.
         let mut env = minijinja::Environment::new();
 
+        let metadata = include_str!("templates/_metadata.yml.j2");
+        env.add_template("metadata", metadata).unwrap();
+        let download_artifacts = include_str!("templates/_download_artifacts.yml.j2");
+        env.add_template("download_artifacts", download_artifacts).unwrap();
+        let source_code = include_str!("templates/_source_code.yml.j2");
+        env.add_template("source_code", source_code).unwrap();
+        let build_image = include_str!("templates/_build_image.yml.j2");
+        env.add_template("build_image", build_image).unwrap();
+        let upload_outputs = include_str!("templates/_upload_outputs.yml.j2");
+        env.add_template("upload_outputs", upload_outputs).unwrap();
+        let matrix_env = include_str!("templates/_matrix_env.yml.j2");
+        env.add_template("matrix_env", matrix_env).unwrap();
+        let main_steps = include_str!("templates/_main_steps.yml.j2");
+        env.add_template("main_steps", main_steps).unwrap();
+        let cell = include_str!("templates/_cell.yml.j2");
+        env.add_template("cell", cell).unwrap();
+        let steps = include_str!("templates/_steps.yml.j2");
+        env.add_template("steps", steps).unwrap();
+
         let template_workflow = include_str!("templates/template_workflow.yml.j2");
         env.add_template("template_workflow", template_workflow).unwrap();
+        let custom_workflow = include_str!("templates/custom_workflow.yml.j2");
+        env.add_template("custom_workflow", custom_workflow).unwrap();
+        let pipeline = include_str!("templates/pipeline.yml.j2");
+        env.add_template("pipeline", pipeline).unwrap();
 
         env
     };
 }
 
-pub enum Template {
+/// Templates of the Argo workflow manifests for each kind of workflow
+pub enum Manifest {
     TemplateWorkflow,
+    CustomWorkflow,
+    Pipeline,
 }
 
-impl fmt::Display for Template {
+impl fmt::Display for Manifest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
-            Template::TemplateWorkflow => "template_workflow",
+            Manifest::TemplateWorkflow => "template_workflow",
+            Manifest::CustomWorkflow => "custom_workflow",
+            Manifest::Pipeline => "pipeline",
         };
         write!(f, "{s}")
     }
 }
 
-pub fn construct_from_template<S, D>(template: Template, ctx: S) -> Result<D>
+pub fn construct_from_template<S, D>(template: Manifest, ctx: S) -> Result<D>
 where
     S: serde::Serialize,
     D: serde::de::DeserializeOwned,
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::Result;
+use recesser_core::repository::label_value;
+
+use crate::metrics;
+use crate::repository::{self, Credentials, LocalRepository};
+
+type Entries = Arc<Mutex<HashMap<String, Entry>>>;
+
+/// Bare clones of the polled repositories that are fetched incrementally. When the clones exceed
+/// the size budget, the least recently used ones are deleted.
+pub struct CloneCache {
+    repositories: PathBuf,
+    worktrees: PathBuf,
+    /// Size budget in bytes
+    budget: u64,
+    entries: Entries,
+}
+
+#[derive(Debug)]
+struct Entry {
+    size: u64,
+    last_used: SystemTime,
+    /// Number of leases that keep the entry from being evicted
+    leases: usize,
+}
+
+/// Marks a cache entry as in use until it's dropped
+#[derive(Debug)]
+pub struct Lease {
+    key: String,
+    entries: Entries,
+}
+
+impl CloneCache {
+    /// Open the cache in `root`. Worktrees left behind by a previous process are deleted.
+    pub fn new(root: &Path, budget: u64) -> Result<Self> {
+        let repositories = root.join("repositories");
+        let worktrees = root.join("worktrees");
+        if worktrees.exists() {
+            fs::remove_dir_all(&worktrees)?;
+        }
+        fs::create_dir_all(&repositories)?;
+        fs::create_dir_all(&worktrees)?;
+
+        let mut entries = HashMap::new();
+        for dir_entry in fs::read_dir(&repositories)? {
+            let dir_entry = dir_entry?;
+            let key = match dir_entry
+                .file_name()
+                .to_str()
+                .and_then(|n| n.strip_suffix(".git"))
+            {
+                Some(key) => key.to_string(),
+                None => continue,
+            };
+            let entry = Entry {
+                size: dir_size(&dir_entry.path())?,
+                last_used: dir_entry.metadata()?.modified()?,
+                leases: 0,
+            };
+            entries.insert(key, entry);
+        }
+
+        let cache = Self {
+            repositories,
+            worktrees,
+            budget,
+            entries: Arc::new(Mutex::new(entries)),
+        };
+        cache.evict();
+        Ok(cache)
+    }
+
+    /// Bare clone of a repository with all branches and tags of the remote. Only fetches if
+    /// the remote advertises refs that differ from the cached ones.
+    pub fn fetch(
+        &self,
+        name: &str,
+        url: &str,
+        credentials: &Credentials,
+    ) -> Result<LocalRepository> {
+        let remote_refs = repository::ls_remote(url, credentials)?;
+
+        let key = label_value(name);
+        let path = self.repositories.join(format!("{key}.git"));
+        let lease = self.lease(&key);
+        let mut local_repository =
+            match LocalRepository::open_or_init(&path, &self.worktrees, lease) {
+                Ok(local_repository) => local_repository,
+                Err(e) => {
+                    tracing::warn!(error = %e, "Cached clone is corrupted. Cloning again");
+                    fs::remove_dir_all(&path)?;
+                    LocalRepository::open_or_init(&path, &self.worktrees, self.lease(&key))?
+                }
+            };
+
+        if local_repository.is_up_to_date(&remote_refs)? {
+            tracing::debug!(message = "Cached clone is up to date");
+            return Ok(local_repository);
+        }
+        local_repository.fetch(url, credentials, &remote_refs)?;
+        tracing::debug!(message = "Fetched changes into cached clone");
+
+        let size = dir_size(&path)?;
+        if let Some(entry) = self.entries.lock().expect("Lock is poisoned").get_mut(&key) {
+            entry.size = size;
+        }
+        self.evict();
+        Ok(local_repository)
+    }
+
+    fn lease(&self, key: &str) -> Lease {
+        let mut entries = self.entries.lock().expect("Lock is poisoned");
+        let entry = entries.entry(key.to_string()).or_insert(Entry {
+            size: 0,
+            last_used: SystemTime::now(),
+            leases: 0,
+        });
+        entry.leases += 1;
+        Lease {
+            key: key.to_string(),
+            entries: self.entries.clone(),
+        }
+    }
+
+    /// Delete the least recently used clones that aren't in use until the cache fits the budget
+    fn evict(&self) {
+        let mut entries = self.entries.lock().expect("Lock is poisoned");
+        let mut total: u64 = entries.values().map(|e| e.size).sum();
+        while total > self.budget {
+            let key = match entries
+                .iter()
+                .filter(|(_, e)| e.leases == 0)
+                .min_by_key(|(_, e)| e.last_used)
+            {
+                Some((key, _)) => key.clone(),
+                None => break,
+            };
+            let path = self.repositories.join(format!("{key}.git"));
+            if let Err(e) = fs::remove_dir_all(&path) {
+                tracing::error!(error = %e, path = %path.display(), "Failed to evict cached clone");
+                break;
+            }
+            let entry = entries.remove(&key).expect("Entry exists");
+            total -= entry.size;
+            metrics::CLONE_CACHE_EVICTIONS.inc();
+            tracing::debug!(path = %path.display(), "Evicted cached clone");
+        }
+        metrics::CLONE_CACHE_BYTES.set(total as i64);
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let mut entries = self.entries.lock().expect("Lock is poisoned");
+        if let Some(entry) = entries.get_mut(&self.key) {
+            entry.leases -= 1;
+            entry.last_used = SystemTime::now();
+        }
+    }
+}
+
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += match metadata.is_dir() {
+            true => dir_size(&entry.path())?,
+            false => metadata.len(),
+        };
+    }
+    Ok(size)
+}
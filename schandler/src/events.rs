@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use recesser_core::event::{Event, EventKind};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::apiserver::Apiserver;
+
+/// Time to wait before reconnecting to the event stream
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Follow the push events the apiserver publishes for webhooks and send the names of the pushed
+/// repositories. Reconnects when the connection is lost, resuming after the last received event.
+pub async fn follow_pushes(apiserver: Apiserver, pushes: UnboundedSender<String>) {
+    let mut last_event_id = None;
+    loop {
+        if let Err(e) = follow(&apiserver, &mut last_event_id, &pushes).await {
+            tracing::warn!(error = %e, "Lost connection to event stream");
+        }
+        if pushes.is_closed() {
+            return;
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn follow(
+    apiserver: &Apiserver,
+    last_event_id: &mut Option<u64>,
+    pushes: &UnboundedSender<String>,
+) -> Result<()> {
+    let mut resp = apiserver
+        .subscribe_events("push_received", *last_event_id)
+        .await?;
+    tracing::info!("Following push events");
+
+    let mut parser = EventStreamParser::default();
+    while let Some(chunk) = resp.chunk().await? {
+        for event in parser.feed(&chunk)? {
+            *last_event_id = Some(event.id);
+            if let EventKind::PushReceived { repository, .. } = event.kind {
+                if pushes.send(repository).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Incremental parser of Server-Sent Events whose data are events of the apiserver
+#[derive(Default)]
+pub struct EventStreamParser {
+    buf: Vec<u8>,
+    data: String,
+}
+
+impl EventStreamParser {
+    /// Parse a chunk of the stream and return the events it completes
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<Event>> {
+        self.buf.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(end) = self.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+            // A blank line dispatches the event. Comments, IDs and event names are ignored
+            // because the data contains all of them.
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(serde_json::from_str(&self.data)?);
+                }
+                self.data.clear();
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data.push_str(value.trim_start());
+            }
+        }
+        Ok(events)
+    }
+}
@@ -1,5 +1,12 @@
 pub mod apiserver;
 pub mod argo_workflows;
+pub mod cache;
+pub mod events;
+pub mod metrics;
+pub mod registry;
 pub mod repository;
+pub mod server;
 pub mod settings;
+pub mod tls;
+pub mod trigger;
 pub mod workflow;
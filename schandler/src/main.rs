@@ -1,22 +1,48 @@
 #![forbid(unsafe_code)]
 
+use std::collections::HashMap;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::{anyhow, Result};
-use recesser_core::repository::Repository;
+use chrono::Utc;
+use recesser_core::event::{EventKind, NewEvent};
+use recesser_core::repository::{self, Auth, Repository};
+use recesser_core::run::{NewRun, RunStatus, RunUpdate};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::{Instant, Interval};
 use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, Registry};
+use uuid::Uuid;
 
 use recesser_schandler::apiserver::Apiserver;
-use recesser_schandler::argo_workflows::{ArgoWorkflow, ArgoWorkflowsServer};
-use recesser_schandler::repository::LocalRepository;
+use recesser_schandler::argo_workflows::{ArgoWorkflow, ArgoWorkflowsServer, Build, Phase, Source};
+use recesser_schandler::cache::CloneCache;
+use recesser_schandler::events;
+use recesser_schandler::metrics;
+use recesser_schandler::registry;
+use recesser_schandler::repository::{Credentials, LocalRepository, Worktree};
+use recesser_schandler::server;
 use recesser_schandler::settings::Settings;
-use recesser_schandler::workflow::Workflow;
+use recesser_schandler::tls::ClientTls;
+use recesser_schandler::trigger::{self, Change};
+use recesser_schandler::workflow::{self, Workflow};
+
+/// Minutes after which a run that was recorded but never submitted is considered failed
+const SUBMISSION_TIMEOUT: i64 = 10;
 
 struct Global {
     apiserver: Apiserver,
     argo_workflows: ArgoWorkflowsServer,
+    cache: CloneCache,
+    /// Registry for images that custom workflows build. Builds fail if it isn't configured.
+    registry: Option<registry::Registry>,
+    /// Locks that keep a repository from being polled by a webhook and the interval at once
+    polls: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
 }
 
 #[tokio::main]
@@ -26,26 +52,243 @@ async fn main() -> Result<()> {
 
     // Setup logging
     let log_level = LevelFilter::from_str(&s.log_level)?;
-    tracing_subscriber::fmt().with_max_level(log_level).init();
+    let (filter, log_level_handle) = reload::Layer::new(log_level);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .init();
+
+    if s.argo_workflows_insecure {
+        tracing::warn!(
+            "Certificate of the Argo Workflows server isn't verified. \
+            RECESSER_ARGO_WORKFLOWS_INSECURE is deprecated, configure RECESSER_ARGO_WORKFLOWS_CA instead"
+        );
+    }
 
     // Initialize global state
     let apiserver_token = std::env::var("RECESSER_APISERVER_TOKEN")
         .map_err(|_| anyhow!("Apiserver token needs to be specified via environment"))?;
     let global = Arc::new(Global {
-        apiserver: Apiserver::new(&s.apiserver_addr, &apiserver_token)?,
-        argo_workflows: ArgoWorkflowsServer::new(&s.argo_workflows_addr)?,
+        apiserver: Apiserver::new(
+            &s.apiserver_addr,
+            &apiserver_token,
+            &ClientTls {
+                ca_bundle: s.apiserver_ca.clone(),
+                identity: s.apiserver_client_cert.clone(),
+                ..Default::default()
+            },
+        )?,
+        argo_workflows: ArgoWorkflowsServer::new(
+            &s.argo_workflows_addr,
+            &ClientTls {
+                ca_bundle: s.argo_workflows_ca.clone(),
+                insecure: s.argo_workflows_insecure,
+                ..Default::default()
+            },
+        )?,
+        cache: CloneCache::new(&s.cache_dir, s.cache_size * 1024 * 1024)?,
+        registry: s
+            .registry_addr
+            .as_deref()
+            .map(registry::Registry::new)
+            .transpose()?,
+        polls: Mutex::new(HashMap::new()),
     });
 
-    // Poll all repositories on an interval
-    let mut interval = tokio::time::interval(Duration::from_secs(s.polling_interval * 60));
+    // Serve metrics and health endpoints
+    let dependencies = server::Dependencies {
+        apiserver: global.apiserver.clone(),
+        argo_workflows: global.argo_workflows.clone(),
+    };
+    let addr = s.addr.parse()?;
+    tokio::spawn(async move {
+        if let Err(e) = server::serve(addr, dependencies).await {
+            tracing::error!(error = %e, "Failed to serve metrics and health endpoints");
+        }
+    });
+
+    // Poll repositories immediately when the apiserver received a push webhook for them
+    let (push_sender, mut pushes) = mpsc::unbounded_channel();
+    tokio::spawn(events::follow_pushes(global.apiserver.clone(), push_sender));
+
+    // Poll all repositories on an interval as well. Signals are only handled between polls so that a
+    // running poll is always finished. Polls of pushed repositories run concurrently and are
+    // awaited before shutting down.
+    let mut pushed_polls = JoinSet::new();
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut polling_interval = s.polling_interval;
+    let mut interval = tokio::time::interval(minutes(polling_interval));
     loop {
-        interval.tick().await;
-        poll_all_repositories(global.clone()).await?;
+        tokio::select! {
+            biased;
+            _ = terminate.recv() => break,
+            _ = interrupt.recv() => break,
+            _ = hangup.recv() => match reload(&log_level_handle) {
+                Ok(s) => {
+                    if s.polling_interval != polling_interval {
+                        polling_interval = s.polling_interval;
+                        interval = interval_from_now(minutes(polling_interval));
+                    }
+                    tracing::info!(polling_interval, "Reloaded settings");
+                }
+                Err(e) => tracing::error!(
+                    error = %e,
+                    "Failed to reload settings. Continuing with the previous ones"
+                ),
+            },
+            _ = interval.tick() => {
+                check_workflows(&global).await;
+                // The error is already logged. A failed poll is retried on the next tick.
+                let _ = poll_all_repositories(global.clone()).await;
+            }
+            Some(name) = pushes.recv() => {
+                let g = global.clone();
+                pushed_polls.spawn(async move { poll_pushed_repository(g, name).await });
+            }
+            // Reap finished polls so they don't pile up
+            Some(_) = pushed_polls.join_next() => (),
+        }
+    }
+
+    if !pushed_polls.is_empty() {
+        tracing::info!(
+            polls = pushed_polls.len(),
+            "Waiting for polls of pushed repositories"
+        );
+    }
+    while pushed_polls.join_next().await.is_some() {}
+
+    tracing::info!("Shut down");
+    Ok(())
+}
+
+/// Reload the log level and return the new settings
+fn reload(log_level_handle: &reload::Handle<LevelFilter, Registry>) -> Result<Settings> {
+    let s = Settings::new()?;
+    let log_level = LevelFilter::from_str(&s.log_level)?;
+    log_level_handle.reload(log_level)?;
+    Ok(s)
+}
+
+fn minutes(minutes: u64) -> Duration {
+    Duration::from_secs(minutes * 60)
+}
+
+/// Interval whose first tick is one period from now instead of immediately
+fn interval_from_now(period: Duration) -> Interval {
+    tokio::time::interval_at(Instant::now() + period, period)
+}
+
+/// Update the runs that haven't completed yet with the status of their workflows and report the
+/// completion. The runs are read from the apiserver, so they're still tracked after a restart.
+async fn check_workflows(g: &Global) {
+    let runs = match g.apiserver.list_active_runs().await {
+        Ok(runs) => runs,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to list active runs");
+            return;
+        }
+    };
+
+    for run in runs {
+        let workflow = match run.argo_workflow {
+            Some(workflow) => workflow,
+            None => {
+                // The submission may still be in progress
+                if Utc::now() - run.created > chrono::Duration::minutes(SUBMISSION_TIMEOUT) {
+                    let update = failed("Workflow was never submitted".to_string());
+                    update_run(g, &run.id, &update).await;
+                }
+                continue;
+            }
+        };
+        let status = match g.argo_workflows.status(&workflow).await {
+            Ok(Some(status)) => status,
+            Ok(None) => {
+                tracing::info!(%workflow, "Workflow was deleted");
+                update_run(g, &run.id, &failed("Workflow was deleted".to_string())).await;
+                continue;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, %workflow, "Failed to retrieve workflow status");
+                continue;
+            }
+        };
+        let (update, kind) = match status.phase {
+            Some(Phase::Succeeded) => {
+                let outputs = status.uploaded_outputs().unwrap_or_else(|e| {
+                    tracing::error!(error = %e, %workflow, "Failed to read handles of outputs");
+                    Vec::new()
+                });
+                let update = RunUpdate {
+                    status: RunStatus::Succeeded,
+                    argo_workflow: None,
+                    message: None,
+                    outputs: outputs.clone(),
+                };
+                let kind = EventKind::WorkflowSucceeded {
+                    repository: run.repository,
+                    workflow: workflow.clone(),
+                    outputs,
+                };
+                (update, kind)
+            }
+            Some(Phase::Failed | Phase::Error) => {
+                let update = RunUpdate {
+                    status: RunStatus::Failed,
+                    argo_workflow: None,
+                    message: status.message.clone(),
+                    outputs: Vec::new(),
+                };
+                let kind = EventKind::WorkflowFailed {
+                    repository: run.repository,
+                    workflow: workflow.clone(),
+                    message: status.message,
+                };
+                (update, kind)
+            }
+            _ => continue,
+        };
+        tracing::info!(%workflow, phase = ?status.phase, "Workflow completed");
+        update_run(g, &run.id, &update).await;
+        report_event(g, kind).await;
+    }
+}
+
+fn failed(message: String) -> RunUpdate {
+    RunUpdate {
+        status: RunStatus::Failed,
+        argo_workflow: None,
+        message: Some(message),
+        outputs: Vec::new(),
+    }
+}
+
+/// Report a change of the status of a run. Failures are only logged because runs that haven't
+/// completed are checked again on the next tick.
+async fn update_run(g: &Global, id: &str, update: &RunUpdate) {
+    if let Err(e) = g.apiserver.update_run(id, update).await {
+        tracing::error!(error = %e, run_id = %id, "Failed to update run");
+    }
+}
+
+/// Report an event to the apiserver. Failures are only logged because events are informational.
+async fn report_event(g: &Global, kind: EventKind) {
+    let event = NewEvent {
+        project: None,
+        kind,
+    };
+    if let Err(e) = g.apiserver.report_event(&event).await {
+        tracing::error!(error = %e, kind = event.kind.name(), "Failed to report event");
     }
 }
 
 #[tracing::instrument(skip_all, err(Display))]
 async fn poll_all_repositories(g: Arc<Global>) -> Result<()> {
+    let _timer = metrics::POLL_DURATION.start_timer();
+
     let repositories = g.apiserver.list_repositories().await?;
     tracing::info!("Retrieved list of all repositories");
 
@@ -64,40 +307,262 @@ async fn poll_all_repositories(g: Arc<Global>) -> Result<()> {
     Ok(())
 }
 
+#[tracing::instrument(skip(g), err(Display))]
+async fn poll_pushed_repository(g: Arc<Global>, name: String) -> Result<()> {
+    tracing::info!("Received push webhook");
+    let repository = g.apiserver.get_repository(&name).await?;
+    poll_repository(g, repository).await
+}
+
 #[tracing::instrument(skip_all, err(Display), fields(name = %repository.name))]
-async fn poll_repository(g: Arc<Global>, repository: Repository) -> Result<()> {
-    let private_key = g.apiserver.get_ssh_key(&repository.name).await?;
-    tracing::info!(
-        message = "Retrieved private key from secret storage",
-        fingerprint = %repository.public_key.fingerprint
-    );
+async fn poll_repository(g: Arc<Global>, mut repository: Repository) -> Result<()> {
+    let lock = g
+        .polls
+        .lock()
+        .expect("Lock is poisoned")
+        .entry(repository.name.clone())
+        .or_default()
+        .clone();
+    let _guard = match lock.try_lock() {
+        Ok(guard) => guard,
+        Err(_) => {
+            let guard = lock.lock().await;
+            // The other poll may have processed new commits in the meantime
+            repository = g.apiserver.get_repository(&repository.name).await?;
+            guard
+        }
+    };
 
-    let local_repository = LocalRepository::from_remote(&repository.url, &private_key)?;
-    tracing::info!(message = "Cloned repository from remote");
+    let local_repository = match try_staged_key(&g, &mut repository).await {
+        Some(local_repository) => local_repository,
+        None => {
+            let credentials = credentials(&g, &repository).await?;
+            match g
+                .cache
+                .fetch(&repository.name, &repository.url, &credentials)
+            {
+                Ok(local_repository) => local_repository,
+                Err(e) => {
+                    metrics::CLONE_FAILURES.inc();
+                    return Err(e);
+                }
+            }
+        }
+    };
+    tracing::info!(message = "Fetched repository from remote");
 
-    // Compare last commit in database to actual last commit from cloned repository
-    // Only submit workflow if the commit has changed.
-    // When change was detected, update the last commit in the database
-    if repository.last_commit == local_repository.last_commit {
+    // Compare the last processed commits in the database to the watched branches and tags of the
+    // cloned repository. The new commits are recorded before submitting, so that a failed
+    // submission isn't retried on every poll.
+    let changes = trigger::changes(&repository, &local_repository)?;
+    if changes.is_empty() {
         tracing::info!(
-            message = "Last commit of repository has not changed",
+            message = "No watched branch or tag has changed",
             commit_id = %local_repository.last_commit
         );
         return Ok(());
     }
+    for change in changes {
+        let reference = change.reference.clone();
+        if let Err(e) = run_change(&g, &repository, &local_repository, change).await {
+            tracing::error!(error = %e, ?reference, "Failed to start run");
+        }
+    }
+
+    tracing::info!(message = "Successfully polled repository");
+    Ok(())
+}
+
+/// Record the new commit of a branch or tag and submit a workflow for it
+async fn run_change(
+    g: &Global,
+    repository: &Repository,
+    local_repository: &LocalRepository,
+    change: Change,
+) -> Result<()> {
     tracing::info!(
-        message = "Last commit of repository has changed",
-        old_commit_id = %repository.last_commit,
-        new_commit_id = %local_repository.last_commit
+        message = "Watched branch or tag has changed",
+        reference = ?change.reference,
+        old_commit_id = %change.previous,
+        new_commit_id = %change.commit
     );
-    g.apiserver
-        .update_last_commit(&repository.name, &local_repository.last_commit)
-        .await?;
+    match &change.reference {
+        Some(reference) => {
+            g.apiserver
+                .update_tracked_ref(&repository.name, reference, &change.commit)
+                .await?
+        }
+        None => {
+            g.apiserver
+                .update_last_commit(&repository.name, &change.commit)
+                .await?
+        }
+    }
+    if !change.relevant {
+        tracing::info!(
+            message = "Commit doesn't change any path that triggers runs",
+            commit_id = %change.commit
+        );
+        return Ok(());
+    }
+    let kind = EventKind::CommitDetected {
+        repository: repository.name.clone(),
+        commit: change.commit.to_string(),
+    };
+    report_event(g, kind).await;
 
-    let workflow = Workflow::from_repo(&local_repository).await?;
-    let argo_workflow = ArgoWorkflow::from_workflow(workflow, repository)?;
-    g.argo_workflows.submit(&argo_workflow).await?;
+    let worktree = local_repository.checkout(&change.commit)?;
+    let workflow = Workflow::from_worktree(&worktree).await?;
+    let builds = plan_builds(g, repository, &workflow, &worktree).await?;
+    let name = repository.name.clone();
+    // The checkout succeeded, so the commit is known
+    let source = Source {
+        commit: change.commit.to_string(),
+        reference: change.reference,
+    };
+    let run_id = Uuid::new_v4().to_string();
+    tracing::info!(%run_id, "Starting run");
+    let workflow_hash = workflow.hash.clone();
+    let inputs = workflow.inputs().to_vec();
+    let argo_workflow =
+        ArgoWorkflow::from_workflow(workflow, repository.clone(), &source, &run_id, &builds)?;
+    // Recorded before submitting, so that the run is tracked even if the submission fails
+    let new_run = NewRun {
+        id: run_id.clone(),
+        repository: name.clone(),
+        commit: source.commit.clone(),
+        reference: source.reference.clone(),
+        workflow_hash,
+        manifest_hash: argo_workflow.hash()?,
+        inputs,
+    };
+    g.apiserver.add_run(&new_run).await?;
+
+    let workflow_name = match g.argo_workflows.submit(&argo_workflow).await {
+        Ok(workflow_name) => workflow_name,
+        Err(e) => {
+            metrics::SUBMISSION_ERRORS.inc();
+            update_run(
+                g,
+                &run_id,
+                &failed(format!("Failed to submit workflow: {e}")),
+            )
+            .await;
+            return Err(e);
+        }
+    };
+    metrics::WORKFLOWS_SUBMITTED
+        .with_label_values(&[&name])
+        .inc();
+
+    let update = RunUpdate {
+        status: RunStatus::Running,
+        argo_workflow: Some(workflow_name.clone()),
+        message: None,
+        outputs: Vec::new(),
+    };
+    update_run(g, &run_id, &update).await;
+    let kind = EventKind::WorkflowSubmitted {
+        repository: name,
+        workflow: workflow_name,
+    };
+    report_event(g, kind).await;
 
-    tracing::info!(message = "Successfully polled repository");
     Ok(())
 }
+
+/// Tag the images a workflow builds with the hashes of their build contexts and look up whether
+/// the registry already has them
+async fn plan_builds(
+    g: &Global,
+    repository: &Repository,
+    workflow: &Workflow,
+    worktree: &Worktree,
+) -> Result<Vec<Build>> {
+    let dockerfiles = workflow.dockerfiles()?;
+    if dockerfiles.is_empty() {
+        return Ok(Vec::new());
+    }
+    let registry = g
+        .registry
+        .as_ref()
+        .ok_or_else(|| anyhow!("Workflow builds images but no registry is configured"))?;
+
+    let name = format!("recesser/{}", repository::label_value(&repository.name));
+    let mut builds = Vec::new();
+    for dockerfile in dockerfiles {
+        if !worktree.path().join(&dockerfile).is_file() {
+            anyhow::bail!("Dockerfile {} doesn't exist", dockerfile.display());
+        }
+        let tag = workflow::hash_build_context(worktree.path(), &dockerfile)?;
+        let cached = registry.exists(&name, &tag).await?;
+        let build = Build {
+            image: registry.reference(&name, &tag),
+            dockerfile: dockerfile.display().to_string(),
+            context: dockerfile
+                .parent()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default(),
+            insecure: registry.insecure(),
+            cached,
+        };
+        tracing::info!(image = %build.image, cached, "Planned image build");
+        builds.push(build);
+    }
+    Ok(builds)
+}
+
+async fn credentials(g: &Global, repository: &Repository) -> Result<Credentials> {
+    let credentials = match &repository.auth {
+        Auth::Ssh => Credentials::SshKey(g.apiserver.get_credentials(&repository.name).await?),
+        Auth::Token { username } => Credentials::Token {
+            username: username.clone(),
+            token: g.apiserver.get_credentials(&repository.name).await?,
+        },
+        Auth::Anonymous => Credentials::None,
+    };
+    tracing::info!(message = "Retrieved credentials from secret storage");
+    Ok(credentials)
+}
+
+/// Fetch the repository with a staged deploy key. If that succeeds, the apiserver switches over
+/// to the staged key and the fetched clone is used for this poll. Otherwise the key is probably not
+/// installed yet and the current key is used.
+async fn try_staged_key(g: &Global, repository: &mut Repository) -> Option<LocalRepository> {
+    let staged_key = repository.staged_key.take()?;
+    let private_key = match g.apiserver.get_staged_ssh_key(&repository.name).await {
+        Ok(private_key) => private_key,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to retrieve staged private key");
+            return None;
+        }
+    };
+    let credentials = Credentials::SshKey(private_key);
+    let local_repository = match g
+        .cache
+        .fetch(&repository.name, &repository.url, &credentials)
+    {
+        Ok(local_repository) => local_repository,
+        Err(e) => {
+            tracing::warn!(
+                error = %e,
+                fingerprint = %staged_key.fingerprint,
+                "Failed to fetch repository with staged key"
+            );
+            return None;
+        }
+    };
+    match g
+        .apiserver
+        .confirm_staged_key(&repository.name, &staged_key.fingerprint)
+        .await
+    {
+        Ok(()) => {
+            tracing::info!(fingerprint = %staged_key.fingerprint, "Switched to staged key");
+            repository.public_key = Some(staged_key);
+        }
+        // The clone is still valid and the secret of the current key still exists
+        Err(e) => tracing::error!(error = %e, "Failed to confirm staged key"),
+    }
+    Some(local_repository)
+}
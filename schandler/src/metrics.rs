@@ -0,0 +1,46 @@
+use anyhow::Result;
+use prometheus::{
+    register_histogram, register_int_counter, register_int_counter_vec, register_int_gauge,
+    Encoder, Histogram, IntCounter, IntCounterVec, IntGauge, TextEncoder,
+};
+
+lazy_static::lazy_static! {
+    pub static ref POLL_DURATION: Histogram = register_histogram!(
+        "recesser_schandler_poll_duration_seconds",
+        "Time spent polling all repositories"
+    )
+    .unwrap();
+    pub static ref CLONE_FAILURES: IntCounter = register_int_counter!(
+        "recesser_schandler_clone_failures_total",
+        "Number of failed repository clones"
+    )
+    .unwrap();
+    pub static ref CLONE_CACHE_BYTES: IntGauge = register_int_gauge!(
+        "recesser_schandler_clone_cache_bytes",
+        "Size of the cached clones"
+    )
+    .unwrap();
+    pub static ref CLONE_CACHE_EVICTIONS: IntCounter = register_int_counter!(
+        "recesser_schandler_clone_cache_evictions_total",
+        "Number of cached clones deleted to stay within the size budget"
+    )
+    .unwrap();
+    pub static ref WORKFLOWS_SUBMITTED: IntCounterVec = register_int_counter_vec!(
+        "recesser_schandler_workflows_submitted_total",
+        "Number of workflows submitted to Argo Workflows",
+        &["repository"]
+    )
+    .unwrap();
+    pub static ref SUBMISSION_ERRORS: IntCounter = register_int_counter!(
+        "recesser_schandler_submission_errors_total",
+        "Number of failed workflow submissions to Argo Workflows"
+    )
+    .unwrap();
+}
+
+/// Encode all metrics in the Prometheus text format
+pub fn encode() -> Result<String> {
+    let mut buf = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buf)?;
+    Ok(String::from_utf8(buf)?)
+}
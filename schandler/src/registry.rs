@@ -0,0 +1,58 @@
+use anyhow::Result;
+use reqwest::{header, Client, StatusCode};
+
+/// Manifest types a registry may store an image as
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+/// HTTP client for the in-cluster registry that built images are pushed to
+#[derive(Clone)]
+pub struct Registry {
+    addr: String,
+    client: Client,
+}
+
+impl Registry {
+    pub fn new(addr: &str) -> Result<Self> {
+        if !addr.starts_with("http://") && !addr.starts_with("https://") {
+            anyhow::bail!("Registry address {addr} needs to start with http:// or https://");
+        }
+        Ok(Self {
+            addr: addr.trim_end_matches('/').to_string(),
+            client: Client::builder().build()?,
+        })
+    }
+
+    /// Host and port of the registry as used in image references
+    pub fn host(&self) -> &str {
+        self.addr
+            .trim_start_matches("http://")
+            .trim_start_matches("https://")
+    }
+
+    /// Whether the registry is reached over plain HTTP
+    pub fn insecure(&self) -> bool {
+        self.addr.starts_with("http://")
+    }
+
+    pub fn reference(&self, name: &str, tag: &str) -> String {
+        format!("{}/{name}:{tag}", self.host())
+    }
+
+    /// Whether an image with the tag was already pushed
+    pub async fn exists(&self, name: &str, tag: &str) -> Result<bool> {
+        let resp = self
+            .client
+            .head(format!("{}/v2/{name}/manifests/{tag}", self.addr))
+            .header(header::ACCEPT, MANIFEST_TYPES)
+            .send()
+            .await?;
+        match resp.status() {
+            StatusCode::OK => Ok(true),
+            StatusCode::NOT_FOUND => Ok(false),
+            status => anyhow::bail!("Registry responded with status {status}"),
+        }
+    }
+}
@@ -1,40 +1,279 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
-use git2::build::RepoBuilder;
-use git2::{Cred, FetchOptions, RemoteCallbacks};
+use chrono::{DateTime, TimeZone, Utc};
+use git2::build::CheckoutBuilder;
+use git2::{
+    AutotagOption, Cred, Direction, FetchOptions, Oid, Remote, RemoteCallbacks, Repository,
+};
 use recesser_core::repository::CommitID;
-use tempfile::tempdir;
+use tempfile::TempDir;
 
+use crate::cache::Lease;
+
+/// Refspecs of the fetched branches and tags. Branches are stored as remote-tracking refs.
+const REFSPECS: [&str; 2] = [
+    "+refs/heads/*:refs/remotes/origin/*",
+    "+refs/tags/*:refs/tags/*",
+];
+
+/// Credentials for the Git host of a repository
+pub enum Credentials {
+    SshKey(String),
+    Token {
+        username: String,
+        token: String,
+    },
+    /// Public repositories and local paths
+    None,
+}
+
+/// Branches and tags advertised by the remote
+#[derive(Debug, Default)]
+pub struct RemoteRefs {
+    /// Commit of the default branch. `None` for empty repositories.
+    pub head: Option<Oid>,
+    /// Full names of the branches and tags and the objects they point to
+    pub refs: BTreeMap<String, Oid>,
+}
+
+/// Bare clone of a repository in the clone cache
 #[derive(Debug)]
 pub struct LocalRepository {
+    /// Git directory of the bare repository
     pub path: PathBuf,
     pub last_commit: CommitID,
+    /// Directory that worktrees are checked out into
+    worktrees: PathBuf,
+    /// Keeps the clone from being evicted while it's in use
+    _lease: Lease,
 }
 
-impl LocalRepository {
-    pub fn from_remote(url: &str, private_key: &str) -> Result<Self> {
-        let dir = tempdir()?;
-        let dirpath = dir.into_path();
+/// Checkout of a single commit that is deleted when dropped
+#[derive(Debug)]
+pub struct Worktree {
+    dir: TempDir,
+}
 
-        let mut callbacks = RemoteCallbacks::new();
-        callbacks.credentials(|_, username, _| {
-            Cred::ssh_key_from_memory(username.unwrap(), None, private_key, None)
-        });
+/// Tag of the remote repository
+#[derive(Debug)]
+pub struct Tag {
+    pub name: String,
+    pub commit: CommitID,
+    /// Time of the tag for annotated tags and of the tagged commit for lightweight ones
+    pub created: DateTime<Utc>,
+}
 
-        let mut fetch_options = FetchOptions::new();
-        fetch_options.remote_callbacks(callbacks);
+/// List the branches and tags of a remote without fetching anything
+pub fn ls_remote(url: &str, credentials: &Credentials) -> Result<RemoteRefs> {
+    let mut remote = Remote::create_detached(url)?;
+    let connection = remote.connect_auth(Direction::Fetch, Some(callbacks(credentials)), None)?;
 
-        let mut builder = RepoBuilder::new();
-        builder.fetch_options(fetch_options);
+    let mut remote_refs = RemoteRefs::default();
+    for head in connection.list()? {
+        let name = head.name();
+        if name == "HEAD" {
+            remote_refs.head = Some(head.oid());
+        } else if (name.starts_with("refs/heads/") || name.starts_with("refs/tags/"))
+            && !name.ends_with("^{}")
+        {
+            remote_refs.refs.insert(name.to_string(), head.oid());
+        }
+    }
+    Ok(remote_refs)
+}
+
+/// Only called if the host asks for credentials
+fn callbacks(credentials: &Credentials) -> RemoteCallbacks<'_> {
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |_, username, _| match credentials {
+        Credentials::SshKey(private_key) => {
+            Cred::ssh_key_from_memory(username.unwrap_or("git"), None, private_key, None)
+        }
+        Credentials::Token { username, token } => Cred::userpass_plaintext(username, token),
+        Credentials::None => Err(git2::Error::from_str(
+            "Repository requires credentials but none are registered",
+        )),
+    });
+    callbacks
+}
 
-        let repo = builder.clone(url, &dirpath)?;
-        let head_obj = repo.revparse_single("HEAD")?;
-        let last_commit = CommitID::new(Some(head_obj.id().to_string()));
+/// Name of the local ref a branch or tag of the remote is fetched into
+fn local_ref(name: &str) -> String {
+    match name.strip_prefix("refs/heads/") {
+        Some(branch) => format!("refs/remotes/origin/{branch}"),
+        None => name.to_string(),
+    }
+}
 
+impl LocalRepository {
+    /// Open the bare repository at `path` or initialize it if it doesn't exist
+    pub(crate) fn open_or_init(path: &Path, worktrees: &Path, lease: Lease) -> Result<Self> {
+        let repo = match Repository::open_bare(path) {
+            Ok(repo) => repo,
+            Err(_) if !path.exists() => Repository::init_bare(path)?,
+            Err(e) => return Err(e.into()),
+        };
+        let last_commit = match repo.refname_to_id("refs/remotes/origin/HEAD") {
+            Ok(oid) => CommitID::new(Some(oid.to_string())),
+            Err(_) => CommitID::new(None),
+        };
         Ok(Self {
-            path: dirpath,
+            path: path.to_path_buf(),
             last_commit,
+            worktrees: worktrees.to_path_buf(),
+            _lease: lease,
         })
     }
+
+    /// Whether all branches and tags of the remote are already fetched
+    pub fn is_up_to_date(&self, remote_refs: &RemoteRefs) -> Result<bool> {
+        let repo = Repository::open_bare(&self.path)?;
+        let local_refs = self.local_refs(&repo)?;
+        let fetched = remote_refs
+            .refs
+            .iter()
+            .all(|(name, oid)| local_refs.get(&local_ref(name)) == Some(oid));
+        Ok(fetched && local_refs.len() == remote_refs.refs.len())
+    }
+
+    /// Fetch the changes since the last fetch and remove branches and tags that were deleted
+    /// on the remote
+    pub fn fetch(
+        &mut self,
+        url: &str,
+        credentials: &Credentials,
+        remote_refs: &RemoteRefs,
+    ) -> Result<()> {
+        let repo = Repository::open_bare(&self.path)?;
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(callbacks(credentials));
+        fetch_options.download_tags(AutotagOption::All);
+        repo.remote_anonymous(url)?
+            .fetch(&REFSPECS, Some(&mut fetch_options), None)?;
+
+        for name in self.local_refs(&repo)?.keys() {
+            let remote_name = match name.strip_prefix("refs/remotes/origin/") {
+                Some(branch) => format!("refs/heads/{branch}"),
+                None => name.clone(),
+            };
+            if !remote_refs.refs.contains_key(&remote_name) {
+                repo.find_reference(name)?.delete()?;
+            }
+        }
+
+        match remote_refs.head {
+            Some(head) => {
+                repo.reference("refs/remotes/origin/HEAD", head, true, "Default branch")?;
+                self.last_commit = CommitID::new(Some(head.to_string()));
+            }
+            None => self.last_commit = CommitID::new(None),
+        }
+        Ok(())
+    }
+
+    /// Remote-tracking branches and tags with the objects they point to
+    fn local_refs(&self, repo: &Repository) -> Result<BTreeMap<String, Oid>> {
+        let mut refs = BTreeMap::new();
+        for glob in ["refs/remotes/origin/*", "refs/tags/*"] {
+            for reference in repo.references_glob(glob)? {
+                let reference = reference?;
+                if let (Some(name), Some(oid)) = (reference.name(), reference.target()) {
+                    if name != "refs/remotes/origin/HEAD" {
+                        refs.insert(name.to_string(), oid);
+                    }
+                }
+            }
+        }
+        Ok(refs)
+    }
+
+    /// Commit a branch of the remote points to or `None` if it doesn't exist
+    pub fn branch(&self, branch: &str) -> Result<Option<CommitID>> {
+        let repo = Repository::open_bare(&self.path)?;
+        let reference = match repo.find_reference(&format!("refs/remotes/origin/{branch}")) {
+            Ok(reference) => reference,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let commit = reference.peel_to_commit()?;
+        Ok(Some(CommitID::new(Some(commit.id().to_string()))))
+    }
+
+    pub fn tags(&self) -> Result<Vec<Tag>> {
+        let repo = Repository::open_bare(&self.path)?;
+        let mut tags = Vec::new();
+        for name in repo.tag_names(None)?.iter().flatten() {
+            let object = repo.revparse_single(&format!("refs/tags/{name}"))?;
+            let commit = object.peel_to_commit()?;
+            let time = match object.as_tag().and_then(|tag| tag.tagger()) {
+                Some(tagger) => tagger.when(),
+                None => commit.time(),
+            };
+            let created = Utc
+                .timestamp_opt(time.seconds(), 0)
+                .single()
+                .ok_or_else(|| anyhow::anyhow!("Tag {name} has an invalid time"))?;
+            tags.push(Tag {
+                name: name.to_string(),
+                commit: CommitID::new(Some(commit.id().to_string())),
+                created,
+            });
+        }
+        Ok(tags)
+    }
+
+    /// Paths changed between two commits. `None` if the old commit is unknown, e.g. because
+    /// the branch is new or its history was rewritten.
+    pub fn changed_paths(&self, old: &CommitID, new: &CommitID) -> Result<Option<Vec<String>>> {
+        let repo = Repository::open_bare(&self.path)?;
+        let old_commit = match old.as_str().and_then(|id| Oid::from_str(id).ok()) {
+            Some(oid) => match repo.find_commit(oid) {
+                Ok(commit) => commit,
+                Err(_) => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        let new_commit = match new.as_str() {
+            Some(id) => repo.find_commit(Oid::from_str(id)?)?,
+            None => return Ok(None),
+        };
+
+        let diff =
+            repo.diff_tree_to_tree(Some(&old_commit.tree()?), Some(&new_commit.tree()?), None)?;
+        let mut paths = Vec::new();
+        for delta in diff.deltas() {
+            // Renames touch both the old and the new path
+            for file in [delta.old_file(), delta.new_file()] {
+                if let Some(path) = file.path().and_then(|p| p.to_str()) {
+                    if !paths.iter().any(|p| p == path) {
+                        paths.push(path.to_string());
+                    }
+                }
+            }
+        }
+        Ok(Some(paths))
+    }
+
+    /// Check out a commit into a new worktree, e.g. to read its recesser.yaml
+    pub fn checkout(&self, commit: &CommitID) -> Result<Worktree> {
+        let id = commit
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Cannot check out an unknown commit"))?;
+        let repo = Repository::open_bare(&self.path)?;
+        let commit = repo.find_commit(Oid::from_str(id)?)?;
+
+        let dir = tempfile::tempdir_in(&self.worktrees)?;
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force().target_dir(dir.path());
+        repo.checkout_tree(commit.as_object(), Some(&mut checkout))?;
+        Ok(Worktree { dir })
+    }
+}
+
+impl Worktree {
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
 }
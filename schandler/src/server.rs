@@ -0,0 +1,72 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use recesser_core::health::{DependencyStatus, Readiness};
+
+use crate::apiserver::Apiserver;
+use crate::argo_workflows::ArgoWorkflowsServer;
+use crate::metrics;
+
+/// Connections whose health is reported by the readiness endpoint
+pub struct Dependencies {
+    pub apiserver: Apiserver,
+    pub argo_workflows: ArgoWorkflowsServer,
+}
+
+/// Serve metrics and health endpoints
+pub async fn serve(addr: SocketAddr, dependencies: Dependencies) -> Result<()> {
+    let dependencies = Arc::new(dependencies);
+    let make_service = make_service_fn(move |_conn| {
+        let dependencies = dependencies.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, dependencies.clone()))) }
+    });
+    tracing::info!(%addr, "Serving metrics and health endpoints");
+    Server::try_bind(&addr)?.serve(make_service).await?;
+    Ok(())
+}
+
+async fn handle(
+    req: Request<Body>,
+    dependencies: Arc<Dependencies>,
+) -> Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => match metrics::encode() {
+            Ok(body) => Response::builder()
+                .header(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)
+                .body(Body::from(body)),
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to encode metrics");
+                status(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        },
+        (&Method::GET, "/healthz") => Response::builder().body(Body::from("ok")),
+        (&Method::GET, "/readyz") => readyz(&dependencies).await,
+        _ => status(StatusCode::NOT_FOUND),
+    };
+    Ok(response.expect("Failed to build response"))
+}
+
+async fn readyz(dependencies: &Dependencies) -> hyper::http::Result<Response<Body>> {
+    let (apiserver, argo_workflows) = tokio::join!(
+        DependencyStatus::check(dependencies.apiserver.ping()),
+        DependencyStatus::check(dependencies.argo_workflows.ping()),
+    );
+    let readiness = Readiness::new([("apiserver", apiserver), ("argo_workflows", argo_workflows)]);
+    let status = match readiness.ready {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    let body = serde_json::to_vec(&readiness).expect("Failed to serialize readiness");
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+}
+
+fn status(status: StatusCode) -> hyper::http::Result<Response<Body>> {
+    Response::builder().status(status).body(Body::empty())
+}
@@ -1,21 +1,45 @@
-use config::{Config, ConfigError, Environment};
+use std::path::PathBuf;
+
+use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
 
 #[derive(Deserialize, Debug)]
 pub struct Settings {
+    pub addr: String,
     pub apiserver_addr: String,
     pub argo_workflows_addr: String,
     pub polling_interval: u64,
     pub log_level: String,
+    /// Directory of the cached clones
+    pub cache_dir: PathBuf,
+    /// Size budget of the cached clones in MiB
+    pub cache_size: u64,
+    /// Registry that images built in the cluster are pushed to, e.g. `http://registry.recesser:5000`
+    pub registry_addr: Option<String>,
+    // CA bundles to verify the certificates of the apiserver and Argo Workflows server
+    pub apiserver_ca: Option<PathBuf>,
+    pub argo_workflows_ca: Option<PathBuf>,
+    /// Skip verification of the certificate of the Argo Workflows server. Deprecated in favor of
+    /// `argo_workflows_ca`.
+    pub argo_workflows_insecure: bool,
+    // Client certificate and private key in PEM format if the apiserver requires mutual TLS
+    pub apiserver_client_cert: Option<PathBuf>,
 }
 
 impl Settings {
     pub fn new() -> std::result::Result<Self, ConfigError> {
+        let config_file =
+            std::env::var("RECESSER_CONFIG_FILE").unwrap_or_else(|_| String::from("config.toml"));
         let config = Config::builder()
+            .set_default("addr", "0.0.0.0:8080")?
             .set_default("apiserver_addr", "http://apiserver.recesser")?
             .set_default("argo_workflows_addr", "https://argo-server.argo:2746")?
+            .set_default("argo_workflows_insecure", false)?
             .set_default("polling_interval", 5)?
             .set_default("log_level", "info")?
+            .set_default("cache_dir", "/var/cache/recesser")?
+            .set_default("cache_size", 1024)?
+            .add_source(File::with_name(&config_file).required(false))
             .add_source(Environment::with_prefix("recesser"))
             .build()?;
 
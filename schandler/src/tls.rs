@@ -0,0 +1,37 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use recesser_core::tls::read_certificates;
+use reqwest::{Certificate, ClientBuilder, Identity};
+
+/// TLS options of an HTTP client
+#[derive(Default)]
+pub struct ClientTls {
+    /// CA bundle that is trusted in addition to the built-in roots
+    pub ca_bundle: Option<PathBuf>,
+    /// Client certificate and private key in a single PEM file for mutual TLS
+    pub identity: Option<PathBuf>,
+    /// Accept any certificate of the server. Deprecated, only kept for servers with self-signed
+    /// certificates that were trusted this way before a CA bundle could be configured.
+    pub insecure: bool,
+}
+
+impl ClientTls {
+    pub fn apply(&self, mut builder: ClientBuilder) -> Result<ClientBuilder> {
+        if let Some(path) = &self.ca_bundle {
+            for der in read_certificates(path)? {
+                builder = builder.add_root_certificate(Certificate::from_der(&der)?);
+            }
+        }
+        if let Some(path) = &self.identity {
+            let pem =
+                fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+            builder = builder.identity(Identity::from_pem(&pem)?);
+        }
+        if self.insecure {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        Ok(builder)
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use recesser_core::repository::{CommitID, Repository};
+
+use crate::repository::LocalRepository;
+
+/// New commit of a watched branch or tag
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    /// Full name of the branch or tag or `None` for the default branch
+    pub reference: Option<String>,
+    pub previous: CommitID,
+    pub commit: CommitID,
+    /// Whether the commit changes paths that match the path filters. Other commits are only
+    /// recorded and don't start runs.
+    pub relevant: bool,
+}
+
+/// Compare the watched branches and tags of the clone to the last processed commits
+pub fn changes(repository: &Repository, local: &LocalRepository) -> Result<Vec<Change>> {
+    let triggers = &repository.triggers;
+    let mut changes = Vec::new();
+
+    if triggers.branches.is_empty() && repository.last_commit != local.last_commit {
+        changes.push(Change {
+            reference: None,
+            previous: repository.last_commit.clone(),
+            commit: local.last_commit.clone(),
+            relevant: true,
+        });
+    }
+    for branch in &triggers.branches {
+        let commit = match local.branch(branch)? {
+            Some(commit) => commit,
+            None => {
+                tracing::warn!(%branch, "Watched branch doesn't exist");
+                continue;
+            }
+        };
+        let reference = format!("refs/heads/{branch}");
+        let previous = repository.tracked_commit(&reference);
+        if previous != commit {
+            changes.push(Change {
+                reference: Some(reference),
+                previous,
+                commit,
+                relevant: true,
+            });
+        }
+    }
+
+    // Path filters only apply to branches. Tags start release runs regardless of what changed.
+    if triggers.filters_paths() {
+        for change in &mut changes {
+            if let Some(paths) = local.changed_paths(&change.previous, &change.commit)? {
+                change.relevant = paths.iter().any(|path| triggers.matches_path(path));
+            }
+        }
+    }
+
+    for tag in local.tags()? {
+        if !triggers.matches_tag(&tag.name) {
+            continue;
+        }
+        let reference = format!("refs/tags/{}", tag.name);
+        let previous = repository.tracked_commit(&reference);
+        let created_before = matches!(triggers.tags_since, Some(since) if tag.created < since);
+        if previous == tag.commit || (previous.as_str().is_none() && created_before) {
+            continue;
+        }
+        changes.push(Change {
+            reference: Some(reference),
+            previous,
+            commit: tag.commit,
+            relevant: true,
+        });
+    }
+
+    Ok(changes)
+}
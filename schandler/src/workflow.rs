@@ -1,8 +1,21 @@
+mod matrix;
+mod pipeline;
+
+use std::collections::HashSet;
+use std::fs as std_fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+
 use anyhow::Result;
+use recesser_core::encoding::hex;
+use recesser_core::hash::{hash_buf, hash_file, DIGEST_LEN};
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 
-use crate::repository::LocalRepository;
+use crate::repository::Worktree;
+
+pub use matrix::{Matrix, Value};
+pub use pipeline::{Input, Pipeline, Step};
 
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -11,6 +24,9 @@ pub struct Workflow {
     pub metadata: Metadata,
     #[serde(flatten)]
     pub kind: Kind,
+    /// Hex encoded hash of the recesser.yaml the workflow was read from
+    #[serde(skip)]
+    pub hash: String,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -23,6 +39,7 @@ pub struct Metadata {
 pub enum Kind {
     TemplateWorkflow(TemplateWorkflow),
     CustomWorkflow(CustomWorkflow),
+    Pipeline(Pipeline),
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -34,6 +51,11 @@ pub struct TemplateWorkflow {
     pub entrypoint: String,
     pub args: Option<Vec<String>>,
     pub working_dir: Option<String>,
+    /// Files and directories that are uploaded as artifacts after the workflow succeeded
+    #[serde(default)]
+    pub outputs: Vec<String>,
+    /// Parameter combinations to run the entrypoint with
+    pub matrix: Option<Matrix>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -46,20 +68,204 @@ pub struct Template {
 #[serde(rename_all = "camelCase")]
 pub struct CustomWorkflow {
     pub inputs: Vec<String>,
+    /// Image pinned by digest, e.g. `python@sha256:<digest>`
     pub image: Option<String>,
+    /// Path of a Dockerfile in the repository that the image is built from
     pub build: Option<String>,
     pub entrypoint: Vec<String>,
     pub args: Option<Vec<String>>,
     pub working_dir: Option<String>,
+    /// Files and directories that are uploaded as artifacts after the workflow succeeded
+    #[serde(default)]
+    pub outputs: Vec<String>,
+    /// Parameter combinations to run the entrypoint with
+    pub matrix: Option<Matrix>,
 }
 
 impl Workflow {
-    pub async fn from_repo(repo: &LocalRepository) -> Result<Self> {
-        let workflow_path = repo.path.join("recesser.yaml");
+    pub async fn from_worktree(worktree: &Worktree) -> Result<Self> {
+        let workflow_path = worktree.path().join("recesser.yaml");
         if !workflow_path.exists() {
             anyhow::bail!("Repository doesn't contain a pipeline definition (recesser.yaml).");
         }
         let buf = fs::read_to_string(&workflow_path).await?;
-        Ok(serde_yaml::from_str(&buf)?)
+        let mut workflow: Self = serde_yaml::from_str(&buf)?;
+        match &workflow.kind {
+            Kind::TemplateWorkflow(template_workflow) => template_workflow.validate()?,
+            Kind::CustomWorkflow(custom_workflow) => custom_workflow.validate()?,
+            Kind::Pipeline(pipeline) => pipeline.validate()?,
+        }
+        workflow.hash = hex::encode(&hash_buf(buf.as_bytes()));
+        Ok(workflow)
+    }
+
+    /// Handles of the artifacts the workflow downloads
+    pub fn inputs(&self) -> &[String] {
+        match &self.kind {
+            Kind::TemplateWorkflow(template_workflow) => {
+                template_workflow.inputs.as_deref().unwrap_or_default()
+            }
+            Kind::CustomWorkflow(custom_workflow) => &custom_workflow.inputs,
+            Kind::Pipeline(pipeline) => &pipeline.inputs,
+        }
+    }
+
+    /// Paths of the Dockerfiles the images of the workflow are built from, without duplicates
+    pub fn dockerfiles(&self) -> Result<Vec<PathBuf>> {
+        let builds: Vec<&String> = match &self.kind {
+            Kind::TemplateWorkflow(_) => Vec::new(),
+            Kind::CustomWorkflow(custom_workflow) => custom_workflow.build.iter().collect(),
+            Kind::Pipeline(pipeline) => pipeline.steps.iter().flat_map(|s| &s.build).collect(),
+        };
+        let mut dockerfiles = Vec::new();
+        for build in builds {
+            let dockerfile = relative_path(build)?;
+            if !dockerfiles.contains(&dockerfile) {
+                dockerfiles.push(dockerfile);
+            }
+        }
+        Ok(dockerfiles)
+    }
+}
+
+impl TemplateWorkflow {
+    pub fn validate(&self) -> Result<()> {
+        validate_outputs(&self.outputs)?;
+        validate_matrix(self.matrix.as_ref())
+    }
+}
+
+impl CustomWorkflow {
+    /// Check that the workflow either runs an image pinned by digest or builds its own. Tags can
+    /// be moved, so they would make runs irreproducible.
+    pub fn validate(&self) -> Result<()> {
+        validate_image(self.image.as_deref(), self.build.as_deref())
+            .map_err(|e| anyhow::anyhow!("Custom workflow: {e}"))?;
+        if self.entrypoint.is_empty() {
+            anyhow::bail!("Custom workflow needs an entrypoint");
+        }
+        validate_outputs(&self.outputs)?;
+        validate_matrix(self.matrix.as_ref())
+    }
+
+    /// Path of the Dockerfile relative to the repository root or `None` if the image isn't built
+    pub fn dockerfile(&self) -> Result<Option<PathBuf>> {
+        self.build.as_deref().map(relative_path).transpose()
+    }
+}
+
+/// Check that either an image pinned by digest or a Dockerfile in the repository is given
+fn validate_image(image: Option<&str>, build: Option<&str>) -> Result<()> {
+    match (image, build) {
+        (Some(image), None) => {
+            if !is_pinned(image) {
+                anyhow::bail!(
+                    "Image {image} is not pinned by digest. Use e.g. {image}@sha256:<digest>"
+                );
+            }
+        }
+        (None, Some(build)) => {
+            relative_path(build)?;
+        }
+        _ => anyhow::bail!("Needs either an image or a build, but not both"),
+    }
+    Ok(())
+}
+
+/// Whether an image reference contains a SHA-256 digest
+fn is_pinned(image: &str) -> bool {
+    match image.rsplit_once("@sha256:") {
+        Some((name, digest)) => {
+            !name.is_empty()
+                && digest.len() == 64
+                && digest
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        }
+        None => false,
+    }
+}
+
+/// Normalize a path in the repository. Paths that leave the repository are rejected.
+pub(crate) fn relative_path(path: &str) -> Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(c) => normalized.push(c),
+            Component::CurDir => (),
+            _ => anyhow::bail!("Path {path} must be relative and stay inside the repository"),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        anyhow::bail!("Path {path} doesn't point to a file");
+    }
+    Ok(normalized)
+}
+
+/// Path an output is uploaded as. Relative paths are relative to the repository root.
+pub(crate) fn output_path(output: &str) -> Result<PathBuf> {
+    relative_path(output.trim_start_matches('/'))
+        .map_err(|_| anyhow::anyhow!("Output {output} must name a file or directory without .."))
+}
+
+fn validate_outputs(outputs: &[String]) -> Result<()> {
+    let mut paths = HashSet::new();
+    for output in outputs {
+        if !paths.insert(output_path(output)?) {
+            anyhow::bail!("Output {output} is declared more than once");
+        }
+    }
+    Ok(())
+}
+
+fn validate_matrix(matrix: Option<&Matrix>) -> Result<()> {
+    match matrix {
+        Some(matrix) => matrix.validate(),
+        None => Ok(()),
+    }
+}
+
+/// Hex encoded hash of the build context of a Dockerfile, i.e. the directory it's in. Covers the
+/// path, the executable bit and the content of every file, so the same hash means the same image.
+/// Files are hashed while walking the context so that only their digests are held in memory.
+pub fn hash_build_context(root: &Path, dockerfile: &Path) -> Result<String> {
+    let context = dockerfile.parent().unwrap_or_else(|| Path::new(""));
+    let mut files = Vec::new();
+    collect_files(&root.join(context), Path::new(""), &mut files)?;
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut manifest = format!("dockerfile {}\n", dockerfile.display());
+    for (path, executable, digest) in files {
+        manifest.push_str(&format!(
+            "{} {} {}\n",
+            hex::encode(&digest),
+            executable,
+            path.display()
+        ));
+    }
+    Ok(hex::encode(&hash_buf(manifest.as_bytes())))
+}
+
+fn collect_files(
+    dir: &Path,
+    prefix: &Path,
+    files: &mut Vec<(PathBuf, bool, [u8; DIGEST_LEN])>,
+) -> Result<()> {
+    for entry in std_fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = prefix.join(entry.file_name());
+        let metadata = std_fs::symlink_metadata(entry.path())?;
+        let executable = metadata.permissions().mode() & 0o111 != 0;
+        if metadata.is_dir() {
+            collect_files(&entry.path(), &path, files)?;
+        } else if metadata.file_type().is_symlink() {
+            // The target is hashed as content because the builder copies the link itself
+            let target = std_fs::read_link(entry.path())?;
+            let digest = hash_buf(target.to_string_lossy().as_bytes());
+            files.push((path, executable, digest));
+        } else {
+            files.push((path, executable, hash_file(&entry.path())?));
+        }
     }
+    Ok(())
 }
@@ -0,0 +1,103 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Upper bound for the number of cells so that a typo doesn't start thousands of pods
+const MAX_CELLS: usize = 256;
+
+/// Parameter combinations that the entrypoint runs with. Every combination is a cell that runs in
+/// its own pod.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Matrix {
+    /// Values of each parameter by its name
+    pub parameters: BTreeMap<String, Vec<Value>>,
+    /// Maximum number of cells that run at the same time. Unlimited if not set.
+    pub parallelism: Option<u32>,
+}
+
+/// Scalar value of a parameter. Cells receive it as string.
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Value {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl Matrix {
+    pub fn validate(&self) -> Result<()> {
+        if self.parameters.is_empty() {
+            anyhow::bail!("Matrix needs at least one parameter");
+        }
+        for (name, values) in &self.parameters {
+            validate_name(name)?;
+            if values.is_empty() {
+                anyhow::bail!("Matrix parameter {name} needs at least one value");
+            }
+            let mut unique = HashSet::new();
+            for value in values {
+                if !unique.insert(value.to_string()) {
+                    anyhow::bail!("Matrix parameter {name} has the value {value} more than once");
+                }
+            }
+        }
+        let cells = self
+            .parameters
+            .values()
+            .try_fold(1usize, |cells, values| cells.checked_mul(values.len()));
+        match cells {
+            Some(cells) if cells <= MAX_CELLS => (),
+            _ => anyhow::bail!("Matrix may have at most {MAX_CELLS} parameter combinations"),
+        }
+        if self.parallelism == Some(0) {
+            anyhow::bail!("Matrix parallelism needs to be at least 1");
+        }
+        Ok(())
+    }
+
+    /// Every combination of parameter values. The last parameter changes fastest.
+    pub fn cells(&self) -> Vec<BTreeMap<&str, String>> {
+        let mut cells = vec![BTreeMap::new()];
+        for (name, values) in &self.parameters {
+            cells = cells
+                .into_iter()
+                .flat_map(|cell| {
+                    values.iter().map(move |value| {
+                        let mut cell = cell.clone();
+                        cell.insert(name.as_str(), value.to_string());
+                        cell
+                    })
+                })
+                .collect();
+        }
+        cells
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Integer(i) => write!(f, "{i}"),
+            Value::Float(x) => write!(f, "{x}"),
+            Value::String(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+/// Parameter names become parts of environment variable and Argo parameter names
+fn validate_name(name: &str) -> Result<()> {
+    let valid = name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        anyhow::bail!(
+            "Matrix parameter {name} may only contain lowercase letters, digits and _ and needs to start with a letter"
+        );
+    }
+    Ok(())
+}
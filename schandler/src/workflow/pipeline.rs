@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, OneOrMany};
+
+use super::{validate_image, Template};
+
+/// Names of the tasks the schandler adds to the DAG
+const RESERVED_NAMES: [&str; 3] = ["download-artifacts", "build-image", "upload-outputs"];
+
+/// Named steps that run as a DAG. Steps run as soon as the steps they depend on succeeded.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Pipeline {
+    /// Artifacts that are downloaded once and can be used by every step
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    pub steps: Vec<Step>,
+    /// Outputs of steps as `<step>.<output>` that are uploaded as artifacts after the pipeline
+    /// succeeded
+    #[serde(default)]
+    pub outputs: Vec<String>,
+}
+
+#[serde_as]
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Step {
+    pub name: String,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Artifacts of the pipeline and outputs of other steps as `<step>.<output>`
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    /// Files the step writes to `/out/<output>` to pass them to other steps
+    #[serde(default)]
+    pub outputs: Vec<String>,
+    /// Template executor and its dependencies like in a `TemplateWorkflow`
+    pub template: Option<Template>,
+    pub dependencies: Option<String>,
+    /// Image pinned by digest or Dockerfile like in a `CustomWorkflow`
+    pub image: Option<String>,
+    pub build: Option<String>,
+    /// Script for template executors, command for images
+    #[serde_as(as = "OneOrMany<_>")]
+    pub entrypoint: Vec<String>,
+    pub args: Option<Vec<String>>,
+    pub working_dir: Option<String>,
+}
+
+/// Where a step gets an input from
+#[derive(Debug, PartialEq)]
+pub enum Input<'a> {
+    /// Artifact of the pipeline
+    Artifact(&'a str),
+    /// Output of another step
+    Output { step: &'a str, output: &'a str },
+}
+
+impl Pipeline {
+    /// Check that the steps form a DAG and that all dependencies and inputs exist
+    pub fn validate(&self) -> Result<()> {
+        if self.steps.is_empty() {
+            anyhow::bail!("Pipeline needs at least one step");
+        }
+        let mut names = HashSet::new();
+        for step in &self.steps {
+            validate_name(&step.name)?;
+            if !names.insert(step.name.as_str()) {
+                anyhow::bail!("Pipeline has more than one step named {}", step.name);
+            }
+        }
+        for step in &self.steps {
+            step.validate()
+                .map_err(|e| anyhow::anyhow!("Step {}: {e}", step.name))?;
+            for dependency in &step.depends_on {
+                if !names.contains(dependency.as_str()) {
+                    anyhow::bail!(
+                        "Step {} depends on step {dependency}, which doesn't exist",
+                        step.name
+                    );
+                }
+            }
+        }
+        let mut uploads = HashSet::new();
+        for output in &self.outputs {
+            if !uploads.insert(output) {
+                anyhow::bail!("Pipeline uploads {output} more than once");
+            }
+            let produced = match output.split_once('.') {
+                Some((step, output)) => matches!(
+                    self.step(step),
+                    Some(s) if s.outputs.iter().any(|o| o == output)
+                ),
+                None => false,
+            };
+            if !produced {
+                anyhow::bail!("Pipeline uploads {output}, which is no output of a step");
+            }
+        }
+        if let Some(cycle) = self.find_cycle() {
+            anyhow::bail!(
+                "Steps depend on each other in a cycle: {}",
+                cycle.join(" -> ")
+            );
+        }
+
+        for step in &self.steps {
+            let ancestors = self.ancestors(step);
+            for input in step.inputs() {
+                match input {
+                    Input::Artifact(handle) => {
+                        if !self.inputs.iter().any(|i| i == handle) {
+                            anyhow::bail!(
+                                "Step {} uses artifact {handle}, which isn't an input of the pipeline",
+                                step.name
+                            );
+                        }
+                    }
+                    Input::Output { step: from, output } => {
+                        let produced = matches!(
+                            self.step(from),
+                            Some(s) if s.outputs.iter().any(|o| o == output)
+                        );
+                        if !produced {
+                            anyhow::bail!(
+                                "Step {} uses output {output} of step {from}, which doesn't exist",
+                                step.name
+                            );
+                        }
+                        if !ancestors.contains(from) {
+                            anyhow::bail!(
+                                "Step {} uses an output of step {from} but doesn't depend on it",
+                                step.name
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn step(&self, name: &str) -> Option<&Step> {
+        self.steps.iter().find(|s| s.name == name)
+    }
+
+    /// Steps that need to finish before a step starts, directly or transitively
+    fn ancestors<'a>(&'a self, step: &'a Step) -> HashSet<&'a str> {
+        let mut ancestors = HashSet::new();
+        let mut queue: Vec<&str> = step.depends_on.iter().map(String::as_str).collect();
+        while let Some(name) = queue.pop() {
+            if ancestors.insert(name) {
+                if let Some(step) = self.step(name) {
+                    queue.extend(step.depends_on.iter().map(String::as_str));
+                }
+            }
+        }
+        ancestors
+    }
+
+    /// Names of steps that depend on each other in a cycle, starting and ending with the same step
+    fn find_cycle(&self) -> Option<Vec<&str>> {
+        let mut done = HashSet::new();
+        for step in &self.steps {
+            let mut path = Vec::new();
+            if let Some(cycle) = self.visit(step, &mut path, &mut done) {
+                return Some(cycle);
+            }
+        }
+        None
+    }
+
+    /// Depth-first search that keeps the path to the current step
+    fn visit<'a>(
+        &'a self,
+        step: &'a Step,
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Option<Vec<&'a str>> {
+        if done.contains(step.name.as_str()) {
+            return None;
+        }
+        if let Some(start) = path.iter().position(|n| *n == step.name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(&step.name);
+            return Some(cycle);
+        }
+        path.push(&step.name);
+        for dependency in step.depends_on.iter().filter_map(|d| self.step(d)) {
+            if let Some(cycle) = self.visit(dependency, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.insert(&step.name);
+        None
+    }
+}
+
+impl Step {
+    fn validate(&self) -> Result<()> {
+        match &self.template {
+            Some(_) => {
+                if self.image.is_some() || self.build.is_some() {
+                    anyhow::bail!("Needs either a template, an image or a build");
+                }
+                if self.dependencies.is_none() {
+                    anyhow::bail!("Template executors need dependencies");
+                }
+                if self.entrypoint.len() != 1 {
+                    anyhow::bail!("Template executors need exactly one script as entrypoint");
+                }
+            }
+            None => validate_image(self.image.as_deref(), self.build.as_deref())?,
+        }
+        if self.entrypoint.is_empty() {
+            anyhow::bail!("Needs an entrypoint");
+        }
+        let mut outputs = HashSet::new();
+        for output in &self.outputs {
+            if output.is_empty()
+                || !output
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                anyhow::bail!("Output {output} may only contain letters, digits, - and _");
+            }
+            if !outputs.insert(output) {
+                anyhow::bail!("Output {output} is declared more than once");
+            }
+        }
+        if self.depends_on.contains(&self.name) {
+            anyhow::bail!("Depends on itself");
+        }
+        Ok(())
+    }
+
+    pub fn inputs(&self) -> impl Iterator<Item = Input<'_>> {
+        self.inputs.iter().map(|input| match input.split_once('.') {
+            Some((step, output)) => Input::Output { step, output },
+            None => Input::Artifact(input),
+        })
+    }
+}
+
+/// Step names become Argo task names, which are limited to lowercase letters, digits and -
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 40
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-');
+    if !valid {
+        anyhow::bail!(
+            "Step name {name} may only contain lowercase letters, digits and - and at most 40 characters"
+        );
+    }
+    if RESERVED_NAMES.iter().any(|r| name.starts_with(r)) {
+        anyhow::bail!("Step name {name} is reserved");
+    }
+    Ok(())
+}
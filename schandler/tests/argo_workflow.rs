@@ -3,8 +3,8 @@ mod common;
 use anyhow::Result;
 use jsonschema::output::BasicOutput;
 use jsonschema::JSONSchema;
-use recesser_core::repository::{CommitID, Fingerprint, PublicKey, Repository};
-use recesser_schandler::argo_workflows::ArgoWorkflow;
+use recesser_core::repository::{Auth, CommitID, Fingerprint, PublicKey, Repository, Triggers};
+use recesser_schandler::argo_workflows::{ArgoWorkflow, Build, Source, WorkflowStatus};
 use recesser_schandler::workflow::Workflow;
 use reqwest::blocking;
 
@@ -12,6 +12,8 @@ use common::read_fixture;
 
 const SCHEMA_URL: &str =
     "https://raw.githubusercontent.com/argoproj/argo-workflows/v3.3.1/api/jsonschema/schema.json";
+const WORKFLOW_HASH: &str = "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262";
+const RUN_ID: &str = "5d0f3c1e-8f5a-4d7e-9c62-2b1f0f4c8a11";
 
 #[test]
 fn produces_schema_conforming_json() -> Result<()> {
@@ -30,25 +32,384 @@ fn produces_schema_conforming_json() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn pins_source_revision() -> Result<()> {
+    let argo_workflow = serde_json::to_value(&mock_argo_workflow()?)?;
+
+    let metadata = &argo_workflow["metadata"];
+    let commit = "59b20b8d5c6ff8d09518454d4dd8b7a30f095ab5";
+    assert_eq!(metadata["labels"]["recesser.io/commit"], commit);
+    assert_eq!(
+        metadata["labels"]["recesser.io/workflow-hash"],
+        &WORKFLOW_HASH[..32]
+    );
+    assert_eq!(metadata["annotations"]["recesser.io/commit"], commit);
+    assert_eq!(
+        metadata["annotations"]["recesser.io/repository-url"],
+        "notAUrl"
+    );
+    assert_eq!(
+        metadata["annotations"]["recesser.io/ref"],
+        "refs/tags/v1.0.0"
+    );
+    assert_eq!(
+        metadata["annotations"]["recesser.io/workflow-hash"],
+        WORKFLOW_HASH
+    );
+
+    let main = template(&argo_workflow, "main").unwrap();
+    assert_eq!(main["inputs"]["artifacts"][0]["git"]["revision"], commit);
+    Ok(())
+}
+
+#[test]
+fn builds_custom_image_unless_cached() -> Result<()> {
+    let mut build = Build {
+        image: format!("registry.recesser:5000/recesser/mock:{WORKFLOW_HASH}"),
+        dockerfile: String::from("Dockerfile"),
+        context: String::new(),
+        insecure: true,
+        cached: false,
+    };
+    let argo_workflow = serde_json::to_value(&mock_custom_argo_workflow(&build)?)?;
+    let build_image = template(&argo_workflow, "build-image").expect("Image is built");
+    let args = build_image["container"]["args"].as_array().unwrap();
+    assert!(args.contains(&serde_json::json!(format!("--destination={}", build.image))));
+    assert!(args.contains(&serde_json::json!("--insecure")));
+    let main = template(&argo_workflow, "main").unwrap();
+    assert_eq!(main["container"]["image"], build.image.as_str());
+    assert_eq!(main["container"]["command"][0], "main.py");
+
+    build.cached = true;
+    let argo_workflow = serde_json::to_value(&mock_custom_argo_workflow(&build)?)?;
+    assert!(template(&argo_workflow, "build-image").is_none());
+    let main = template(&argo_workflow, "main").unwrap();
+    assert_eq!(main["container"]["image"], build.image.as_str());
+    Ok(())
+}
+
+#[test]
+fn quotes_arguments_of_custom_workflows() -> Result<()> {
+    let args = [
+        r#"--label="x""#,
+        r"\d+",
+        // Would add an environment variable if it could end the string
+        "x\"\n        env:\n          - name: INJECTED\n            value: \"1",
+    ];
+    let fixture = read_fixture("custom_workflow.yml")?.replace(
+        "entrypoint: [main.py]",
+        &format!(
+            "entrypoint: [main.py]\n  args: {}",
+            serde_json::to_string(&args)?
+        ),
+    );
+    let mut workflow: Workflow = serde_yaml::from_str(&fixture)?;
+    workflow.hash = String::from(WORKFLOW_HASH);
+    let build = Build {
+        image: format!("registry.recesser:5000/recesser/mock:{WORKFLOW_HASH}"),
+        dockerfile: String::from("Dockerfile"),
+        context: String::new(),
+        insecure: false,
+        cached: true,
+    };
+    let argo_workflow = ArgoWorkflow::from_workflow(
+        workflow,
+        mock_repository(),
+        &mock_source(),
+        RUN_ID,
+        std::slice::from_ref(&build),
+    )?;
+    let argo_workflow = serde_json::to_value(&argo_workflow)?;
+
+    let main = template(&argo_workflow, "main").unwrap();
+    let rendered = main["container"]["args"].as_array().unwrap();
+    assert_eq!(
+        rendered[..args.len()],
+        serde_json::json!(args).as_array().unwrap()[..]
+    );
+    let env = main["container"]["env"].as_array().unwrap();
+    assert!(env.iter().all(|e| e["name"] != "INJECTED"));
+    Ok(())
+}
+
+#[test]
+fn renders_pipeline_as_dag() -> Result<()> {
+    let mut workflow: Workflow = serde_yaml::from_str(&read_fixture("pipeline.yml")?)?;
+    workflow.hash = String::from(WORKFLOW_HASH);
+    let build = Build {
+        image: format!("registry.recesser:5000/recesser/mock:{WORKFLOW_HASH}"),
+        dockerfile: String::from("model/Dockerfile"),
+        context: String::from("model"),
+        insecure: false,
+        cached: false,
+    };
+    let argo_workflow = ArgoWorkflow::from_workflow(
+        workflow,
+        mock_repository(),
+        &mock_source(),
+        RUN_ID,
+        std::slice::from_ref(&build),
+    )?;
+    let argo_workflow = serde_json::to_value(&argo_workflow)?;
+
+    let dag = template(&argo_workflow, "dag").unwrap();
+    let task = |name: &str| {
+        dag["dag"]["tasks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|t| t["name"] == name)
+            .cloned()
+            .unwrap()
+    };
+    assert_eq!(
+        task("clean")["dependencies"],
+        serde_json::json!(["scrape", "download-artifacts"])
+    );
+    assert_eq!(
+        task("model")["dependencies"],
+        serde_json::json!(["clean", "build-image-1"])
+    );
+    assert_eq!(
+        task("plot")["arguments"]["artifacts"][1],
+        serde_json::json!({
+            "name": "model-model",
+            "from": "{{tasks.model.outputs.artifacts.model}}"
+        })
+    );
+
+    let model = template(&argo_workflow, "step-model").unwrap();
+    assert_eq!(model["container"]["image"], build.image.as_str());
+    assert_eq!(
+        model["container"]["args"],
+        serde_json::json!(["/tmp/clean/dataset"])
+    );
+    assert!(template(&argo_workflow, "build-image-1").is_some());
+    let scrape = template(&argo_workflow, "step-scrape").unwrap();
+    assert_eq!(scrape["outputs"]["artifacts"][0]["path"], "/out/tweets");
+
+    let upload = task("upload-outputs");
+    assert_eq!(
+        upload["dependencies"],
+        serde_json::json!(["clean", "model"])
+    );
+    assert_eq!(
+        upload["arguments"]["artifacts"][1]["from"],
+        "{{tasks.model.outputs.artifacts.model}}"
+    );
+    Ok(())
+}
+
+#[test]
+fn quotes_arguments_of_pipeline_steps() -> Result<()> {
+    let args = [
+        r#"--label="x""#,
+        // Would add a volume mount if it could end the string
+        "x\"\n        volumeMounts:\n          - name: injected\n            mountPath: \"/",
+    ];
+    let fixture = read_fixture("pipeline.yml")?.replace(
+        "args: [--out, /out/dataset]",
+        &format!("args: {}", serde_json::to_string(&args)?),
+    );
+    let mut workflow: Workflow = serde_yaml::from_str(&fixture)?;
+    workflow.hash = String::from(WORKFLOW_HASH);
+    let build = Build {
+        image: format!("registry.recesser:5000/recesser/mock:{WORKFLOW_HASH}"),
+        dockerfile: String::from("model/Dockerfile"),
+        context: String::from("model"),
+        insecure: false,
+        cached: true,
+    };
+    let argo_workflow = ArgoWorkflow::from_workflow(
+        workflow,
+        mock_repository(),
+        &mock_source(),
+        RUN_ID,
+        std::slice::from_ref(&build),
+    )?;
+    let argo_workflow = serde_json::to_value(&argo_workflow)?;
+
+    let clean = template(&argo_workflow, "step-clean").unwrap();
+    let rendered = clean["container"]["args"].as_array().unwrap();
+    assert_eq!(
+        rendered[..args.len()],
+        serde_json::json!(args).as_array().unwrap()[..]
+    );
+    assert_eq!(
+        clean["container"]["volumeMounts"],
+        serde_json::json!([{ "name": "outputs", "mountPath": "/out" }])
+    );
+    Ok(())
+}
+
+#[test]
+fn uploads_outputs_with_provenance() -> Result<()> {
+    let argo_workflow = serde_json::to_value(&mock_argo_workflow()?)?;
+    assert_eq!(
+        argo_workflow["metadata"]["labels"]["recesser.io/run-id"],
+        RUN_ID
+    );
+
+    let main = template(&argo_workflow, "main").unwrap();
+    assert_eq!(
+        main["outputs"]["artifacts"][0],
+        serde_json::json!({ "name": "output-0", "path": "/src/results/model.h5" })
+    );
+    let upload = template(&argo_workflow, "upload-outputs").unwrap();
+    assert_eq!(
+        upload["inputs"]["artifacts"][0]["path"],
+        "/outputs/results/model.h5"
+    );
+    let command = upload["container"]["command"].as_array().unwrap();
+    let provenance = command
+        .iter()
+        .position(|arg| arg == "--provenance")
+        .map(|i| command[i + 1].as_str().unwrap())
+        .unwrap();
+    let provenance: serde_json::Value = serde_json::from_str(provenance)?;
+    assert_eq!(provenance["runId"], RUN_ID);
+    assert_eq!(
+        provenance["commit"],
+        "59b20b8d5c6ff8d09518454d4dd8b7a30f095ab5"
+    );
+    assert_eq!(provenance["argoWorkflow"], "{{workflow.name}}");
+    assert_eq!(provenance["inputs"].as_array().unwrap().len(), 2);
+    assert_eq!(provenance["executors"][0]["template"]["version"], "1.0.0");
+
+    let steps = template(&argo_workflow, "steps").unwrap()["steps"]
+        .as_array()
+        .unwrap();
+    assert_eq!(
+        steps.last().unwrap()[0]["name"],
+        serde_json::json!("upload-outputs-step")
+    );
+    Ok(())
+}
+
+#[test]
+fn reads_uploaded_outputs_from_status() -> Result<()> {
+    let status: WorkflowStatus = serde_json::from_value(serde_json::json!({
+        "phase": "Succeeded",
+        "nodes": {
+            "train-x7k2p-1": { "templateName": "main" },
+            "train-x7k2p-2": {
+                "templateName": "upload-outputs",
+                "inputs": {
+                    "parameters": [{ "name": "seed", "value": "42" }]
+                },
+                "outputs": {
+                    "parameters": [{
+                        "name": "handles",
+                        "value": "{\"results/model.h5\":\"AQEUo_pBYYItKCM2TI29WFfh-wi7kkyKGcbS-xDlUexpZw\"}"
+                    }]
+                }
+            }
+        }
+    }))?;
+    let outputs = status.uploaded_outputs()?;
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(outputs[0].path, "results/model.h5");
+    assert_eq!(
+        outputs[0].handle,
+        "AQEUo_pBYYItKCM2TI29WFfh-wi7kkyKGcbS-xDlUexpZw"
+    );
+    assert_eq!(outputs[0].parameters["seed"], "42");
+    Ok(())
+}
+
+#[test]
+fn runs_matrix_cells_with_their_parameters() -> Result<()> {
+    let build = Build {
+        image: format!("registry.recesser:5000/recesser/mock:{WORKFLOW_HASH}"),
+        dockerfile: String::from("Dockerfile"),
+        context: String::new(),
+        insecure: true,
+        cached: true,
+    };
+    let argo_workflow = serde_json::to_value(&mock_custom_argo_workflow(&build)?)?;
+
+    let steps = template(&argo_workflow, "steps").unwrap();
+    assert_eq!(steps["parallelism"], 2);
+    let cells = &steps["steps"][1][0];
+    assert_eq!(cells["template"], "cell");
+    assert_eq!(cells["withItems"].as_array().unwrap().len(), 6);
+    assert_eq!(
+        cells["withItems"][1],
+        serde_json::json!({ "seed": "1", "window": "2020" })
+    );
+
+    let cell = template(&argo_workflow, "cell").unwrap();
+    let main_step = &cell["steps"][0][0];
+    assert_eq!(
+        main_step["arguments"]["artifacts"][0]["from"],
+        "{{inputs.artifacts.AQExRKu0RUToW4g-jgmhVDuvDzpRpHgkjIsB6ZXpu_JwBA}}"
+    );
+    assert_eq!(cell["steps"][1][0]["template"], "upload-outputs");
+
+    let main = template(&argo_workflow, "main").unwrap();
+    assert_eq!(
+        main["container"]["env"][0],
+        serde_json::json!({ "name": "MATRIX_SEED", "value": "{{inputs.parameters.seed}}" })
+    );
+    let upload = template(&argo_workflow, "upload-outputs").unwrap();
+    let command = upload["container"]["command"].as_array().unwrap();
+    assert!(command.contains(&serde_json::json!("window={{inputs.parameters.window}}")));
+    Ok(())
+}
+
+fn template<'a>(argo_workflow: &'a serde_json::Value, name: &str) -> Option<&'a serde_json::Value> {
+    argo_workflow["spec"]["templates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|t| t["name"] == name)
+}
+
 fn retrieve_argo_workflows_schema() -> Result<serde_json::Value> {
     let schema = blocking::get(SCHEMA_URL)?.json::<serde_json::Value>()?;
     Ok(schema)
 }
 
 fn mock_argo_workflow() -> Result<ArgoWorkflow> {
-    let workflow: Workflow = serde_yaml::from_str(&read_fixture("template_workflow.yml")?)?;
+    let mut workflow: Workflow = serde_yaml::from_str(&read_fixture("template_workflow.yml")?)?;
+    workflow.hash = String::from(WORKFLOW_HASH);
     let repository = mock_repository();
-    ArgoWorkflow::from_workflow(workflow, repository)
+    ArgoWorkflow::from_workflow(workflow, repository, &mock_source(), RUN_ID, &[])
+}
+
+fn mock_custom_argo_workflow(build: &Build) -> Result<ArgoWorkflow> {
+    let mut workflow: Workflow = serde_yaml::from_str(&read_fixture("custom_workflow.yml")?)?;
+    workflow.hash = String::from(WORKFLOW_HASH);
+    ArgoWorkflow::from_workflow(
+        workflow,
+        mock_repository(),
+        &mock_source(),
+        RUN_ID,
+        std::slice::from_ref(build),
+    )
+}
+
+fn mock_source() -> Source {
+    Source {
+        commit: String::from("59b20b8d5c6ff8d09518454d4dd8b7a30f095ab5"),
+        reference: Some(String::from("refs/tags/v1.0.0")),
+    }
 }
 
 fn mock_repository() -> Repository {
     Repository {
         name: "mockRepository".into(),
         url: "notAUrl".into(),
-        public_key: PublicKey {
+        auth: Auth::Ssh,
+        public_key: Some(PublicKey {
             public_key: "notAPublicKey".into(),
             fingerprint: Fingerprint::new("notAFingerprint".into()),
-        },
+        }),
         last_commit: CommitID::new(None),
+        staged_key: None,
+        retired_keys: Vec::new(),
+        triggers: Triggers::default(),
+        refs: Vec::new(),
+        webhook: false,
     }
 }
@@ -0,0 +1,108 @@
+use std::path::Path;
+
+use anyhow::Result;
+use git2::{Repository, Signature};
+use recesser_core::repository::{self, Auth, CommitID};
+use recesser_schandler::cache::CloneCache;
+use recesser_schandler::repository::Credentials;
+use recesser_schandler::trigger;
+
+#[test]
+fn clones_local_repository_without_credentials() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let repo = Repository::init(dir.path())?;
+    let commit = commit_file(&repo, "recesser.yaml", "name: example\n")?;
+    let commit = CommitID::new(Some(commit));
+
+    let cache_dir = tempfile::tempdir()?;
+    let cache = CloneCache::new(cache_dir.path(), u64::MAX)?;
+    let url = format!("file://{}", dir.path().display());
+    let local_repository = cache.fetch("test/example", &url, &Credentials::None)?;
+
+    assert_eq!(local_repository.last_commit, commit);
+    let worktree = local_repository.checkout(&commit)?;
+    assert!(worktree.path().join("recesser.yaml").exists());
+    let worktree_path = worktree.path().to_path_buf();
+    drop(worktree);
+    assert!(!worktree_path.exists());
+    Ok(())
+}
+
+#[test]
+fn fetches_incrementally_and_evicts_least_recently_used() -> Result<()> {
+    let first_dir = tempfile::tempdir()?;
+    let first = Repository::init(first_dir.path())?;
+    commit_file(&first, "recesser.yaml", "name: first\n")?;
+    let second_dir = tempfile::tempdir()?;
+    let second = Repository::init(second_dir.path())?;
+    commit_file(&second, "recesser.yaml", "name: second\n")?;
+
+    // Every clone exceeds the budget, so only clones in use are kept
+    let cache_dir = tempfile::tempdir()?;
+    let cache = CloneCache::new(cache_dir.path(), 0)?;
+    let first_url = format!("file://{}", first_dir.path().display());
+    let second_url = format!("file://{}", second_dir.path().display());
+
+    let local_first = cache.fetch("test/first", &first_url, &Credentials::None)?;
+    let first_path = local_first.path.clone();
+    drop(local_first);
+    let new_commit = commit_file(&first, "analysis/train.py", "print('training')\n")?;
+    let local_first = cache.fetch("test/first", &first_url, &Credentials::None)?;
+    assert_eq!(local_first.path, first_path);
+    assert_eq!(local_first.last_commit, CommitID::new(Some(new_commit)));
+    drop(local_first);
+
+    let local_second = cache.fetch("test/second", &second_url, &Credentials::None)?;
+    assert!(!first_path.exists());
+    assert!(local_second.path.exists());
+    Ok(())
+}
+
+#[test]
+fn only_changes_to_included_paths_start_runs() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let repo = Repository::init(dir.path())?;
+    let first = commit_file(&repo, "recesser.yaml", "name: example\n")?;
+    commit_file(&repo, "README.md", "# Example\n")?;
+
+    let url = format!("file://{}", dir.path().display());
+    let mut registered = repository::Repository::new("test/example", &url, Auth::Anonymous, None);
+    registered.last_commit = CommitID::new(Some(first));
+    registered.triggers.include = vec!["analysis/".into()];
+
+    let cache_dir = tempfile::tempdir()?;
+    let cache = CloneCache::new(cache_dir.path(), u64::MAX)?;
+    let local_repository = cache.fetch("test/example", &url, &Credentials::None)?;
+    let changes = trigger::changes(&registered, &local_repository)?;
+    assert_eq!(changes.len(), 1);
+    assert!(!changes[0].relevant);
+    drop(local_repository);
+
+    commit_file(&repo, "analysis/train.py", "print('training')\n")?;
+    let local_repository = cache.fetch("test/example", &url, &Credentials::None)?;
+    let changes = trigger::changes(&registered, &local_repository)?;
+    assert_eq!(changes.len(), 1);
+    assert!(changes[0].relevant);
+    Ok(())
+}
+
+/// Commit a file on top of `HEAD` and return the ID of the commit
+fn commit_file(repo: &Repository, path: &str, contents: &str) -> Result<String> {
+    let workdir = repo.workdir().expect("Repository isn't bare");
+    let file = workdir.join(path);
+    std::fs::create_dir_all(file.parent().expect("File has a parent"))?;
+    std::fs::write(file, contents)?;
+
+    let mut index = repo.index()?;
+    index.add_path(Path::new(path))?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let signature = Signature::now("Recesser", "recesser@example.com")?;
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(_) => None,
+    };
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    let commit = repo.commit(Some("HEAD"), &signature, &signature, path, &tree, &parents)?;
+    Ok(commit.to_string())
+}
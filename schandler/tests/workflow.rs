@@ -1,7 +1,10 @@
 mod common;
 
+use std::fs;
+use std::path::Path;
+
 use anyhow::Result;
-use recesser_schandler::workflow::Workflow;
+use recesser_schandler::workflow::{self, Kind, Pipeline, Value, Workflow};
 
 use common::read_fixture;
 
@@ -18,3 +21,134 @@ fn can_parse_custom_workflow_yaml() -> Result<()> {
     serde_yaml::from_str::<Workflow>(&custom_workflow)?;
     Ok(())
 }
+
+#[test]
+fn custom_workflow_images_must_be_pinned() -> Result<()> {
+    let workflow: Workflow = serde_yaml::from_str(&read_fixture("custom_workflow.yml")?)?;
+    let mut custom_workflow = match workflow.kind {
+        Kind::CustomWorkflow(custom_workflow) => custom_workflow,
+        _ => panic!("Fixture is not a custom workflow"),
+    };
+    custom_workflow.validate()?;
+    assert_eq!(
+        custom_workflow.dockerfile()?,
+        Some(Path::new("Dockerfile").to_path_buf())
+    );
+
+    custom_workflow.build = Some(String::from("../Dockerfile"));
+    assert!(custom_workflow.validate().is_err());
+
+    custom_workflow.build = None;
+    custom_workflow.image = Some(String::from("python:3.10"));
+    assert!(custom_workflow.validate().is_err());
+    let digest = "9a6c8b9c6e3e5bd7a1ad1b6a1e3f9a8a4bcdfb2ad7a12b4ce7b6f1c8e4d2a0b1";
+    custom_workflow.image = Some(format!("python@sha256:{digest}"));
+    custom_workflow.validate()?;
+    Ok(())
+}
+
+#[test]
+fn expands_matrix_into_cells() -> Result<()> {
+    let workflow: Workflow = serde_yaml::from_str(&read_fixture("custom_workflow.yml")?)?;
+    let mut matrix = match workflow.kind {
+        Kind::CustomWorkflow(custom_workflow) => custom_workflow.matrix.unwrap(),
+        _ => panic!("Fixture is not a custom workflow"),
+    };
+    matrix.validate()?;
+    let cells = matrix.cells();
+    assert_eq!(cells.len(), 6);
+    assert_eq!(cells[0]["seed"], "1");
+    assert_eq!(cells[0]["window"], "2016");
+    assert_eq!(cells[5]["seed"], "3");
+    assert_eq!(cells[5]["window"], "2020");
+
+    matrix.parallelism = Some(0);
+    assert!(matrix.validate().is_err());
+    matrix.parallelism = None;
+    matrix.parameters.insert(
+        String::from("seed"),
+        vec![Value::Integer(1), Value::Integer(1)],
+    );
+    assert!(matrix.validate().is_err());
+    matrix
+        .parameters
+        .insert(String::from("seed"), vec![Value::Integer(1)]);
+    matrix.validate()?;
+    matrix
+        .parameters
+        .insert(String::from("Seed"), vec![Value::Integer(1)]);
+    assert!(matrix.validate().is_err());
+    Ok(())
+}
+
+#[test]
+fn validates_pipeline_dependencies() -> Result<()> {
+    read_pipeline()?.validate()?;
+
+    let mut pipeline = read_pipeline()?;
+    pipeline.steps[0].depends_on = vec![String::from("plot")];
+    let error = pipeline.validate().unwrap_err().to_string();
+    assert!(
+        error.contains("scrape -> plot -> model -> clean -> scrape"),
+        "{error}"
+    );
+
+    let mut pipeline = read_pipeline()?;
+    pipeline.steps[1].depends_on = vec![String::from("download")];
+    let error = pipeline.validate().unwrap_err().to_string();
+    assert!(error.contains("depends on step download"), "{error}");
+
+    let mut pipeline = read_pipeline()?;
+    pipeline.steps[2].inputs = vec![String::from("clean.embeddings")];
+    let error = pipeline.validate().unwrap_err().to_string();
+    assert!(error.contains("output embeddings of step clean"), "{error}");
+
+    let mut pipeline = read_pipeline()?;
+    pipeline.steps[3].depends_on.clear();
+    let error = pipeline.validate().unwrap_err().to_string();
+    assert!(error.contains("doesn't depend on it"), "{error}");
+    Ok(())
+}
+
+fn read_pipeline() -> Result<Pipeline> {
+    let workflow: Workflow = serde_yaml::from_str(&read_fixture("pipeline.yml")?)?;
+    match workflow.kind {
+        Kind::Pipeline(pipeline) => Ok(pipeline),
+        _ => anyhow::bail!("Fixture is not a pipeline"),
+    }
+}
+
+#[test]
+fn build_context_hash_ignores_files_outside_the_context() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::create_dir_all(dir.path().join("image"))?;
+    fs::write(dir.path().join("image/Dockerfile"), "FROM python:3.10")?;
+    fs::write(dir.path().join("main.py"), "print('hello')")?;
+    let dockerfile = Path::new("image/Dockerfile");
+
+    let hash = workflow::hash_build_context(dir.path(), dockerfile)?;
+    fs::write(dir.path().join("main.py"), "print('hello world')")?;
+    assert_eq!(workflow::hash_build_context(dir.path(), dockerfile)?, hash);
+
+    fs::write(dir.path().join("image/requirements.txt"), "numpy")?;
+    assert_ne!(workflow::hash_build_context(dir.path(), dockerfile)?, hash);
+    Ok(())
+}
+
+#[test]
+fn build_context_hash_covers_all_bytes_of_large_files() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("Dockerfile"), "FROM python:3.10")?;
+    // Larger than the buffer files are read with
+    let mut weights = vec![1u8; 300 * 1024];
+    fs::write(dir.path().join("weights.bin"), &weights)?;
+    let dockerfile = Path::new("Dockerfile");
+
+    let hash = workflow::hash_build_context(dir.path(), dockerfile)?;
+    assert_eq!(workflow::hash_build_context(dir.path(), dockerfile)?, hash);
+
+    weights.push(0);
+    fs::write(dir.path().join("weights.bin"), &weights)?;
+    assert_ne!(workflow::hash_build_context(dir.path(), dockerfile)?, hash);
+    Ok(())
+}
@@ -15,12 +15,11 @@ input_data="/tmp/aclImdb_v1.tar.gz"
 export RUST_VERSION=1.62
 export TEMPLATE_EXECUTORS_VERSION=1.0.0
 
-get_token() {
-    kubectl get \
-        --namespace recesser \
-        secrets/apiserver-token \
-        --template="{{.data.token}}" | 
-        base64 --decode
+init_system() {
+    cargo run --quiet --package recesser-cli -- \
+        --host "${host}" \
+        admin \
+        init
 }
 
 upload_data() {
@@ -53,15 +52,14 @@ skaffold run
 echo "Waiting for 40 seconds for deployment to stabilize"
 sleep 40 # Wait for deployment to stabilize
 
-# Get initial Receesser apiserver token
-token=$(get_token)
-echo "[Initial Token] ${token}"
-
 # Forward port of apiserver to localhost:8080 in the background
 kubectl port-forward service/apiserver 8080:80 -n recesser > /dev/null 2>&1 &
 sleep 1 # Sleep for a while otherwise the next command fails
 trap 'kill %kubectl' EXIT # Kill port forward once script exits
 
+# Initialize system and get admin token
+token=$(init_system)
+
 # Download data for tensorflow-example if it doesn't exist yet
 if [[ ! -f "${input_data}" ]]; then
     wget http://ai.stanford.edu/~amaas/data/sentiment/aclImdb_v1.tar.gz -O "${input_data}"